@@ -16,6 +16,9 @@ use crate::method;
 use crate::method::LoadResp;
 use crate::method::Method;
 use crate::method::PreviewResp;
+use crate::nvim::check_connection;
+use crate::utils::command::on_path;
+use crate::utils::fzf;
 
 /// internal
 /// Subcommand called by fzf
@@ -56,6 +59,32 @@ pub enum Command {
         #[clap(flatten)]
         params: method::ChangeDirectoryParam,
     },
+    /// internal
+    GetPrompt {
+        #[clap(long, env)]
+        fzfw_socket: String,
+    },
+    /// internal
+    ResetMode {
+        #[clap(long, env)]
+        fzfw_socket: String,
+    },
+    /// internal
+    Transform {
+        #[clap(long, env)]
+        fzfw_socket: String,
+        #[clap(flatten)]
+        params: method::TransformParam,
+    },
+    /// Check external dependencies and the connection to neovim
+    Doctor {
+        /// Address or filepath to a socket used to communicate with neovim.
+        #[clap(long, env)]
+        nvim: Option<String>,
+        /// Address or filepath to a socket used to communicate with neovim (legacy).
+        #[clap(long, env)]
+        nvim_listen_address: Option<String>,
+    },
 }
 
 pub async fn run_command(command: Command) -> Result<(), Box<dyn Error>> {
@@ -115,23 +144,53 @@ pub async fn run_command(command: Command) -> Result<(), Box<dyn Error>> {
         Command::ChangeMode {
             fzfw_socket,
             params,
+        } => match send_request(fzfw_socket, method::ChangeMode, params).await? {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(e.into()),
+            Err(e) => {
+                println!("Error: {}", e);
+                Ok(())
+            }
+        },
+        Command::ChangeDirectory {
+            fzfw_socket,
+            params,
         } => {
-            match send_request(fzfw_socket, method::ChangeMode, params).await? {
+            match send_request(fzfw_socket, method::ChangeDirectory, params).await? {
                 Ok(_) => {}
                 Err(e) => println!("Error: {}", e),
             }
             Ok(())
         }
-        Command::ChangeDirectory {
+        Command::ResetMode { fzfw_socket } => {
+            match send_request(fzfw_socket, method::ResetMode, ()).await? {
+                Ok(_) => {}
+                Err(e) => println!("Error: {}", e),
+            }
+            Ok(())
+        }
+        Command::GetPrompt { fzfw_socket } => {
+            match send_request(fzfw_socket, method::GetPrompt, ()).await? {
+                Ok(prompt) => println!("{}", prompt),
+                Err(e) => println!("Error: {}", e),
+            }
+            Ok(())
+        }
+        Command::Transform {
             fzfw_socket,
             params,
         } => {
-            match send_request(fzfw_socket, method::ChangeDirectory, params).await? {
-                Ok(_) => {}
-                Err(e) => println!("Error: {}", e),
+            // 出力はそのまま fzf の action として実行されるので、エラーは出さない
+            match send_request(fzfw_socket, method::Transform, params).await? {
+                Ok(actions) => println!("{}", actions),
+                Err(e) => error!("client: transform error"; "error" => e),
             }
             Ok(())
         }
+        Command::Doctor {
+            nvim,
+            nvim_listen_address,
+        } => doctor(nvim.or(nvim_listen_address)).await,
     }
 }
 
@@ -171,3 +230,116 @@ pub fn send_stream_request<M: Method>(
         }
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Doctor
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+struct Dependency {
+    program: &'static str,
+    /// 無いと fzfw 自体が使い物にならないもの
+    required: bool,
+    used_by: &'static str,
+}
+
+const fn dep(program: &'static str, required: bool, used_by: &'static str) -> Dependency {
+    Dependency {
+        program,
+        required,
+        used_by,
+    }
+}
+
+// モードを足したらここも更新する
+const DEPENDENCIES: &[Dependency] = &[
+    dep("fzf", true, "all modes"),
+    dep("fd", true, "fd"),
+    dep("rg", true, "livegrep, livegrepf, todos"),
+    dep("git", true, "git-*, livegrep, permalinks"),
+    dep("bat", false, "previews (falls back to plain text)"),
+    dep("glow", false, "markdown previews"),
+    dep("file", false, "previews of binary files"),
+    dep("chafa", false, "image previews in fd"),
+    dep("gh", false, "gh-run, browse on GitHub"),
+    dep("lazygit", false, "git-diff"),
+    dep("eza", false, "dirs, zoxide previews"),
+    dep("zoxide", false, "zoxide"),
+    dep("cargo", false, "cargo-deps"),
+    dep("nix", false, "nix"),
+    dep("ss", false, "ports"),
+    dep("ps", false, "ports previews"),
+    dep("tmux", false, "tmux"),
+    dep("mise", false, "tool-versions (or asdf)"),
+    dep("asdf", false, "tool-versions (if mise is missing)"),
+    dep("vifm", false, "fd, git-status"),
+    dep("xdragon", false, "fd (drag and drop)"),
+    dep("trash-put", false, "fd (trash), clip-diff (backup)"),
+    dep("xsel", false, "yank, clip-diff"),
+    dep("code", false, "open in VSCode"),
+];
+
+async fn doctor(nvim_address: Option<String>) -> Result<(), Box<dyn Error>> {
+    use ansi_term::Colour;
+    // 色を付けると幅がずれるので先に揃えておく
+    fn status(label: &str, colour: Colour) -> String {
+        colour.paint(format!("{label:7}")).to_string()
+    }
+    let mut missing_required = vec![];
+    let fzf_bin = fzf::fzf_bin();
+    let width = DEPENDENCIES
+        .iter()
+        .map(|d| d.program.len())
+        .chain([fzf_bin.len()])
+        .max()
+        .unwrap_or(0);
+    for d in DEPENDENCIES {
+        // FZFW_FZF_BIN で差し替えられている
+        let program = if d.program == "fzf" {
+            fzf_bin.as_str()
+        } else {
+            d.program
+        };
+        let status = if on_path(program) {
+            status("OK", Colour::Green)
+        } else if d.required {
+            missing_required.push(program);
+            status("MISSING", Colour::Red)
+        } else {
+            status("MISSING", Colour::Yellow)
+        };
+        let kind = if d.required { "required" } else { "optional" };
+        println!("{:width$}  {status}  {kind:8}  {}", program, d.used_by);
+    }
+
+    // neovim との接続
+    let nvim_ok = match &nvim_address {
+        None => {
+            let status = status("MISSING", Colour::Red);
+            println!("{:width$}  {status}  required  --nvim is not given", "nvim");
+            false
+        }
+        Some(address) => match check_connection(address).await {
+            Ok(version) => {
+                let status = status("OK", Colour::Green);
+                println!(
+                    "{:width$}  {status}  required  v:version={version} ({address})",
+                    "nvim"
+                );
+                true
+            }
+            Err(e) => {
+                let status = status("NG", Colour::Red);
+                println!("{:width$}  {status}  required  {address}: {e}", "nvim");
+                false
+            }
+        },
+    };
+
+    if !missing_required.is_empty() {
+        return Err(format!("missing required tools: {}", missing_required.join(", ")).into());
+    }
+    if !nvim_ok {
+        return Err("cannot connect to neovim".into());
+    }
+    Ok(())
+}
@@ -1,18 +1,31 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
 use crate::mode;
 use crate::mode::MkMode;
 use crate::mode::Mode;
 use crate::mode::ModeDef;
 use crate::nvim::Neovim;
+use crate::state::LastSession;
 
 pub struct Config {
     pub myself: String,
     pub socket: String,
     pub log_file: String,
     pub initial_mode: String,
+    pub initial_query: String,
     pub nvim: Neovim,
+    pub nvim_address: String,
     pub modes: Vec<(String, MkMode)>,
 }
 
+// 他の mode の変種や menu 自身は一覧に出さない
+const UNLISTED_MODES: [&str; 3] = ["livegrepf", "fdf", "menu"];
+
 impl Config {
     pub fn get_initial_mode(&self) -> Mode {
         self.get_mode(&self.initial_mode)
@@ -20,28 +33,261 @@ impl Config {
 
     pub fn get_mode(&self, mode: impl Into<String>) -> Mode {
         let mode = mode.into();
-        for (name, mk_mode) in &self.modes {
-            if name == &mode {
-                return mk_mode();
-            }
+        match self.try_get_mode(&mode) {
+            Some(mode) => mode,
+            None => panic!("unknown mode: {}", mode),
         }
-        panic!("unknown mode: {}", mode);
     }
 
+    /// Like `get_mode`, but returns `None` for an unknown mode name.
+    pub fn try_get_mode(&self, mode: &str) -> Option<Mode> {
+        self.modes
+            .iter()
+            .find(|(name, _)| name == mode)
+            .map(|(_, mk_mode)| mk_mode())
+    }
+
+    /// Names of the modes to be listed (e.g. in menu).
+    /// `modes` in the config file restricts them, but every mode is still reachable
+    /// with `get_mode` because some modes switch to others directly.
     pub fn get_mode_names(&self) -> Vec<&str> {
-        self.modes.iter().map(|(name, _)| name.as_str()).collect()
+        let enabled = file().modes.as_ref();
+        self.modes
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !UNLISTED_MODES.contains(name))
+            .filter(|name| enabled.is_none_or(|modes| modes.iter().any(|m| m == name)))
+            .collect()
+    }
+
+    pub fn has_mode(&self, mode: &str) -> bool {
+        self.modes.iter().any(|(name, _)| name == mode)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Config file
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// `$XDG_CONFIG_HOME/fzfw/config.toml` (optional)
+///
+/// ```toml
+/// modes = ["fd", "buffer", "git-diff", "livegrep"]
+/// initial_mode = "fd"
+/// fzf_opts = ["--border"]
+///
+/// [mode_keys]
+/// ctrl-f = "fd"
+/// ctrl-o = "mru"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Modes listed in menu (default: all)
+    pub modes: Option<Vec<String>>,
+    /// Overridden by `--fzfw-initial-mode` / `FZFW_INITIAL_MODE`
+    pub initial_mode: Option<String>,
+    /// Passed to every fzf (before `FZFW_FZF_OPTS`)
+    #[serde(default)]
+    pub fzf_opts: Vec<String>,
+    /// key -> mode. Overrides the mode switching keys of the default bindings
+    #[serde(default)]
+    pub mode_keys: HashMap<String, String>,
+}
+
+static CONFIG_FILE: OnceCell<ConfigFile> = OnceCell::new();
+
+/// The loaded config file, or the defaults if it is absent (or not loaded yet).
+pub fn file() -> &'static ConfigFile {
+    CONFIG_FILE.get_or_init(ConfigFile::default)
+}
+
+/// `$XDG_CONFIG_HOME/fzfw`, falling back to `$HOME/.config/fzfw`
+pub fn config_dir() -> Option<String> {
+    match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => Some(format!("{dir}/fzfw")),
+        _ => std::env::var("HOME")
+            .ok()
+            .map(|home| format!("{home}/.config/fzfw")),
+    }
+}
+
+/// `$XDG_STATE_HOME/fzfw`, falling back to `$HOME/.local/state/fzfw` and then `/tmp/fzfw-$UID`.
+/// Logs and histories live here.
+pub fn state_dir() -> String {
+    state_dir_from(
+        std::env::var("XDG_STATE_HOME").ok(),
+        std::env::var("HOME").ok(),
+        unsafe { libc::getuid() },
+    )
+}
+
+fn state_dir_from(xdg_state_home: Option<String>, home: Option<String>, uid: u32) -> String {
+    match (xdg_state_home, home) {
+        (Some(dir), _) if !dir.is_empty() => format!("{dir}/fzfw"),
+        (_, Some(home)) if !home.is_empty() => format!("{home}/.local/state/fzfw"),
+        _ => format!("/tmp/fzfw-{uid}"),
     }
 }
 
-pub fn new(myself: String, nvim: Neovim, socket: String, log_file: String) -> Config {
-    let initial_mode = mode::menu::Menu.name().to_string();
+pub fn config_file_path() -> Option<String> {
+    config_dir().map(|dir| format!("{dir}/config.toml"))
+}
+
+/// Loads and validates the config file. Must be called before `file()` is used.
+pub fn load_file() -> Result<()> {
+    let config_file = match config_file_path() {
+        Some(path) if std::path::Path::new(&path).exists() => {
+            let content = std::fs::read_to_string(&path)?;
+            let config_file = parse_file(&content).map_err(|e| anyhow!("{path}: {e}"))?;
+            info!("config: loaded"; "path" => path);
+            config_file
+        }
+        _ => ConfigFile::default(),
+    };
+    CONFIG_FILE
+        .set(config_file)
+        .map_err(|_| anyhow!("config file is already loaded"))
+}
+
+fn parse_file(content: &str) -> Result<ConfigFile> {
+    let config_file: ConfigFile = toml::from_str(content)?;
+    let known = mode::all_modes()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    let check = |mode: &String| {
+        if known.contains(mode) {
+            Ok(())
+        } else {
+            Err(anyhow!("unknown mode: {mode}"))
+        }
+    };
+    config_file.modes.iter().flatten().try_for_each(check)?;
+    config_file.initial_mode.iter().try_for_each(check)?;
+    config_file.mode_keys.values().try_for_each(check)?;
+    Ok(config_file)
+}
+
+pub fn new(
+    myself: String,
+    nvim: Neovim,
+    nvim_address: String,
+    socket: String,
+    log_file: String,
+    initial_mode: Option<String>,
+    resume: bool,
+) -> Config {
     let modes = mode::all_modes();
+    let last_session = resume.then(LastSession::load).flatten();
+    let (initial_mode, initial_query) = initial_mode_and_query(&modes, last_session, initial_mode);
     Config {
         myself,
         nvim,
+        nvim_address,
         socket,
         log_file,
         initial_mode,
+        initial_query,
         modes,
     }
 }
+
+// 前回のモードが今は無い (名前が変わったなど) なら普通に起動する
+fn initial_mode_and_query(
+    modes: &[(String, MkMode)],
+    last_session: Option<LastSession>,
+    initial_mode: Option<String>,
+) -> (String, String) {
+    match last_session.filter(|s| modes.iter().any(|(name, _)| *name == s.mode)) {
+        Some(LastSession { mode, query }) => (mode, query),
+        None => {
+            let initial_mode = initial_mode
+                .or_else(|| file().initial_mode.clone())
+                .unwrap_or(mode::menu::Menu.name().to_string());
+            (initial_mode, String::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_restores_saved_mode_and_query() {
+        let modes = mode::all_modes();
+        let session = LastSession {
+            mode: "livegrep".to_string(),
+            query: "foo".to_string(),
+        };
+        assert_eq!(
+            initial_mode_and_query(&modes, Some(session), Some("fd".to_string())),
+            ("livegrep".to_string(), "foo".to_string())
+        );
+    }
+
+    #[test]
+    fn resume_ignores_unknown_mode() {
+        let modes = mode::all_modes();
+        let session = LastSession {
+            mode: "no-such-mode".to_string(),
+            query: "foo".to_string(),
+        };
+        assert_eq!(
+            initial_mode_and_query(&modes, Some(session), Some("fd".to_string())),
+            ("fd".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn sample_config_file_is_parsed() {
+        let config_file = parse_file(
+            r#"
+            modes = ["fd", "buffer", "git-diff", "livegrep"]
+            initial_mode = "fd"
+            fzf_opts = ["--border"]
+
+            [mode_keys]
+            ctrl-f = "fd"
+            ctrl-o = "mru"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config_file.modes,
+            Some(vec![
+                "fd".to_string(),
+                "buffer".to_string(),
+                "git-diff".to_string(),
+                "livegrep".to_string(),
+            ])
+        );
+        assert_eq!(config_file.initial_mode.as_deref(), Some("fd"));
+        assert_eq!(config_file.fzf_opts, vec!["--border".to_string()]);
+        assert_eq!(config_file.mode_keys["ctrl-o"], "mru");
+    }
+
+    #[test]
+    fn unknown_mode_in_config_file_is_rejected() {
+        let e = parse_file(r#"modes = ["fd", "no-such-mode"]"#).unwrap_err();
+        assert_eq!(e.to_string(), "unknown mode: no-such-mode");
+        let e = parse_file("[mode_keys]\nctrl-f = \"no-such-mode\"").unwrap_err();
+        assert_eq!(e.to_string(), "unknown mode: no-such-mode");
+        assert!(parse_file("no_such_field = 1").is_err());
+    }
+
+    #[test]
+    fn state_dir_prefers_xdg_state_home() {
+        let dir = state_dir_from(Some("/xdg".to_string()), Some("/home/u".to_string()), 1000);
+        assert_eq!(dir, "/xdg/fzfw");
+    }
+
+    #[test]
+    fn state_dir_falls_back_to_home_then_uid() {
+        let dir = state_dir_from(Some("".to_string()), Some("/home/u".to_string()), 1000);
+        assert_eq!(dir, "/home/u/.local/state/fzfw");
+        let dir = state_dir_from(None, None, 1000);
+        assert_eq!(dir, "/tmp/fzfw-1000");
+    }
+}
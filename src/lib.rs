@@ -6,6 +6,7 @@ mod mode;
 mod nvim;
 mod server;
 mod state;
+pub mod timing;
 mod utils;
 
 // std
@@ -33,6 +34,8 @@ use clap::Parser;
 
 // tokio
 use tokio::net::UnixListener;
+use tokio::signal;
+use tokio::signal::unix::SignalKind;
 
 use crate::client::run_command;
 use crate::client::Command;
@@ -52,6 +55,7 @@ static VERSION: Lazy<String> = Lazy::new(|| {
 #[derive(Parser)]
 #[clap(author, version = VERSION.as_str(), about, long_about = None)]
 #[clap(propagate_version = true)]
+#[clap(subcommand_negates_reqs = true)]
 struct Cli {
     /// (internal)
     #[clap(subcommand)]
@@ -60,8 +64,17 @@ struct Cli {
     #[clap(long, env)]
     fzfw_self: Option<String>,
 
-    #[clap(long, env, default_value = "/tmp/fzfw")]
-    fzfw_log_file: String,
+    /// Log file prefix (default: $XDG_STATE_HOME/fzfw/log)
+    #[clap(long, env)]
+    fzfw_log_file: Option<String>,
+
+    /// Mode to start with (default: menu).
+    #[clap(long, env)]
+    fzfw_initial_mode: Option<String>,
+
+    /// Restore the mode and query of the last session instead of the initial mode.
+    #[clap(long, env)]
+    fzfw_resume: bool,
 
     /// Address or filepath to a socket used to communicate with neovim.
     #[clap(long, env, required_unless("nvim-listen-address"))]
@@ -104,9 +117,12 @@ async fn init(args: Cli) -> Result<(), Box<dyn Error>> {
         UnixListener::bind(sockfile).expect("Failed to bind socket")
     }
 
-    let nvim = start_nvim(&args.nvim.or(args.nvim_listen_address).unwrap())
-        .await
-        .map_err(|e| e.to_string())?;
+    config::load_file().map_err(|e| e.to_string())?;
+
+    let nvim_address = args.nvim.or(args.nvim_listen_address).unwrap();
+    let nvim = start_nvim(&nvim_address).await.map_err(|e| e.to_string())?;
+
+    utils::git::export_git_env();
 
     let socket_name = gen_socket_name();
     let socket = create_listener(&socket_name);
@@ -115,21 +131,47 @@ async fn init(args: Cli) -> Result<(), Box<dyn Error>> {
     let config = config::new(
         myself.clone(),
         nvim,
+        nvim_address,
         socket_name.clone(),
-        args.fzfw_log_file,
+        args.fzfw_log_file.unwrap(),
+        args.fzfw_initial_mode,
+        args.fzfw_resume,
     );
+    if !config.has_mode(&config.initial_mode) {
+        fs::remove_file(&socket_name).ok();
+        return Err(format!("unknown mode: {}", config.initial_mode).into());
+    }
     let state = state::State::new();
 
-    server::server(config, state, socket)
-        .await
-        .unwrap_or_else(|e| {
-            error!("server: error"; "error" => e);
-        });
+    let r = serve(server::server(config, state, socket), &socket_name).await;
+    Ok(r?)
+}
+
+// SIGTERM/SIGINT で殺されてもソケットを消してから終了する
+async fn serve(
+    server: impl std::future::Future<Output = Result<(), String>>,
+    socket_name: &str,
+) -> Result<(), String> {
+    let mut sigterm = signal::unix::signal(SignalKind::terminate()).map_err(|e| e.to_string())?;
+    let r = tokio::select! {
+        r = server => r,
+        _ = sigterm.recv() => {
+            info!("server: received SIGTERM, exiting");
+            Ok(())
+        }
+        _ = signal::ctrl_c() => {
+            info!("server: received SIGINT, exiting");
+            Ok(())
+        }
+    };
+    if let Err(e) = &r {
+        error!("server: error"; "error" => e);
+    }
 
     // 後始末
-    fs::remove_file(&socket_name).expect("Failed to remove socket");
+    fs::remove_file(socket_name).expect("Failed to remove socket");
 
-    Ok(())
+    r
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -137,15 +179,62 @@ async fn init(args: Cli) -> Result<(), Box<dyn Error>> {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 pub async fn tokio_main() -> Result<(), Box<dyn Error>> {
-    let args = Cli::parse();
+    let mut args = Cli::parse();
+    let log_file = args
+        .fzfw_log_file
+        .get_or_insert_with(logger::default_log_file)
+        .clone();
     match args.command {
         None => {
-            let _guard = logger::init(&format!("{}-server.log", args.fzfw_log_file))?;
+            let _guard = logger::init(format!("{}-server.log", log_file))?;
             init(args).await
         }
         Some(command) => {
-            let _guard = logger::init(&format!("{}-client.log", args.fzfw_log_file))?;
+            let _guard = logger::init(format!("{}-client.log", log_file))?;
             run_command(command).await
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn socket_is_removed_when_server_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_name = dir.path().join("fzfw.sock");
+        let socket_name = socket_name.to_str().unwrap();
+        let _listener = UnixListener::bind(socket_name).unwrap();
+
+        // fzf が PATH に無いときの server の結果
+        let server = async { Err("fzfw: fzf not found on PATH".to_string()) };
+        let r = serve(server, socket_name).await;
+        assert_eq!(r, Err("fzfw: fzf not found on PATH".to_string()));
+        assert!(!Path::new(socket_name).exists());
+    }
+
+    #[tokio::test]
+    async fn socket_is_removed_on_sigterm() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_name = dir.path().join("fzfw.sock");
+        let socket_name = socket_name.to_str().unwrap().to_string();
+        let _listener = UnixListener::bind(&socket_name).unwrap();
+
+        // 先に handler を入れておき、送った SIGTERM でテストプロセスごと死なないようにする
+        let _guard = signal::unix::signal(SignalKind::terminate()).unwrap();
+        let server = tokio::spawn({
+            let socket_name = socket_name.clone();
+            async move { serve(std::future::pending(), &socket_name).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+
+        let r = tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server must exit on SIGTERM")
+            .unwrap();
+        assert_eq!(r, Ok(()));
+        assert!(!Path::new(&socket_name).exists());
+    }
+}
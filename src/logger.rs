@@ -9,7 +9,17 @@ use slog::PushFnValue;
 use slog::Record;
 use slog_scope::GlobalLoggerGuard;
 
+use crate::config;
+
+/// `{state_dir}/log` (see `config::state_dir`)
+pub fn default_log_file() -> String {
+    format!("{}/log", config::state_dir())
+}
+
 pub fn init(log_path: impl AsRef<Path>) -> Result<GlobalLoggerGuard, Box<dyn Error>> {
+    if let Some(dir) = log_path.as_ref().parent() {
+        std::fs::create_dir_all(dir)?;
+    }
     let file = OpenOptions::new()
         .create(true)
         .append(true)
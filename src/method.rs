@@ -2,6 +2,7 @@ use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::utils::ansi;
 use crate::utils::fzf::PreviewWindow;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -48,6 +49,18 @@ pub enum Request {
         method: ChangeDirectory,
         params: <ChangeDirectory as Method>::Param,
     },
+    GetPrompt {
+        method: GetPrompt,
+        params: <GetPrompt as Method>::Param,
+    },
+    ResetMode {
+        method: ResetMode,
+        params: <ResetMode as Method>::Param,
+    },
+    Transform {
+        method: Transform,
+        params: <Transform as Method>::Param,
+    },
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -61,6 +74,14 @@ pub struct Preview;
 #[derive(Serialize, Deserialize, clap::Parser, Default, Clone, Debug)]
 pub struct PreviewParam {
     pub item: String,
+    /// registered name of the preview callback (default: "default")
+    #[clap(long)]
+    #[serde(default)]
+    pub callback: Option<String>,
+    /// index of the item (`{n}`)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub index: ItemIndex,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
@@ -75,7 +96,7 @@ impl Method for Preview {
         "preview"
     }
     fn request(self, params: Self::Param) -> Request {
-        let preview_window = PreviewWindow::from_env().unwrap();
+        let preview_window = PreviewWindow::from_env();
         Request::Preview {
             method: Preview,
             params,
@@ -90,6 +111,11 @@ impl PreviewResp {
             message: err.to_string(),
         }
     }
+    pub fn strip_ansi(self) -> Self {
+        Self {
+            message: ansi::strip(&self.message),
+        }
+    }
 }
 
 impl TryFrom<String> for Preview {
@@ -139,6 +165,123 @@ impl From<GetLastLoad> for String {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ResetMode method
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct ResetMode;
+
+impl Method for ResetMode {
+    type Param = ();
+    type Response = ();
+    fn method_name() -> &'static str {
+        "reset_mode"
+    }
+    fn request(self, params: Self::Param) -> Request {
+        Request::ResetMode {
+            method: ResetMode,
+            params,
+        }
+    }
+}
+
+impl TryFrom<String> for ResetMode {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        mk_try_from()(s)
+    }
+}
+
+impl From<ResetMode> for String {
+    fn from(_: ResetMode) -> Self {
+        <ResetMode as Method>::method_name().to_string()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// GetPrompt method
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct GetPrompt;
+
+impl Method for GetPrompt {
+    type Param = ();
+    type Response = String;
+    fn method_name() -> &'static str {
+        "get_prompt"
+    }
+    fn request(self, params: Self::Param) -> Request {
+        Request::GetPrompt {
+            method: GetPrompt,
+            params,
+        }
+    }
+}
+
+impl TryFrom<String> for GetPrompt {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        mk_try_from()(s)
+    }
+}
+
+impl From<GetPrompt> for String {
+    fn from(_: GetPrompt) -> Self {
+        <GetPrompt as Method>::method_name().to_string()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Transform method
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct Transform;
+
+/// The response is a string of fzf actions (e.g. `pos(3)`), run by fzf's `transform` action.
+impl Method for Transform {
+    type Param = TransformParam;
+    type Response = String;
+    fn method_name() -> &'static str {
+        "transform"
+    }
+    fn request(self, params: Self::Param) -> Request {
+        Request::Transform {
+            method: Transform,
+            params,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, clap::Parser, Clone, Debug)]
+pub struct TransformParam {
+    pub registered_name: String,
+    pub query: String,
+    pub item: String,
+    /// index of the item (`{n}`)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub index: ItemIndex,
+}
+
+impl TryFrom<String> for Transform {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        mk_try_from()(s)
+    }
+}
+
+impl From<Transform> for String {
+    fn from(_: Transform) -> Self {
+        <Transform as Method>::method_name().to_string()
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // ChangeMode method
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -149,7 +292,8 @@ pub struct ChangeMode;
 
 impl Method for ChangeMode {
     type Param = ChangeModeParam;
-    type Response = ();
+    /// `Err` if the mode does not exist (the current mode is kept)
+    type Response = Result<(), String>;
     fn method_name() -> &'static str {
         "change_mode"
     }
@@ -165,6 +309,10 @@ impl Method for ChangeMode {
 pub struct ChangeModeParam {
     pub mode: String,
     pub query: Option<String>,
+    /// Passed to the new mode (see `ModeDef::set_arg`)
+    #[clap(long)]
+    #[serde(default)]
+    pub arg: Option<String>,
 }
 
 impl TryFrom<String> for ChangeMode {
@@ -319,6 +467,31 @@ pub struct ExecuteParam {
     pub registered_name: String,
     pub query: String,
     pub item: String,
+    /// selected items ({+}). only passed by multi-select actions
+    #[clap(multiple_values = true)]
+    #[serde(default)]
+    pub items: Vec<String>,
+    /// index of the item (`{n}`)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub index: ItemIndex,
+}
+
+/// 0-based index of the current item in the loaded list (fzf's `{n}`).
+/// fzf passes an empty string when nothing matches, which becomes `None`.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug)]
+#[serde(transparent)]
+pub struct ItemIndex(pub Option<usize>);
+
+impl std::str::FromStr for ItemIndex {
+    type Err = std::num::ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            Ok(ItemIndex(None))
+        } else {
+            s.parse().map(|n| ItemIndex(Some(n)))
+        }
+    }
 }
 
 impl TryFrom<String> for Execute {
@@ -363,7 +536,19 @@ pub struct LoadResp {
     pub is_last: bool,
 }
 
+/// 結果が空のときに表示する item の印。
+/// この印で始まる item は execute / preview で「何も選択されていない」ものとして扱われる。
+pub const EMPTY_ITEM_MARKER: &str = "\u{200b}";
+
+pub fn is_empty_item(item: &str) -> bool {
+    item.starts_with(EMPTY_ITEM_MARKER)
+}
+
 impl LoadResp {
+    /// A single informational line shown instead of a blank list.
+    pub fn empty_with_message(msg: impl AsRef<str>) -> Self {
+        Self::new_with_default_header(vec![format!("{EMPTY_ITEM_MARKER}({})", msg.as_ref())])
+    }
     pub fn new_with_default_header(items: Vec<String>) -> Self {
         let pwd = std::env::current_dir().unwrap().into_os_string();
         Self {
@@ -394,6 +579,13 @@ impl LoadResp {
             is_last: true,
         }
     }
+    pub fn strip_ansi(self) -> Self {
+        Self {
+            header: self.header.map(|h| ansi::strip(&h)),
+            items: self.items.iter().map(|i| ansi::strip(i)).collect(),
+            is_last: self.is_last,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, clap::Parser, Clone, Debug)]
@@ -401,6 +593,14 @@ pub struct LoadParam {
     pub registered_name: String,
     pub query: String,
     pub item: Option<String>,
+    /// index of the item (`{n}`)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub index: ItemIndex,
+    /// Generation of the fzf that sent this request (see `fzf::Config::generation`)
+    #[clap(long, env = "FZFW_GENERATION", default_value_t = 0)]
+    #[serde(default)]
+    pub generation: u64,
 }
 
 impl TryFrom<String> for Load {
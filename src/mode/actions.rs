@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::state::State;
+use crate::utils::action_log;
+use crate::utils::action_log::Record;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// Execute callbacks fzfw ran (`action_log`), newest first
+#[derive(Clone)]
+pub struct Actions {
+    // fzf 上で n 番目 ({n}) の item が items[n]
+    items: Arc<Mutex<Option<Vec<Record>>>>,
+}
+
+impl Actions {
+    pub const NAME: &'static str = "actions";
+
+    pub fn new() -> Self {
+        Self {
+            items: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn lookup(&self, index: Option<usize>) -> Result<Record> {
+        let items = self.items.lock().await;
+        let items = items.as_ref().ok_or(anyhow!("actions not loaded"))?;
+        index
+            .and_then(|n| items.get(n))
+            .cloned()
+            .ok_or(anyhow!("no action at index {index:?}"))
+    }
+}
+
+impl ModeDef for Actions {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+    fn replayable(&self) -> bool {
+        false
+    }
+    fn load<'a>(
+        &'a self,
+        _config: &Config,
+        _state: &'a mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'a> {
+        Box::pin(async_stream::stream! {
+            let records = action_log::list()?;
+            if records.is_empty() {
+                self.items.lock().await.replace(vec![]);
+                yield Ok(LoadResp::empty_with_message("no actions logged yet"));
+                return;
+            }
+            let rendered = records.iter().map(render).collect::<Vec<_>>();
+            self.items.lock().await.replace(records);
+            yield Ok(LoadResp::new_with_default_header(rendered))
+        })
+    }
+    fn preview<'a>(
+        &'a self,
+        config: &'a Config,
+        win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        self.preview_with_index(config, win, item, None)
+    }
+    fn preview_with_index<'a>(
+        &'a self,
+        _config: &'a Config,
+        _win: &'a PreviewWindow,
+        _item: String,
+        index: Option<usize>,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            let record = self.lookup(index).await?;
+            let message = serde_json::to_string_pretty(&record)?;
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                {
+                    let self_ = self.clone();
+                    b.execute(move |_mode,config,state,_query,_item| {
+                        let self_ = self_.clone();
+                        async move {
+                            let record = self_.lookup(state.index).await?;
+                            rerun(config, record).await
+                        }.boxed()
+                    })
+                },
+                b.reload(),
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// 01-02 15:04:05 ✓ git-diff ctrl-x src/main.rs
+fn render(record: &Record) -> String {
+    let time = chrono::DateTime::parse_from_rfc3339(&record.timestamp)
+        .map(|t| t.format("%m-%d %H:%M:%S").to_string())
+        .unwrap_or(record.timestamp.clone());
+    let mark = if record.success {
+        ansi_term::Colour::Green.paint("✓")
+    } else {
+        ansi_term::Colour::Red.paint("✗")
+    };
+    format!(
+        "{} {mark} {} {} {}",
+        ansi_term::Colour::Fixed(8).paint(time),
+        ansi_term::Colour::Cyan.paint(&record.mode),
+        ansi_term::Colour::Yellow.paint(&record.action),
+        record.item,
+    )
+}
+
+// callback の名前は登録順で決まるので、fzfw を更新すると別の操作を指しうる。
+// 記録時と同じキーに結び付いていることを確かめてから実行する
+async fn rerun(config: &Config, record: Record) -> Result<()> {
+    let cwd = std::env::current_dir()?.to_string_lossy().into_owned();
+    if cwd != record.cwd {
+        return Err(anyhow!("cd to {} to run this action again", record.cwd));
+    }
+    let mode = config
+        .try_get_mode(&record.mode)
+        .ok_or(anyhow!("unknown mode: {}", record.mode))?;
+    // 読み込んだ item を引く callback は、読み込んでいない mode で動かすと別の item に作用しうる
+    if !mode.mode_def.replayable() {
+        return Err(anyhow!(
+            "actions of {} depend on its loaded items and cannot be run again from here",
+            record.mode
+        ));
+    }
+    let callbacks = mode.callbacks();
+    if callbacks.key_of(&record.callback) != Some(record.action.as_str()) {
+        return Err(anyhow!(
+            "{} in {} has changed since it was logged",
+            record.action,
+            record.mode
+        ));
+    }
+    let header = format!(
+        "run {} in {} on '{}' again?",
+        record.action, record.mode, record.item
+    );
+    if !fzf::confirm(header).await? {
+        return Ok(());
+    }
+    let callback = callbacks
+        .execute
+        .get(&record.callback)
+        .ok_or(anyhow!("unknown callback: {}", record.callback))?;
+
+    let mut state = State::new();
+    state.selected_items = if record.items.is_empty() {
+        vec![record.item.clone()]
+    } else {
+        record.items.clone()
+    };
+    let result = (callback.callback)(
+        mode.mode_def.as_ref(),
+        config,
+        &mut state,
+        record.query.clone(),
+        record.item.clone(),
+    )
+    .await;
+
+    let rerun = Record {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        success: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        ..record
+    };
+    if let Err(e) = action_log::append(&rerun) {
+        warn!("actions: failed to log action"; "error" => e.to_string());
+    }
+    result
+}
@@ -18,6 +18,7 @@ use crate::state::State;
 use crate::utils::bat;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::path::to_abspath;
 use crate::utils::path::to_relpath;
 use crate::utils::xsel;
 
@@ -87,6 +88,13 @@ impl ModeDef for Bookmark {
                     Ok(())
                 })
             ],
+            "ctrl-alt-y" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    let bookmark = BookmarkItem::parse(&item)?;
+                    xsel::yank(to_abspath(bookmark.file)).await?;
+                    Ok(())
+                })
+            ],
         }
     }
 }
@@ -123,7 +131,9 @@ async fn open(bookmark: BookmarkItem, config: &Config, opts: ExecOpts) -> Result
             let nvim = config.nvim.clone();
             let nvim_opts = nvim::OpenOpts {
                 line: Some(bookmark.line as usize),
+                col: None,
                 tabedit,
+                window: None,
             };
             let r = nvim.open(bookmark.file.clone().into(), nvim_opts).await;
             if let Err(e) = r {
@@ -2,12 +2,10 @@ use anyhow::anyhow;
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use once_cell::sync::Lazy;
-use regex::Regex;
 use serde::Deserialize;
+use serde::Serialize;
 use tokio::process::Command;
 
-use crate::bindings;
 use crate::config::Config;
 use crate::method::LoadResp;
 use crate::method::PreviewResp;
@@ -15,9 +13,11 @@ use crate::mode::config_builder;
 use crate::mode::CallbackMap;
 use crate::mode::ModeDef;
 use crate::state::State;
+use crate::utils::ansi;
 use crate::utils::browser;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::item;
 use crate::utils::sqlite;
 
 #[derive(Clone)]
@@ -39,6 +39,7 @@ impl BrowserBookmark {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct Item {
     title: String,
     url: String,
@@ -46,28 +47,29 @@ struct Item {
 
 impl Item {
     fn render(&self) -> String {
-        format!("{}|{}", self.title, self.url)
+        let display = format!("{} {}", self.title, self.url);
+        item::render_item(display, serde_json::to_string(self).unwrap())
     }
-    fn parse(item: String) -> Self {
-        let title = ITEM_PATTERN.replace(&item, "$title").into_owned();
-        let url = ITEM_PATTERN.replace(&item, "$url").into_owned();
-        Item { title, url }
+    fn parse(item: &str) -> Result<Self> {
+        let (_, payload) = item::parse_item(item);
+        Ok(serde_json::from_str(payload)?)
     }
 }
 
-static ITEM_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?P<title>.*)\|(?P<url>.*)").unwrap());
-
 impl ModeDef for BrowserBookmark {
     fn name(&self) -> &'static str {
         "browser-bookmark"
     }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        item::FZF_OPTS.to_vec()
+    }
     fn load<'a>(
         &'a self,
         _config: &'a Config,
         _state: &'a mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'a> {
         Box::pin(async_stream::stream! {
             let items = self
                 .load_items()
@@ -78,15 +80,16 @@ impl ModeDef for BrowserBookmark {
             yield Ok(LoadResp::new_with_default_header(items))
         })
     }
-    fn preview(
+    fn preview<'a>(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &'a PreviewWindow,
         item: String,
-    ) -> BoxFuture<'static, Result<PreviewResp>> {
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
         async move {
-            let Item { title, url } = Item::parse(item);
+            let Item { title, url } = Item::parse(&item)?;
             let message = format!("URL:   {url}\nTITLE: {title}");
+            let message = ansi::wrap_lines(&message, win.columns);
             Ok(PreviewResp { message })
         }
         .boxed()
@@ -100,7 +103,7 @@ impl ModeDef for BrowserBookmark {
                 b.execute(move |_mode,_config,_state,_query,item| {
                     let self_ = self_.clone();
                     async move {
-                        let url = ITEM_PATTERN.replace(&item, "$url").into_owned();
+                        let Item { url, .. } = Item::parse(&item)?;
                         Command::new(self_.browser.as_ref())
                             .arg(&url)
                             .spawn()
@@ -2,11 +2,10 @@ use anyhow::anyhow;
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use once_cell::sync::Lazy;
-use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
 use tokio::process::Command;
 
-use crate::bindings;
 use crate::config::Config;
 use crate::method::LoadResp;
 use crate::method::PreviewResp;
@@ -14,9 +13,12 @@ use crate::mode::config_builder;
 use crate::mode::CallbackMap;
 use crate::mode::ModeDef;
 use crate::state::State;
+use crate::utils::ansi;
 use crate::utils::browser;
+use crate::utils::dedup;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::item;
 use crate::utils::sqlite;
 
 #[derive(Clone)]
@@ -32,26 +34,42 @@ impl BrowserHistory {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct Item {
     url: String,
     title: String,
     date: String,
 }
 
-static ITEM_PATTERN: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"(?P<date>[^|]*)\|(?P<url>[^|]*)\|(?P<title>.*)").unwrap());
+impl Item {
+    fn render(&self) -> String {
+        self.render_with_count(1)
+    }
+    fn render_with_count(&self, count: usize) -> String {
+        let display = format!("{} {} {}", self.date, self.url, self.title);
+        let display = dedup::annotate(display, count);
+        item::render_item(display, serde_json::to_string(self).unwrap())
+    }
+    fn parse(item: &str) -> Result<Self> {
+        let (_, payload) = item::parse_item(item);
+        Ok(serde_json::from_str(payload)?)
+    }
+}
 
 impl ModeDef for BrowserHistory {
     fn name(&self) -> &'static str {
         "browser-history"
     }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        item::FZF_OPTS.to_vec()
+    }
     fn load<'a>(
         &'a self,
         _config: &'a Config,
         _state: &'a mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'a> {
         Box::pin(async_stream::stream! {
             let (db, query) = match self.browser {
                 browser::Browser::Firefox(_) => (get_firefox_db_path()?, firefox_query()),
@@ -65,24 +83,29 @@ impl ModeDef for BrowserHistory {
                     Ok(Item { url, title, date })
                 })
             })
-            .await??
-            .into_iter()
-            .map(|x| format!("{}|{}|{}", x.date, x.url, x.title))
-            .collect();
+            .await??;
+            // 同じ URL を何度も訪れていることが多いので、FZFW_DEDUP=1 なら新しいものだけ残す
+            let items = if dedup::enabled() {
+                dedup::dedup_by_key(items, |x| x.url.clone())
+                    .into_iter()
+                    .map(|(x, count)| x.render_with_count(count))
+                    .collect()
+            } else {
+                items.into_iter().map(|x| x.render()).collect()
+            };
             yield Ok(LoadResp::new_with_default_header(items))
         })
     }
-    fn preview(
+    fn preview<'a>(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &'a PreviewWindow,
         item: String,
-    ) -> BoxFuture<'static, Result<PreviewResp>> {
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
         async move {
-            let url = ITEM_PATTERN.replace(&item, "$url").into_owned();
-            let title = ITEM_PATTERN.replace(&item, "$title").into_owned();
-            let date = ITEM_PATTERN.replace(&item, "$date").into_owned();
+            let Item { url, title, date } = Item::parse(&item)?;
             let message = format!("URL:   {url}\nTITLE: {title}\nDATE:  {date}");
+            let message = ansi::wrap_lines(&message, win.columns);
             Ok(PreviewResp { message })
         }
         .boxed()
@@ -96,7 +119,7 @@ impl ModeDef for BrowserHistory {
                 b.execute(move |_mode,_config,_state,_query,item| {
                     let self_ = self_.clone();
                     async move {
-                        let url = ITEM_PATTERN.replace(&item, "$url").into_owned();
+                        let Item { url, .. } = Item::parse(&item)?;
                         Command::new(self_.browser.as_ref())
                             .arg(&url)
                             .spawn()
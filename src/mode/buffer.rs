@@ -1,8 +1,6 @@
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use once_cell::sync::Lazy;
-use regex::Regex;
 use rmpv::ext::from_value;
 use serde::Deserialize;
 use serde::Serialize;
@@ -21,28 +19,35 @@ use crate::state::State;
 use crate::utils::bat;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::icons;
+use crate::utils::path::parent_dir;
 use crate::utils::xsel;
 
 #[derive(Clone)]
 pub struct Buffer;
 
-static ITEM_PATTERN: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"\s*(?P<bufnr>\d+):(?P<path>.*)").unwrap());
-
 impl ModeDef for Buffer {
     fn name(&self) -> &'static str {
         "buffer"
     }
-    fn load(
-        &self,
-        config: &Config,
-        _state: &mut State,
+    fn replayable(&self) -> bool {
+        false
+    }
+    fn load<'a>(
+        &'a self,
+        config: &'a Config,
+        state: &'a mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'a> {
         let nvim = config.nvim.clone();
         Box::pin(async_stream::stream! {
-            let items = get_nvim_buffers(&nvim).await?;
+            let buffers = get_nvim_buffers(&nvim).await?;
+            let mut items = vec![];
+            for (item, payload) in buffers {
+                state.set_payload(&item, &payload);
+                items.push(item);
+            }
             yield Ok(LoadResp::new_with_default_header(items))
         })
     }
@@ -50,16 +55,32 @@ impl ModeDef for Buffer {
         &self,
         _config: &Config,
         _win: &PreviewWindow,
+        _item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        // preview_with_payload が payload から開く
+        async move {
+            Ok(PreviewResp {
+                message: "No Preview".to_string(),
+            })
+        }
+        .boxed()
+    }
+    fn preview_with_payload(
+        &self,
+        _config: &Config,
+        win: &PreviewWindow,
         item: String,
+        _index: Option<usize>,
+        payload: Option<serde_json::Value>,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
+        let columns = win.columns;
         async move {
-            let bufnr = ITEM_PATTERN.replace(&item, "$bufnr").into_owned();
-            let path = ITEM_PATTERN.replace(&item, "$path").into_owned();
+            let Payload { bufnr, name: path } = super::decode_payload(payload, &item)?;
             trace!("buffer: preview"; "bufnr" => bufnr, "path" => &path);
             let meta = std::fs::metadata(&path);
             match meta {
                 Ok(meta) if meta.is_file() => {
-                    let message = bat::render_file(&path).await?;
+                    let message = bat::render_file_or_markdown(&path, columns).await?;
                     Ok(PreviewResp { message })
                 }
                 _ => {
@@ -77,53 +98,92 @@ impl ModeDef for Buffer {
         bindings! {
             b <= default_bindings(),
             "enter" => [
-                execute!(b, |_mode,config,_state,_query,item| {
+                execute!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::Open { tabedit: false };
-                    exec(config, item, opts).await
+                    exec(config, mode.payload(state, &item)?, opts).await
                 })
             ],
             "ctrl-t" => [
-                execute!(b, |_mode,config,_state,_query,item| {
+                execute!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::Open { tabedit: true };
-                    exec(config, item, opts).await
+                    exec(config, mode.payload(state, &item)?, opts).await
+                })
+            ],
+            "alt-enter" => [
+                execute_silent_multi!(b, |mode,config,state,_query,_item| {
+                    let targets = state
+                        .selected_items
+                        .iter()
+                        .map(|item| {
+                            let Payload { bufnr, .. } = mode.payload(state, item)?;
+                            Ok((bufnr.into(), nvim::OpenOpts::default()))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    config.nvim.open_tabs(targets).await
                 })
             ],
             "ctrl-x" => [
-                execute!(b, |_mode,config,_state,_query,item| {
+                execute!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::Delete { force: true };
-                    exec(config, item, opts).await
+                    exec(config, mode.payload(state, &item)?, opts).await
                 }),
                 b.reload(),
             ],
             "ctrl-y" => [
-                execute!(b, |_mode,_config,_state,_query,item| {
-                    let file = ITEM_PATTERN.replace(&item, "$path");
-                    xsel::yank(file).await?;
+                execute!(b, |mode,_config,state,_query,item| {
+                    let Payload { name, .. } = mode.payload(state, &item)?;
+                    xsel::yank(name).await?;
                     Ok(())
                 })
             ],
+            "pgup" => [
+                select_and_execute!{b, |mode,config,state,_query,item|
+                    "oil" => {
+                        let Payload { name, .. } = mode.payload(state, &item)?;
+                        config.nvim.open_oil(parent_dir(name)).await
+                    },
+                }
+            ],
         }
     }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        vec!["--multi"]
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Util
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-async fn get_nvim_buffers(nvim: &Neovim) -> Result<Vec<String>> {
-    let buffers: Vec<BufferItem> = from_value(nvim.eval("getbufinfo()").await?)?;
+async fn get_nvim_buffers(nvim: &Neovim) -> Result<Vec<(String, Payload)>> {
+    let buffers: Vec<BufferItem> = from_value(nvim.eval_retry("getbufinfo()").await?)?;
     let mut buffers: Vec<BufferItem> = buffers
         .into_iter()
         // .filter(|b| b.name.len() > 0 && b.hidden == 0 && b.loaded == 1)
         .filter(|b| !b.name.is_empty() && b.listed > 0)
         .collect();
-    buffers.sort_by(|a, b| b.lastused.cmp(&a.lastused));
+    buffers.sort_by_key(|b| std::cmp::Reverse(b.lastused));
     trace!("buffer: get_nvim_buffers: buffers"; "buffers" => Serde(buffers.clone()));
-    let items = buffers
+    Ok(render_buffers(buffers))
+}
+
+fn render_buffers(buffers: Vec<BufferItem>) -> Vec<(String, Payload)> {
+    buffers
         .into_iter()
-        .map(|b| format!("{:>3}:{}", b.bufnr, b.name))
-        .collect();
-    Ok(items)
+        .map(|b| {
+            let item = format!("{:>3}:{}", b.bufnr, b.name);
+            let item = if icons::enabled() {
+                format!("{} {}", icons::icon_for(&b.name), item)
+            } else {
+                item
+            };
+            let payload = Payload {
+                bufnr: b.bufnr as usize,
+                name: b.name,
+            };
+            (item, payload)
+        })
+        .collect()
 }
 
 // :h getbufinfo() から抜粋
@@ -138,22 +198,28 @@ struct BufferItem {
     loaded: u64,
 }
 
+// load で State に保存しておき、execute で表示文字列を parse せずに取り出す
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Payload {
+    bufnr: usize,
+    name: String,
+}
+
 enum ExecOpts {
     Open { tabedit: bool },
     Delete { force: bool },
 }
 
-async fn exec(config: &Config, item: String, opts: ExecOpts) -> Result<()> {
-    let bufnr = ITEM_PATTERN
-        .replace(&item, "$bufnr")
-        .into_owned()
-        .parse::<usize>()?;
+async fn exec(config: &Config, payload: Payload, opts: ExecOpts) -> Result<()> {
+    let bufnr = payload.bufnr;
     match opts {
         ExecOpts::Open { tabedit } => {
             let nvim = config.nvim.clone();
             let nvim_opts = nvim::OpenOpts {
                 line: None,
+                col: None,
                 tabedit,
+                window: None,
             };
             let r = nvim.open(bufnr.into(), nvim_opts).await;
             if let Err(e) = r {
@@ -170,3 +236,39 @@ async fn exec(config: &Config, item: String, opts: ExecOpts) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_survives_load_to_execute() {
+        let buffers = vec![BufferItem {
+            bufnr: 3,
+            name: "/tmp/a b:c.rs".to_string(),
+            lnum: 1,
+            lastused: 0,
+            listed: 1,
+            hidden: 0,
+            loaded: 1,
+        }];
+        // load と同じく表示文字列に payload を付けておく
+        let mut state = State::new();
+        let mut items = vec![];
+        for (item, payload) in render_buffers(buffers) {
+            state.set_payload(&item, &payload);
+            items.push(item);
+        }
+
+        let mode: &(dyn ModeDef + Sync + Send) = &Buffer;
+        let Payload { bufnr, name } = mode.payload(&state, &items[0]).unwrap();
+        assert_eq!((bufnr, name.as_str()), (3, "/tmp/a b:c.rs"));
+        let Payload { bufnr, .. } =
+            super::super::decode_payload(state.payload_value(&items[0]), &items[0]).unwrap();
+        assert_eq!(bufnr, 3);
+
+        // 次の load の前に消える
+        state.clear_payloads();
+        assert!(mode.payload::<Payload>(&state, &items[0]).is_err());
+    }
+}
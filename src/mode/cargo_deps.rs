@@ -0,0 +1,164 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::state::State;
+use crate::utils::browser;
+use crate::utils::command;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::xsel;
+
+#[derive(Clone)]
+pub struct CargoDeps;
+
+struct Item {
+    name: String,
+    version: String,
+}
+
+impl Item {
+    fn render(&self) -> String {
+        format!("{} {}", self.name, self.version)
+    }
+    fn parse(item: &str) -> Result<Self> {
+        let (name, version) = item.split_once(' ').ok_or(anyhow!("invalid item"))?;
+        Ok(Item {
+            name: name.to_string(),
+            version: version.to_string(),
+        })
+    }
+}
+
+mod dto {
+    #[derive(serde::Deserialize, Debug)]
+    pub(crate) struct Metadata {
+        pub(crate) packages: Vec<Package>,
+        pub(crate) workspace_members: Vec<String>,
+    }
+    #[derive(serde::Deserialize, Debug)]
+    pub(crate) struct Package {
+        pub(crate) id: String,
+        pub(crate) name: String,
+        pub(crate) version: String,
+    }
+}
+
+impl ModeDef for CargoDeps {
+    fn name(&self) -> &'static str {
+        "cargo-deps"
+    }
+    fn load<'a>(
+        &'a self,
+        _config: &'a Config,
+        _state: &'a mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'a> {
+        Box::pin(async_stream::stream! {
+            let output = Command::new("cargo")
+                .arg("metadata")
+                .arg("--format-version=1")
+                .output()
+                .await?;
+            if !output.status.success() {
+                yield Ok(LoadResp::empty_with_message(
+                    "not in a cargo project: run fzfw in a directory containing Cargo.toml",
+                ));
+                return;
+            }
+            let metadata = serde_json::from_slice::<dto::Metadata>(&output.stdout)?;
+            let mut items = metadata
+                .packages
+                .into_iter()
+                .filter(|p| !metadata.workspace_members.contains(&p.id))
+                .map(|p| {
+                    Item {
+                        name: p.name,
+                        version: p.version,
+                    }
+                    .render()
+                })
+                .collect::<Vec<_>>();
+            items.sort();
+            yield Ok(LoadResp::new_with_default_header(items))
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        _win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        async move {
+            let Item { name, version } = Item::parse(&item)?;
+            // 誰がこの crate に依存しているか
+            let output = command::output_with_timeout(
+                "preview",
+                Command::new("cargo")
+                    .arg("tree")
+                    .arg("--color=always")
+                    .arg("--invert")
+                    .arg(format!("{name}@{version}")),
+                command::preview_timeout(),
+            )
+            .await?;
+            let message = if output.status.success() {
+                String::from_utf8_lossy(output.stdout.as_slice()).into_owned()
+            } else {
+                String::from_utf8_lossy(output.stderr.as_slice()).into_owned()
+            };
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,_config,_state,_query,item| {
+                    let Item { name, version } = Item::parse(&item)?;
+                    open_browser(format!("https://docs.rs/{name}/{version}")).await
+                })
+            ],
+            "ctrl-y" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    let Item { name, version } = Item::parse(&item)?;
+                    xsel::yank(format!("{name} = \"{version}\"")).await?;
+                    Ok(())
+                })
+            ],
+            "pgup" => [
+                select_and_execute!{b, |_mode,_config,_state,_query,item|
+                    "docs.rs" => {
+                        let Item { name, version } = Item::parse(&item)?;
+                        open_browser(format!("https://docs.rs/{name}/{version}")).await
+                    },
+                    "crates.io" => {
+                        let Item { name, version } = Item::parse(&item)?;
+                        open_browser(format!("https://crates.io/crates/{name}/{version}")).await
+                    },
+                }
+            ],
+        }
+    }
+}
+
+async fn open_browser(url: String) -> Result<()> {
+    let browser = browser::get_browser();
+    Command::new(browser.as_ref())
+        .arg(&url)
+        .spawn()?
+        .wait()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use futures::StreamExt as _;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::diff_pager;
+use crate::utils::fd;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::icons;
+use crate::utils::trash;
+use crate::utils::xsel;
+
+/// クリップボードの中身とファイルの diff を見て、必要ならクリップボードで上書きする
+#[derive(Clone)]
+pub struct ClipDiff;
+
+impl ModeDef for ClipDiff {
+    fn name(&self) -> &'static str {
+        "clip-diff"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let fd = fd::new(fd::FdOpts::default());
+            let stream = command::command_output_stream(fd).chunks(100); // tekito
+            tokio::pin!(stream);
+            while let Some(r) = stream.next().await {
+                let (lines, error) = command::split_chunk(r);
+                let lines = lines.into_iter().map(icons::decorate).collect();
+                yield Ok(LoadResp::wip_with_default_header(lines));
+                if let Some(e) = error {
+                    yield Ok(LoadResp::error(e.to_string()));
+                    return;
+                }
+            }
+            yield Ok(LoadResp::last())
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        let win = *win;
+        async move {
+            let file = icons::strip(&item).to_string();
+            let clipboard = match clipboard_text().await {
+                Ok(clipboard) => clipboard,
+                Err(e) => {
+                    return Ok(PreviewResp {
+                        message: e.to_string(),
+                    })
+                }
+            };
+            let diff = diff_with(&file, &clipboard).await?;
+            let message = if diff.is_empty() {
+                "(no difference)".to_string()
+            } else {
+                diff_pager::render(&diff, &win).await.unwrap_or(diff)
+            };
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let file = icons::strip(&item).to_string();
+                    let clipboard = clipboard_text().await?;
+                    if !fzf::confirm(format!("overwrite {file} with the clipboard? (the original goes to the trash)")).await? {
+                        return Ok(());
+                    }
+                    apply(&file, &clipboard).await?;
+                    config.nvim.notify_info(format!("overwrote {file} with the clipboard")).await
+                })
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// これより大きいものは設定ファイルの貼り付けではないとみなす
+const MAX_CLIPBOARD_BYTES: usize = 1024 * 1024;
+
+// バイナリや巨大なものは diff しても意味がないので断る
+async fn clipboard_text() -> Result<String> {
+    let bytes = xsel::clipboard().await?;
+    if bytes.len() > MAX_CLIPBOARD_BYTES {
+        return Err(anyhow!(
+            "clipboard is too large ({} bytes > {MAX_CLIPBOARD_BYTES})",
+            bytes.len()
+        ));
+    }
+    if bytes.contains(&0) {
+        return Err(anyhow!("clipboard contains binary data"));
+    }
+    String::from_utf8(bytes).map_err(|_| anyhow!("clipboard is not valid UTF-8"))
+}
+
+// diff <(clipboard) file。git diff --no-index は差分があると 1 で終わる
+async fn diff_with(file: &str, clipboard: &str) -> Result<String> {
+    let mut tmp = tempfile::NamedTempFile::new()?;
+    tmp.write_all(clipboard.as_bytes())?;
+    let output = command::output_with_timeout(
+        "preview",
+        Command::new("git")
+            .arg("diff")
+            .arg("--no-index")
+            .arg("--color=always")
+            .arg("--no-ext")
+            .arg("--")
+            .arg(tmp.path())
+            .arg(file),
+        command::preview_timeout(),
+    )
+    .await?;
+    match output.status.code() {
+        Some(0) | Some(1) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => Err(anyhow!(
+            "git diff --no-index: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+// 元のファイルはゴミ箱に送ってバックアップにする。
+// trash-put が無いと消えてしまうので、その場合は上書きしない
+async fn apply(file: &str, clipboard: &str) -> Result<()> {
+    if !command::on_path("trash-put") {
+        return Err(anyhow!("trash-put is required to back up {file}"));
+    }
+    let permissions = tokio::fs::metadata(file).await?.permissions();
+    trash::remove(file).await?;
+    tokio::fs::write(file, clipboard).await?;
+    tokio::fs::set_permissions(file, permissions).await?;
+    Ok(())
+}
@@ -3,11 +3,11 @@ use anyhow::anyhow;
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use once_cell::sync::Lazy;
-use regex::Regex;
 use rmpv::ext::from_value;
 use serde::Deserialize;
 use serde::Serialize;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -25,25 +25,78 @@ use crate::utils::bat;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
 use crate::utils::glow;
+use crate::utils::path::to_abspath;
 use crate::utils::path::to_relpath;
+use crate::utils::xsel;
 
 #[derive(Clone)]
 pub struct Diagnostics {
-    items: Arc<Mutex<Option<Vec<DiagnosticsItem>>>>,
+    items: Arc<Mutex<Option<Loaded>>>,
+    // severity 1..=4 をそれぞれ表示するかどうか
+    severities: Arc<std::sync::Mutex<[bool; 4]>>,
+    // 次の load で neovim に問い合わせずに items を使い回す
+    use_cache: Arc<AtomicBool>,
 }
 
 impl Diagnostics {
     pub fn new() -> Self {
         Self {
             items: Arc::new(Mutex::new(None)),
+            severities: Arc::new(std::sync::Mutex::new([true; 4])),
+            use_cache: Arc::new(AtomicBool::new(false)),
         }
     }
+
+    /// Looks up the item by the index in fzf (`{n}`)
+    async fn lookup(&self, index: Option<usize>) -> Result<DiagnosticsItem> {
+        let loaded = self.items.lock().await;
+        let loaded = loaded.as_ref().ok_or(anyhow!("diagnostics not loaded"))?;
+        let item = index
+            .and_then(|n| loaded.shown.get(n))
+            .and_then(|&i| loaded.all.get(i))
+            .ok_or(anyhow!("no diagnostic at index {index:?}"))?;
+        Ok(item.clone())
+    }
+
+    fn severities(&self) -> [bool; 4] {
+        *self.severities.lock().unwrap()
+    }
+
+    fn toggle_severity(&self, severity: u64) {
+        let mut severities = self.severities.lock().unwrap();
+        let ix = severity as usize - 1;
+        severities[ix] = !severities[ix];
+        self.use_cache.store(true, Ordering::SeqCst);
+    }
+
+    fn toggle_severity_action(
+        &self,
+        b: &mut config_builder::ConfigBuilder,
+        severity: u64,
+    ) -> fzf::Action {
+        let self_ = self.clone();
+        b.toggle_option(move || self_.toggle_severity(severity))
+    }
 }
 
 impl ModeDef for Diagnostics {
     fn name(&self) -> &'static str {
         "diagnostics"
     }
+    fn replayable(&self) -> bool {
+        false
+    }
+    fn fzf_prompt(&self) -> String {
+        let severities = self.severities();
+        if severities.iter().all(|&on| on) {
+            return format!("{}>", self.name());
+        }
+        let shown = (1..=4)
+            .filter(|&s| severities[s as usize - 1])
+            .map(|s| Severity(s).letter())
+            .collect::<Vec<_>>();
+        format!("{}[{}]>", self.name(), shown.join(","))
+    }
     fn load<'a>(
         &'a self,
         config: &Config,
@@ -52,29 +105,56 @@ impl ModeDef for Diagnostics {
         _item: String,
     ) -> super::LoadStream<'a> {
         let nvim = config.nvim.clone();
+        let use_cache = self.use_cache.swap(false, Ordering::SeqCst);
+        let severities = self.severities();
         Box::pin(async_stream::stream! {
-            let mut diagnostics =
-                DiagnosticsItem::gather(&nvim).await?
-                .into_iter()
-                .filter(|d| !d.file.contains("node_modules"))
+            let mut cache = self.items.lock().await;
+            let diagnostics = match cache.take() {
+                Some(loaded) if use_cache => loaded.all,
+                _ => {
+                    let mut diagnostics =
+                        DiagnosticsItem::gather(&nvim).await?
+                        .into_iter()
+                        .filter(|d| !d.file.contains("node_modules"))
+                        .collect::<Vec<_>>();
+                    diagnostics.sort_by_key(|d| d.severity.0);
+                    diagnostics
+                }
+            };
+            let shown = diagnostics
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| d.severity.shown(&severities))
+                .map(|(i, _)| i)
                 .collect::<Vec<_>>();
-            diagnostics.sort_by(|a, b| a.severity.0.cmp(&b.severity.0));
-            let items = DiagnosticsItem::render_list(&diagnostics);
-            self.items.lock().await.replace(diagnostics);
-            yield Ok(LoadResp::new_with_default_header(items))
+            let items = shown.iter().map(|&i| diagnostics[i].render()).collect::<Vec<_>>();
+            cache.replace(Loaded { all: diagnostics, shown });
+            drop(cache);
+            if items.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no diagnostics"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
         })
     }
     fn preview<'a>(
         &'a self,
-        config: &Config,
-        _win: &PreviewWindow,
+        config: &'a Config,
+        win: &'a PreviewWindow,
         item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        self.preview_with_index(config, win, item, None)
+    }
+    fn preview_with_index<'a>(
+        &'a self,
+        config: &'a Config,
+        _win: &'a PreviewWindow,
+        _item: String,
+        index: Option<usize>,
     ) -> BoxFuture<'a, Result<PreviewResp>> {
         let nvim = config.nvim.clone();
         async move {
-            let items = self.items.lock().await;
-            let items = items.as_ref().ok_or(anyhow!("diagnostics not loaded"))?;
-            let item = DiagnosticsItem::lookup(items, item.clone())?;
+            let item = self.lookup(index).await?;
             let file = nvim.get_buf_name(item.bufnr as usize).await?;
             let rendered_message =
                 glow::render_markdown(format!("### {}\n{}", item.severity.render(), item.message))
@@ -93,12 +173,10 @@ impl ModeDef for Diagnostics {
             b <= default_bindings(),
             "enter" => [{
                 let self_ = self.clone();
-                b.execute(move |_mode,config,_state,_query,item| {
+                b.execute(move |_mode,config,state,_query,_item| {
                     let self_ = self_.clone();
                     async move {
-                        let items = self_.items.lock().await;
-                        let items = items.as_ref().ok_or(anyhow!("diagnostics not loaded"))?;
-                        let item = DiagnosticsItem::lookup(items, item.clone())?;
+                        let item = self_.lookup(state.index).await?;
                         let opts = OpenOpts { tabedit: false };
                         open(config, item, opts).await
                     }.boxed()
@@ -106,17 +184,46 @@ impl ModeDef for Diagnostics {
             }],
             "ctrl-t" => [{
                 let self_ = self.clone();
-                b.execute(move |_mode,config,_state,_query,item| {
+                b.execute(move |_mode,config,state,_query,_item| {
                     let self_ = self_.clone();
                     async move {
-                        let items = self_.items.lock().await;
-                        let items = items.as_ref().ok_or(anyhow!("diagnostics not loaded"))?;
-                        let item = DiagnosticsItem::lookup(items, item.clone())?;
+                        let item = self_.lookup(state.index).await?;
                         let opts = OpenOpts { tabedit: true };
                         open(config, item, opts).await
                     }.boxed()
                 })
             }],
+            "alt-1" => [
+                self.toggle_severity_action(&mut b, 1),
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "alt-2" => [
+                self.toggle_severity_action(&mut b, 2),
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "alt-3" => [
+                self.toggle_severity_action(&mut b, 3),
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "alt-4" => [
+                self.toggle_severity_action(&mut b, 4),
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "ctrl-alt-y" => [{
+                let self_ = self.clone();
+                b.execute_silent(move |_mode,_config,state,_query,_item| {
+                    let self_ = self_.clone();
+                    async move {
+                        let item = self_.lookup(state.index).await?;
+                        xsel::yank(to_abspath(&item.file)).await?;
+                        Ok(())
+                    }.boxed()
+                })
+            }],
         }
     }
 }
@@ -135,12 +242,16 @@ pub struct DiagnosticsItem {
     pub severity: Severity,
 }
 
-static ITEM_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r".*\s{200}(?P<num>\d+)$").unwrap());
+// shown[n] は fzf 上で n 番目 ({n}) の item の all での位置
+struct Loaded {
+    all: Vec<DiagnosticsItem>,
+    shown: Vec<usize>,
+}
 
 impl DiagnosticsItem {
     async fn gather(nvim: &Neovim) -> Result<Vec<DiagnosticsItem>> {
         Ok(from_value(
-            nvim.eval_lua(
+            nvim.eval_lua_retry(
                 r#"
                     local ds = vim.diagnostic.get()
                     for _, d in ipairs(ds) do
@@ -153,30 +264,14 @@ impl DiagnosticsItem {
         )?)
     }
 
-    fn render(&self, num: usize) -> String {
+    fn render(&self) -> String {
         format!(
-            "{} {}|{}{}{}",
+            "{} {}|{}",
             self.severity.mark(),
             to_relpath(&self.file),
             self.message.replace('\n', ". "),
-            " ".repeat(200), // numが表示から外れるように適当に長めに空白を入れる
-            num,
         )
     }
-
-    fn render_list(items: &[Self]) -> Vec<String> {
-        items.iter().enumerate().map(|(i, d)| d.render(i)).collect()
-    }
-
-    fn lookup(items: &[Self], item: String) -> Result<Self> {
-        let ix = ITEM_PATTERN
-            .captures(&item)
-            .and_then(|c| c.name("num"))
-            .and_then(|n| n.as_str().parse::<usize>().ok())
-            .ok_or(anyhow!("モポ"))?;
-        let item = items.get(ix).ok_or(anyhow!("モポ"))?.clone();
-        Ok(item)
-    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize, Serialize)]
@@ -192,6 +287,21 @@ impl Severity {
             _ => panic!("unknown severity {}", self.0),
         }
     }
+    pub fn letter(&self) -> &'static str {
+        match self.0 {
+            1 => "E",
+            2 => "W",
+            3 => "I",
+            4 => "H",
+            _ => panic!("unknown severity {}", self.0),
+        }
+    }
+    fn shown(&self, severities: &[bool; 4]) -> bool {
+        match self.0 {
+            1..=4 => severities[self.0 as usize - 1],
+            _ => true,
+        }
+    }
     pub fn render(&self) -> String {
         match self.0 {
             1 => "Error".to_string(),
@@ -211,8 +321,11 @@ async fn open(config: &Config, item: DiagnosticsItem, opts: OpenOpts) -> Result<
     let nvim = config.nvim.clone();
     let file = nvim.get_buf_name(item.bufnr as usize).await?;
     let opts = nvim::OpenOpts {
+        // どちらも zero-indexed
         line: Some(item.lnum as usize + 1),
+        col: Some(item.col as usize + 1),
         tabedit: opts.tabedit,
+        window: None,
     };
     let _ = tokio::spawn(async move {
         let r = nvim.open(file.into(), opts).await;
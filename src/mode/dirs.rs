@@ -0,0 +1,103 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::dirs;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// Recently visited directories (recorded by change-directory), without zoxide
+#[derive(Clone)]
+pub struct Dirs;
+
+impl ModeDef for Dirs {
+    fn name(&self) -> &'static str {
+        "dirs"
+    }
+    fn fzf_sort(&self) -> Option<bool> {
+        // frecency 順を保つ
+        Some(false)
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let dirs = dirs::ranked()?;
+            if dirs.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no directories visited yet"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(dirs))
+            }
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        _win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        async move {
+            let message = list_dir(&item).await?;
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                // server と neovim の cwd を両方移動して fd で開く
+                b.execute_silent_raw("change-directory --dir {}"),
+                b.change_mode(super::fd::Fd::new().name(), false),
+            ],
+        }
+    }
+}
+
+/// Contents of `dir` for previews (`eza`, or `ls` if it is missing).
+pub(super) async fn list_dir(dir: &str) -> Result<String> {
+    let eza = command::output_with_timeout(
+        "preview",
+        Command::new("eza")
+            .args(vec!["--color", "always"])
+            .args(vec!["--all"])
+            .args(vec!["--sort", "name"])
+            .args(vec!["--classify"])
+            .arg(dir),
+        command::preview_timeout(),
+    )
+    .await;
+    let output = match eza {
+        Ok(output) => output,
+        // eza が無ければ ls。timeout などはそのまま返す
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) =>
+        {
+            command::output_with_timeout(
+                "preview",
+                Command::new("ls")
+                    .args(vec!["-A", "-F", "--color=always"])
+                    .arg(dir),
+                command::preview_timeout(),
+            )
+            .await?
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(String::from_utf8_lossy(output.stdout.as_slice()).into_owned())
+}
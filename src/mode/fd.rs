@@ -1,3 +1,6 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
@@ -17,60 +20,139 @@ use crate::utils::bat;
 use crate::utils::command;
 use crate::utils::command::edit_and_run;
 use crate::utils::fd;
+use crate::utils::file_manager;
+use crate::utils::file_sort::FileSort;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
 use crate::utils::gh;
+use crate::utils::git;
+use crate::utils::icons;
+use crate::utils::image;
+use crate::utils::path::parent_dir;
+use crate::utils::pins;
 use crate::utils::vscode;
 use crate::utils::xsel;
 
 #[derive(Clone)]
-pub struct Fd;
+pub struct Fd {
+    opts: Arc<Mutex<fd::FdOpts>>,
+    sort: Arc<Mutex<FileSort>>,
+}
+
+impl Fd {
+    pub fn new() -> Self {
+        Self {
+            opts: Arc::new(Mutex::new(fd::FdOpts::default())),
+            sort: Arc::new(Mutex::new(FileSort::default())),
+        }
+    }
+
+    fn opts(&self) -> fd::FdOpts {
+        *self.opts.lock().unwrap()
+    }
+
+    fn sort(&self) -> FileSort {
+        *self.sort.lock().unwrap()
+    }
+
+    fn toggle_sort(&self) {
+        let mut sort = self.sort.lock().unwrap();
+        *sort = sort.next();
+    }
+
+    // hidden と no-ignore はまとめて切り替える
+    fn toggle_hidden(&self) {
+        let mut opts = self.opts.lock().unwrap();
+        let on = !(opts.hidden && opts.no_ignore);
+        opts.hidden = on;
+        opts.no_ignore = on;
+    }
+
+    fn toggle_follow(&self) {
+        let mut opts = self.opts.lock().unwrap();
+        opts.follow = !opts.follow;
+    }
+
+    fn toggle_directories(&self) {
+        let mut opts = self.opts.lock().unwrap();
+        opts.directories = !opts.directories;
+    }
+}
 
 impl ModeDef for Fd {
     fn name(&self) -> &'static str {
         "fd"
     }
+    fn fzf_prompt(&self) -> String {
+        let opts = self.opts();
+        let filters = [
+            ("hidden", opts.hidden),
+            ("no-ignore", opts.no_ignore),
+            ("follow", opts.follow),
+            ("dirs", opts.directories),
+        ]
+        .into_iter()
+        .filter_map(|(name, on)| on.then_some(name))
+        .chain(self.sort().label())
+        .collect::<Vec<_>>();
+        if filters.is_empty() {
+            format!("{}>", self.name())
+        } else {
+            format!("{}[{}]>", self.name(), filters.join(","))
+        }
+    }
     fn load(
         &self,
         _config: &Config,
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
+        let opts = self.opts();
+        let sort = self.sort();
         Box::pin(async_stream::stream! {
-            let fd = fd::new();
+            let pinned = pins::Pinned::load();
+            let fd = fd::new(opts);
             let stream = command::command_output_stream(fd).chunks(100); // tekito
             tokio::pin!(stream);
             let mut has_error = false;
+            // 並べ替えるときは全部揃うまで流せない
+            let mut buffered = vec![];
             while let Some(r) = stream.next().await {
-                let r = r.into_iter().collect::<Result<Vec<String>>>();
-                match r {
-                    Ok(lines) => {
-                        yield Ok(LoadResp::wip_with_default_header(lines));
-                    }
-                    Err(e) => {
-                        yield Ok(LoadResp::error(e.to_string()));
-                        has_error = true;
-                        break;
-                    }
+                let (lines, error) = command::split_chunk(r);
+                let lines = lines
+                    .into_iter()
+                    .map(|l| pinned.decorate(icons::decorate(&l), &l))
+                    .collect::<Vec<_>>();
+                if sort == FileSort::Default {
+                    yield Ok(LoadResp::wip_with_default_header(lines));
+                } else {
+                    buffered.extend(lines);
+                }
+                if let Some(e) = error {
+                    yield Ok(LoadResp::error(e.to_string()));
+                    has_error = true;
+                    break;
                 }
             }
             if !has_error {
-                yield Ok(LoadResp::last())
+                if sort == FileSort::Default {
+                    yield Ok(LoadResp::last())
+                } else {
+                    sort.sort(&mut buffered);
+                    yield Ok(LoadResp::new_with_default_header(buffered))
+                }
             }
         })
     }
     fn preview(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &PreviewWindow,
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
-        async move {
-            let message = bat::render_file(&item).await?;
-            Ok(PreviewResp { message })
-        }
-        .boxed()
+        let win = *win;
+        async move { preview(item, win).await }.boxed()
     }
     fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
         use config_builder::*;
@@ -78,13 +160,30 @@ impl ModeDef for Fd {
             b <= default_bindings(),
             "enter" => [
                 execute!(b, |_mode,config,_state,_query,item| {
-                    let opts = if vscode::in_vscode() {
-                        OpenOpts::VSCode
-                    } else {
-                        OpenOpts::Neovim { tabedit: false }
-                    };
-                    open(config, item, opts).await
-                })
+                    if selected_dir(&item).is_some() {
+                        return Ok(());
+                    }
+                    open_default(config, item).await
+                }),
+                // ディレクトリなら開かずに cd して読み直す (alt-D で一覧に出る)。
+                // execute の後に cd しないと、execute から相対パスが見えなくなる
+                b.transform(|_mode,config,_state,_query,item,_index| {
+                    async move {
+                        let Some(dir) = selected_dir(&item) else {
+                            return Ok(String::new());
+                        };
+                        super::change_directory(config, dir).await?;
+                        let actions = [
+                            fzf::Action::ClearQuery,
+                            fzf::Action::Reload("load --index {n} default {q} {}".to_string()),
+                        ];
+                        Ok(actions.map(|a| a.render(&config.myself)).join("+"))
+                    }
+                    .boxed()
+                }),
+            ],
+            "ctrl-/" => [
+                b.change_mode(FdF.name(), false),
             ],
             "ctrl-t" => [
                 execute!(b, |_mode,config,_state,_query,item| {
@@ -92,6 +191,21 @@ impl ModeDef for Fd {
                     open(config, item, opts).await
                 })
             ],
+            "alt-enter" => [
+                execute_silent_multi!(b, |_mode,config,state,_query,_item| {
+                    open_tabs(config, &state.selected_items).await
+                })
+            ],
+            "alt-a" => [
+                execute_silent_multi!(b, |_mode,config,state,_query,item| {
+                    // :argdo で一括操作できるように arglist にする
+                    let files = state.selected_or(item)
+                        .iter()
+                        .map(|item| icons::strip(item).to_string())
+                        .collect::<Vec<_>>();
+                    config.nvim.set_arglist(&files).await
+                })
+            ],
             "ctrl-v" => [
                 execute!(b, |_mode,config,_state,_query,item| {
                     let opts = OpenOpts::Vifm;
@@ -100,20 +214,70 @@ impl ModeDef for Fd {
             ],
             "ctrl-y" => [
                 execute!(b, |_mode,_config,_state,_query,item| {
-                    xsel::yank(item).await?;
+                    xsel::yank(icons::strip(&item)).await?;
                     Ok(())
                 })
             ],
+            "ctrl-p" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    pins::toggle(icons::strip(&item))?;
+                    Ok(())
+                }),
+                b.reload(),
+            ],
+            "ctrl-alt-g" => [
+                execute_silent!(b, |_mode,config,_state,_query,item| {
+                    let revision = git::rev_parse("HEAD")?;
+                    let url = gh::permalink(icons::strip(&item), &revision, None)?;
+                    xsel::yank(&url).await?;
+                    config.nvim.notify_info(format!("yanked {url}")).await
+                })
+            ],
+            "alt-h" => [
+                {
+                    let self_ = self.clone();
+                    b.toggle_option(move || self_.toggle_hidden())
+                },
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "alt-L" => [
+                {
+                    let self_ = self.clone();
+                    b.toggle_option(move || self_.toggle_follow())
+                },
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "alt-D" => [
+                {
+                    let self_ = self.clone();
+                    b.toggle_option(move || self_.toggle_directories())
+                },
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "alt-m" => [
+                {
+                    let self_ = self.clone();
+                    b.toggle_option(move || self_.toggle_sort())
+                },
+                b.reload(),
+                b.refresh_prompt(),
+            ],
             "pgup" => [
                 select_and_execute!{b, |_mode,config,_state,_query,item|
+                    "neovim (keep picker)" => {
+                        let opts = OpenOpts::OriginWindow;
+                        open(config, item, opts).await
+                    },
                     "oil" => {
-                        let cwd = std::env::current_dir().unwrap();
                         let opts = OpenOpts::Oil;
-                        open(config, format!("{}", cwd.display()), opts).await
+                        open(config, item, opts).await
                     },
                     "new file" => {
                         let cwd = std::env::current_dir().unwrap();
-                        let fname = fzf::input_with_placeholder("Enter file name", &item).await?;
+                        let fname = fzf::input_with_placeholder("Enter file name", icons::strip(&item)).await?;
                         let fname = fname.trim();
                         let path = format!("{}/{}", cwd.display(), fname);
                         let dir = std::path::Path::new(&path).parent().unwrap();
@@ -134,7 +298,7 @@ impl ModeDef for Fd {
                         open(config, path, opts).await
                     },
                     "execute any command" => {
-                        let (cmd, output) = edit_and_run(format!(" {item}"))
+                        let (cmd, output) = edit_and_run(format!(" {}", icons::strip(&item)))
                             .await?;
                         config.nvim.notify_command_result(&cmd, output)
                             .await?;
@@ -148,31 +312,182 @@ impl ModeDef for Fd {
                         let opts = OpenOpts::Xdragon;
                         open(config, item, opts).await
                     },
+                    "reveal in file manager" => {
+                        let opts = OpenOpts::FileManager;
+                        open(config, item, opts).await
+                    },
                 }
             ]
         }
     }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        vec!["--multi"]
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Fuzzy search after fd
+////////////////////////////////////////////////////////////////////////////////
+
+/// fd の結果を fd を再実行せずに絞り込む
+#[derive(Clone)]
+pub struct FdF;
+
+impl ModeDef for FdF {
+    fn name(&self) -> &'static str {
+        "fdf"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        let fd_result = state.last_load_resp.clone();
+        Box::pin(async_stream::stream! {
+            let items = match fd_result {
+                Some(resp) => resp.items,
+                None => vec![],
+            };
+            yield Ok(LoadResp::new_with_default_header(items))
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        let win = *win;
+        async move { preview(item, win).await }.boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    // 一覧が古くなるので cd したら fd に戻る
+                    if let Some(dir) = selected_dir(&item) {
+                        super::change_directory(config, dir).await?;
+                        super::spawn_change_mode(config, Fd::new().name(), "");
+                        return Ok(());
+                    }
+                    open_default(config, item).await
+                }),
+            ],
+            "ctrl-t" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let opts = OpenOpts::Neovim { tabedit: true };
+                    open(config, item, opts).await
+                })
+            ],
+            "alt-enter" => [
+                execute_silent_multi!(b, |_mode,config,state,_query,_item| {
+                    open_tabs(config, &state.selected_items).await
+                })
+            ],
+            "ctrl-y" => [
+                execute!(b, |_mode,_config,_state,_query,item| {
+                    xsel::yank(icons::strip(&item)).await?;
+                    Ok(())
+                })
+            ],
+            "esc" => [
+                b.change_mode(Fd::new().name(), false),
+            ],
+        }
+    }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        vec!["--multi"]
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Common
+////////////////////////////////////////////////////////////////////////////////
+
+async fn preview(item: String, win: PreviewWindow) -> Result<PreviewResp> {
+    let file = icons::strip(&item);
+    let message = if is_dir(file) {
+        super::dirs::list_dir(file).await?
+    } else if image::is_image(file) {
+        image::render_image(file, &win).await?
+    } else if let Some(summary) = bat::summary_if_unpreviewable(file).await? {
+        summary
+    } else {
+        bat::render_file_or_markdown(file, win.columns).await?
+    };
+    Ok(PreviewResp { message })
+}
+
+fn is_dir(path: &str) -> bool {
+    matches!(std::fs::metadata(path), Ok(meta) if meta.is_dir())
+}
+
+// ディレクトリなら (アイコンなどを除いた) パスを返す
+fn selected_dir(item: &str) -> Option<&str> {
+    let path = icons::strip(item);
+    is_dir(path).then_some(path)
+}
+
+async fn open_default(config: &Config, item: String) -> Result<()> {
+    let opts = if vscode::in_vscode() {
+        OpenOpts::VSCode
+    } else {
+        OpenOpts::Neovim { tabedit: false }
+    };
+    open(config, item, opts).await
 }
 
 enum OpenOpts {
     Neovim { tabedit: bool },
+    // fzf を閉じずに元のウィンドウで開く
+    OriginWindow,
     VSCode,
     Oil,
     Vifm,
     BrowseGithub,
     Xdragon,
+    FileManager,
+}
+
+async fn open_tabs(config: &Config, items: &[String]) -> Result<()> {
+    let targets = items
+        .iter()
+        .map(|item| {
+            (
+                icons::strip(item).to_string().into(),
+                nvim::OpenOpts::default(),
+            )
+        })
+        .collect();
+    config.nvim.open_tabs(targets).await
 }
 
 async fn open(config: &Config, file: String, opts: OpenOpts) -> Result<()> {
+    let file = icons::strip(&file).to_string();
     match opts {
         OpenOpts::Neovim { tabedit } => {
             let nvim = config.nvim.clone();
             let nvim_opts = nvim::OpenOpts {
                 line: None,
+                col: None,
                 tabedit,
+                window: None,
             };
             nvim.open(file.into(), nvim_opts).await?
         }
+        OpenOpts::OriginWindow => {
+            let nvim_opts = nvim::OpenOpts {
+                line: None,
+                col: None,
+                tabedit: false,
+                window: Some(config.nvim.origin_window().await?),
+            };
+            config.nvim.open(file.into(), nvim_opts).await?
+        }
         OpenOpts::VSCode => {
             let output = vscode::open(file, None).await?;
             config.nvim.notify_command_result("code", output).await?;
@@ -182,11 +497,7 @@ async fn open(config: &Config, file: String, opts: OpenOpts) -> Result<()> {
             Command::new("vifm").arg(&pwd).spawn()?.wait().await?;
         }
         OpenOpts::Oil => {
-            config.nvim.hide_floaterm().await?;
-            config
-                .nvim
-                .command(&format!("Oil --float {}", file))
-                .await?;
+            config.nvim.open_oil(parent_dir(&file)).await?;
         }
         OpenOpts::BrowseGithub => {
             gh::browse_github(file).await?;
@@ -194,6 +505,47 @@ async fn open(config: &Config, file: String, opts: OpenOpts) -> Result<()> {
         OpenOpts::Xdragon => {
             Command::new("xdragon").arg(&file).spawn()?.wait().await?;
         }
+        OpenOpts::FileManager => {
+            file_manager::reveal(&file)?;
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selected_dir_only_for_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub dir");
+        let file = dir.path().join("file.rs");
+        std::fs::create_dir(&sub).unwrap();
+        std::fs::write(&file, "").unwrap();
+        let sub = sub.to_str().unwrap();
+        let file = file.to_str().unwrap();
+
+        assert_eq!(selected_dir(sub), Some(sub));
+        assert_eq!(selected_dir(&format!("{}{sub}", pins::MARK)), Some(sub));
+        assert_eq!(selected_dir(file), None);
+        assert_eq!(selected_dir(&format!("{sub}/missing")), None);
+    }
+
+    #[test]
+    fn toggle_hidden_switches_both_filters() {
+        let fd = Fd::new();
+        let has = |fd: &Fd, arg: &str| fd.opts().args().contains(&arg);
+        assert!(has(&fd, "--hidden") && has(&fd, "--no-ignore"));
+
+        fd.toggle_hidden();
+        assert!(!has(&fd, "--hidden") && !has(&fd, "--no-ignore"));
+        assert!(!fd.fzf_prompt().contains("hidden"));
+
+        // 片方だけ有効なら両方有効にする
+        fd.opts.lock().unwrap().hidden = true;
+        fd.toggle_hidden();
+        assert!(has(&fd, "--hidden") && has(&fd, "--no-ignore"));
+        assert!(fd.fzf_prompt().contains("hidden,no-ignore"));
+    }
+}
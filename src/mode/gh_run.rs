@@ -0,0 +1,173 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::ansi;
+use crate::utils::command;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::item;
+
+#[derive(Clone)]
+pub struct GhRun;
+
+mod dto {
+    // gh run list --json databaseId,displayTitle,status,conclusion,headBranch
+    #[derive(serde::Deserialize, Debug)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct Run {
+        pub(crate) database_id: u64,
+        pub(crate) display_title: String,
+        pub(crate) status: String,
+        pub(crate) conclusion: String,
+        pub(crate) head_branch: String,
+    }
+}
+
+impl ModeDef for GhRun {
+    fn name(&self) -> &'static str {
+        "gh-run"
+    }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        item::FZF_OPTS.to_vec()
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let items = run_list().await?.iter().map(render).collect::<Vec<_>>();
+            if items.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no workflow runs"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview<'a>(
+        &self,
+        _config: &Config,
+        win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            let id = parse_id(&item)?;
+            let output = command::output_with_timeout(
+                "preview",
+                Command::new("gh").arg("run").arg("view").arg(&id),
+                command::preview_timeout(),
+            )
+            .await?;
+            let message = if output.status.success() {
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            } else {
+                String::from_utf8_lossy(&output.stderr).into_owned()
+            };
+            // job 名やエラーメッセージが長いことがあるので折り返す
+            let message = ansi::wrap_lines(&message, win.columns);
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    let id = parse_id(&item)?;
+                    Command::new("gh")
+                        .arg("run")
+                        .arg("view")
+                        .arg(&id)
+                        .arg("--web")
+                        .output()
+                        .await?;
+                    Ok(())
+                })
+            ],
+            "ctrl-l" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let id = parse_id(&item)?;
+                    let _ = config.nvim.hide_floaterm().await;
+                    config.nvim.command(&format!("FloatermNew gh run view {id} --log | less -R"))
+                        .await?;
+                    Ok(())
+                })
+            ],
+            "ctrl-r" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let id = parse_id(&item)?;
+                    let output = Command::new("gh")
+                        .arg("run")
+                        .arg("rerun")
+                        .arg(&id)
+                        .output()
+                        .await?;
+                    config.nvim.notify_command_result(format!("gh run rerun {id}"), output)
+                        .await
+                }),
+                b.reload(),
+            ],
+        }
+    }
+}
+
+async fn run_list() -> Result<Vec<dto::Run>> {
+    let output = Command::new("gh")
+        .arg("run")
+        .arg("list")
+        .arg("--json")
+        .arg("databaseId,displayTitle,status,conclusion,headBranch")
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(output.stderr.as_slice())
+        ));
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+// 完了したものは conclusion を、実行中のものは status を表示する
+fn render_status(run: &dto::Run) -> String {
+    use ansi_term::Colour::*;
+    match (run.status.as_str(), run.conclusion.as_str()) {
+        ("completed", "success") => Green.paint("success").to_string(),
+        ("completed", "failure") => Red.paint("failure").to_string(),
+        ("completed", conclusion) => Fixed(8).paint(conclusion).to_string(),
+        (status, _) => Yellow.paint(status).to_string(),
+    }
+}
+
+fn render(run: &dto::Run) -> String {
+    let display = format!(
+        "{}  {}  {}",
+        render_status(run),
+        ansi_term::Colour::Cyan.paint(&run.head_branch),
+        run.display_title,
+    );
+    item::render_item(display, run.database_id.to_string())
+}
+
+fn parse_id(item: &str) -> Result<String> {
+    let (_, id) = item::parse_item(item);
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(anyhow!("invalid item: {item}"));
+    }
+    Ok(id.to_string())
+}
@@ -1,3 +1,7 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
 use anyhow::anyhow;
 use anyhow::Result;
 use futures::future::BoxFuture;
@@ -19,7 +23,18 @@ use crate::utils::git;
 use crate::utils::xsel;
 
 #[derive(Clone)]
-pub struct GitBranch;
+pub struct GitBranch {
+    // プレビューを log の代わりに HEAD...branch の diff --stat にする
+    preview_diff: Arc<AtomicBool>,
+}
+
+impl GitBranch {
+    pub fn new() -> Self {
+        Self {
+            preview_diff: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
 
 impl ModeDef for GitBranch {
     fn name(&self) -> &'static str {
@@ -31,7 +46,7 @@ impl ModeDef for GitBranch {
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         Box::pin(async_stream::stream! {
             let head = git::head()?;
             let mut branches = git::local_branches()?;
@@ -39,12 +54,16 @@ impl ModeDef for GitBranch {
                 if a == &head {
                     std::cmp::Ordering::Less
                 } else if b == &head {
-                    return std::cmp::Ordering::Greater;
+                    std::cmp::Ordering::Greater
                 } else {
-                    return a.cmp(b);
+                    a.cmp(b)
                 }
             });
-            yield Ok(LoadResp::new_with_default_header(branches))
+            if branches.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no branches"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(branches))
+            }
         })
     }
     fn preview(
@@ -53,12 +72,7 @@ impl ModeDef for GitBranch {
         _win: &PreviewWindow,
         branch: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
-        async move {
-            let log = git::log_graph(branch).await?;
-            let message = log.join("\n");
-            Ok(PreviewResp { message })
-        }
-        .boxed()
+        async move { preview_log(branch).await }.boxed()
     }
     fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
         use config_builder::*;
@@ -84,6 +98,7 @@ impl ModeDef for GitBranch {
                     "repoint" => {
                         let commit = git::select_commit(format!("select commit to repoint {branch} to"))
                             .await?;
+                        let old_commit = git::rev_parse(&branch)?;
                         let _ = Command::new("git")
                             .arg("branch")
                             .arg("-D")
@@ -96,6 +111,12 @@ impl ModeDef for GitBranch {
                             .arg(commit.clone())
                             .output()
                             .await?;
+                        if output.status.success() {
+                            git::push_undo(git::UndoAction::ResetBranch {
+                                branch: branch.clone(),
+                                commit: old_commit,
+                            });
+                        }
                         config.nvim.notify_command_result(
                             format!("git branch {branch} {commit}"),
                             output,
@@ -111,6 +132,34 @@ impl ModeDef for GitBranch {
                 },
                 b.reload(),
             ],
+            "ctrl-d" => [
+                {
+                    let self_ = self.clone();
+                    b.toggle_option(move || {
+                        self_.preview_diff.fetch_xor(true, Ordering::SeqCst);
+                    })
+                },
+                {
+                    let self_ = self.clone();
+                    b.preview_with(move |_mode,_config,_win,branch,_index,_payload| {
+                        let preview_diff = self_.preview_diff.load(Ordering::SeqCst);
+                        async move {
+                            if preview_diff {
+                                let message = git::diff_stat_range("HEAD", branch).await?;
+                                Ok(PreviewResp { message })
+                            } else {
+                                preview_log(branch).await
+                            }
+                        }.boxed()
+                    })
+                },
+            ],
+            "ctrl-z" => [
+                execute!(b, |_mode,config,_state,_query,_branch| {
+                    super::undo_git_action(config).await
+                }),
+                b.reload(),
+            ],
             "ctrl-y" => [
                 execute!(b, |_mode,_config,_state,_query,branch| {
                     xsel::yank(branch).await?;
@@ -126,6 +175,12 @@ impl ModeDef for GitBranch {
     }
 }
 
+async fn preview_log(branch: String) -> Result<PreviewResp> {
+    let log = git::log_graph(branch).await?;
+    let message = log.join("\n");
+    Ok(PreviewResp { message })
+}
+
 async fn select_remote(local_branch: impl AsRef<str>) -> Result<String> {
     let upstream = git::upstream_of(&local_branch).ok();
     let mut branches = git::remote_branches()?;
@@ -133,9 +188,9 @@ async fn select_remote(local_branch: impl AsRef<str>) -> Result<String> {
         if Some(a) == upstream.as_ref() {
             std::cmp::Ordering::Less
         } else if Some(b) == upstream.as_ref() {
-            return std::cmp::Ordering::Greater;
+            std::cmp::Ordering::Greater
         } else {
-            return a.cmp(b);
+            a.cmp(b)
         }
     });
     let context = format!("pushing {} => ?", local_branch.as_ref());
@@ -158,12 +213,16 @@ async fn push_branch_to_remote(nvim: &Neovim, branch: String, force: bool) -> Re
 
 async fn delete_branch(nvim: &Neovim, branch: String, force: bool) -> Result<()> {
     let opt = if force { "-D" } else { "-d" };
+    let commit = git::rev_parse(&branch)?;
     let output = Command::new("git")
         .arg("branch")
         .arg(opt)
-        .arg(branch)
+        .arg(&branch)
         .output()
         .await?;
+    if output.status.success() {
+        git::push_undo(git::UndoAction::ResetBranch { branch, commit });
+    }
     nvim.notify_command_result(format!("git branch {opt}"), output)
         .await
 }
@@ -0,0 +1,188 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::command::edit_and_run;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// git のコマンドパレット
+#[derive(Clone)]
+pub struct GitCommand;
+
+// (command, help)
+const COMMON_COMMANDS: &[(&str, &str)] = &[
+    ("status", "Show the working tree status"),
+    (
+        "fetch --prune",
+        "Download objects and refs, removing stale remote-tracking refs",
+    ),
+    (
+        "pull --rebase",
+        "Fetch and rebase the current branch onto its upstream",
+    ),
+    ("push", "Update remote refs along with associated objects"),
+    (
+        "stash",
+        "Stash the changes in a dirty working directory away",
+    ),
+    (
+        "stash pop",
+        "Apply the latest stash and remove it from the stash list",
+    ),
+    (
+        "commit --amend --no-edit",
+        "Amend the last commit without editing its message",
+    ),
+    ("rebase -i", "Reapply commits interactively"),
+    (
+        "rebase --continue",
+        "Continue a rebase after resolving conflicts",
+    ),
+    ("rebase --abort", "Abort a rebase in progress"),
+    ("merge --abort", "Abort a merge in progress"),
+    (
+        "cherry-pick",
+        "Apply the changes introduced by some existing commits",
+    ),
+    (
+        "reset --soft HEAD~",
+        "Undo the last commit, keeping its changes staged",
+    ),
+    ("switch -c", "Create and switch to a new branch"),
+    ("clean -n", "Show untracked files that would be removed"),
+    (
+        "gc",
+        "Cleanup unnecessary files and optimize the local repository",
+    ),
+];
+
+const SEPARATOR: &str = "  ";
+
+enum Entry {
+    Common { command: String, help: String },
+    Alias { name: String, expansion: String },
+}
+
+impl Entry {
+    fn name(&self) -> &str {
+        match self {
+            Entry::Common { command, .. } => command,
+            Entry::Alias { name, .. } => name,
+        }
+    }
+    fn render(&self) -> String {
+        match self {
+            Entry::Common { command, help } => format!("{command}{SEPARATOR}{help}"),
+            Entry::Alias { name, expansion } => format!(
+                "{name}{SEPARATOR}{}",
+                ansi_term::Colour::Cyan.paint(format!("alias: {expansion}"))
+            ),
+        }
+    }
+    // `name  description` の name 部分
+    fn parse_name(item: &str) -> Result<String> {
+        let name = item
+            .split(SEPARATOR)
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or(anyhow!("invalid item: {item}"))?;
+        Ok(name.to_string())
+    }
+}
+
+impl ModeDef for GitCommand {
+    fn name(&self) -> &'static str {
+        "git-command"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let items = entries().await?.iter().map(Entry::render).collect();
+            yield Ok(LoadResp::new_with_default_header(items))
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        _win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        async move {
+            let name = Entry::parse_name(&item)?;
+            let message = match entries().await?.into_iter().find(|e| e.name() == name) {
+                Some(Entry::Common { command, help }) => format!("git {command}\n\n{help}"),
+                Some(Entry::Alias { name, expansion }) => {
+                    format!("git {name}\n\nalias.{name} = {expansion}")
+                }
+                None => "No description".to_string(),
+            };
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    // 引数を足せるように一度エディタで開く
+                    let name = Entry::parse_name(&item)?;
+                    let (cmd, output) = edit_and_run(format!("git {name} ")).await?;
+                    config.nvim.notify_command_result(&cmd, output).await
+                })
+            ],
+        }
+    }
+}
+
+async fn entries() -> Result<Vec<Entry>> {
+    let mut entries = COMMON_COMMANDS
+        .iter()
+        .map(|(command, help)| Entry::Common {
+            command: command.to_string(),
+            help: help.to_string(),
+        })
+        .collect::<Vec<_>>();
+    entries.extend(aliases().await?);
+    Ok(entries)
+}
+
+// git config --get-regexp ^alias\. => "alias.co checkout"
+async fn aliases() -> Result<Vec<Entry>> {
+    let output = Command::new("git")
+        .arg("config")
+        .arg("--get-regexp")
+        .arg(r"^alias\.")
+        .output()
+        .await?;
+    // alias が1つも無いと exit 1 になるのでエラーにはしない
+    let aliases = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (key, expansion) = line.split_once(' ').unwrap_or((line, ""));
+            let name = key.strip_prefix("alias.")?;
+            Some(Entry::Alias {
+                name: name.to_string(),
+                expansion: expansion.to_string(),
+            })
+        })
+        .collect();
+    Ok(aliases)
+}
@@ -29,9 +29,11 @@ use crate::nvim::Neovim;
 use crate::nvim::NeovimExt;
 use crate::state::State;
 use crate::utils::bat;
+use crate::utils::diff_pager;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
 use crate::utils::git;
+use crate::utils::trash;
 use crate::utils::vscode;
 use crate::utils::xsel;
 
@@ -78,12 +80,46 @@ impl GitDiff {
         let path = temp.path().to_str().unwrap().to_string();
         Ok((temp, path))
     }
+
+    /// 1-based position of the hunk among the hunks of the same file, and the number of them.
+    async fn hunk_position(&self, item: &Item) -> Option<(usize, usize)> {
+        let hunks = self.hunks.read().await;
+        let mut same_file = hunks
+            .keys()
+            .filter(|i| i.file() == item.file())
+            .collect::<Vec<_>>();
+        same_file.sort_by_key(|i| i.hunk_order());
+        let n = same_file.iter().position(|i| *i == item)?;
+        Some((n + 1, same_file.len()))
+    }
+
+    async fn push_discard_undo(&self, item: &Item, index: bool) -> Result<()> {
+        let hunk = self.hunk_of_item(item).await?;
+        git::push_undo(git::UndoAction::ReapplyPatch {
+            description: format!("discard a hunk of {}", hunk.new_file),
+            patch: hunk.patch,
+            index,
+        });
+        Ok(())
+    }
 }
 
 impl ModeDef for GitDiff {
     fn name(&self) -> &'static str {
         "git-diff"
     }
+    // ctrl-x などは読み込んだ hunk を引くので、別のインスタンスで動かすと壊れる
+    fn replayable(&self) -> bool {
+        false
+    }
+    fn fzf_prompt(&self) -> String {
+        // rebase 中は ctrl-alt-c/ctrl-alt-x が使えることを示す
+        if git::rebase_in_progress() {
+            format!("{}[rebasing]>", self.name())
+        } else {
+            format!("{}>", self.name())
+        }
+    }
     fn load<'a>(
         &'a self,
         _config: &Config,
@@ -98,26 +134,27 @@ impl ModeDef for GitDiff {
             let mut files = self.files.write().await;
             let mut hunks = self.hunks.write().await;
 
-            for hunk in git_diff()? {
-                let target_start = hunk.target_start;
+            let unstaged = git_diff()?.into_iter().map(|hunk| {
                 let item = Item::UnstagedHunk {
                     file: hunk.new_file.clone(),
-                    target_start,
+                    target_start: hunk.target_start,
                 };
-                files.insert(hunk.new_file.clone());
-                hunks.insert(item.clone(), hunk);
-                items.push(item.render());
-            }
-
-            for hunk in git_diff_cached()? {
-                let target_start = hunk.target_start;
+                (item, hunk)
+            });
+            let staged = git_diff_cached()?.into_iter().map(|hunk| {
                 let item = Item::StagedHunk {
                     file: hunk.new_file.clone(),
-                    target_start,
+                    target_start: hunk.target_start,
                 };
+                (item, hunk)
+            });
+            // 同じファイルの hunk が連続するように並べる (ctrl-n/ctrl-p で辿れるように)
+            let mut file_hunks = unstaged.chain(staged).collect::<Vec<_>>();
+            file_hunks.sort_by_key(|(item, _)| item.hunk_order());
+            for (item, hunk) in file_hunks {
                 files.insert(hunk.new_file.clone());
-                hunks.insert(item.clone(), hunk);
                 items.push(item.render());
+                hunks.insert(item, hunk);
             }
 
             git::workingtree_modified_files()?
@@ -152,26 +189,39 @@ impl ModeDef for GitDiff {
                 .map(|s| Item::ConflictedFile { file: s })
                 .for_each(|item| items.push(item.render()));
 
-            yield Ok(LoadResp::new_with_default_header(items))
+            let mut resp = LoadResp::new_with_default_header(items);
+            match diff_summary().await {
+                Ok(summary) => {
+                    resp.header = Some(format!("{} {summary}", resp.header.unwrap_or_default()));
+                }
+                Err(e) => warn!("git-diff: shortstat failed"; "error" => e.to_string()),
+            }
+            yield Ok(resp)
         })
     }
     fn preview<'a>(
         &'a self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &PreviewWindow,
         item: String,
     ) -> BoxFuture<'a, Result<PreviewResp>> {
+        let win = *win;
         async move {
             let item = Item::parse(&item)?;
             match item {
-                Item::StagedHunk { .. } => {
-                    let hunk = self.hunk_of_item(&item).await?;
-                    let message = hunk.colorize();
-                    Ok(PreviewResp { message })
-                }
-                Item::UnstagedHunk { .. } => {
+                Item::StagedHunk { .. } | Item::UnstagedHunk { .. } => {
                     let hunk = self.hunk_of_item(&item).await?;
-                    let message = hunk.colorize();
+                    let position = self.hunk_position(&item).await;
+                    let message = hunk.render(&win).await;
+                    let message = match position {
+                        Some((n, total)) if total > 1 => format!(
+                            "{}\n{message}",
+                            ansi_term::Style::new()
+                                .dimmed()
+                                .paint(format!("hunk {n}/{total}"))
+                        ),
+                        _ => message,
+                    };
                     Ok(PreviewResp { message })
                 }
                 Item::StagedBinayChange { .. } => {
@@ -211,12 +261,16 @@ impl ModeDef for GitDiff {
     fn execute<'a>(
         &'a self,
         config: &'a Config,
-        _state: &'a mut State,
+        state: &'a mut State,
         item: String,
         args: serde_json::Value,
     ) -> BoxFuture<'a, Result<()>> {
         async move {
-            match from_value(args)? {
+            let opts: ExecOpts = from_value(args)?;
+            // index だけを変える操作は、前後で index が変わっていれば undo を積む
+            let undo_description = opts.index_undo_description();
+            let index_before = undo_description.and_then(|_| git::snapshot_index().ok());
+            match opts {
                 ExecOpts::Open { tabedit } => {
                     let root = git::get_repo()?
                         .workdir()
@@ -231,7 +285,9 @@ impl ModeDef for GitDiff {
                             let file = format!("{root}/{file}");
                             let nvim_opts = nvim::OpenOpts {
                                 line: Some(target_start),
+                                col: None,
                                 tabedit,
+                                window: None,
                             };
                             if vscode::in_vscode() {
                                 vscode::open(file, None).await?;
@@ -243,7 +299,9 @@ impl ModeDef for GitDiff {
                             let file = format!("{root}/{file}");
                             let nvim_opts = nvim::OpenOpts {
                                 line: Some(target_start),
+                                col: None,
                                 tabedit,
+                                window: None,
                             };
                             if vscode::in_vscode() {
                                 vscode::open(file, None).await?;
@@ -270,7 +328,9 @@ impl ModeDef for GitDiff {
                             let file = format!("{root}/{file}");
                             let nvim_opts = nvim::OpenOpts {
                                 line: None,
+                                col: None,
                                 tabedit,
+                                window: None,
                             };
                             if vscode::in_vscode() {
                                 vscode::open(file, None).await?;
@@ -282,7 +342,9 @@ impl ModeDef for GitDiff {
                             let file = format!("{root}/{file}");
                             let nvim_opts = nvim::OpenOpts {
                                 line: None,
+                                col: None,
                                 tabedit,
+                                window: None,
                             };
                             if vscode::in_vscode() {
                                 vscode::open(file, None).await?;
@@ -359,27 +421,42 @@ impl ModeDef for GitDiff {
                     }
                 }
                 ExecOpts::StageFile => {
-                    let item = Item::parse(&item)?;
-                    let file = item.file();
-                    git_stage_file(&config.nvim, file).await?;
+                    let files = selected_files(state)?;
+                    if files.len() > 1 {
+                        git_stage_files(&config.nvim, files).await?;
+                    } else {
+                        let item = Item::parse(&item)?;
+                        let file = item.file();
+                        git_stage_file(&config.nvim, file).await?;
+                    }
                 }
                 ExecOpts::UnstageFile => {
-                    let item = Item::parse(&item)?;
-                    let file = item.file();
-                    git_unstage_file(&config.nvim, file).await?;
+                    let files = selected_files(state)?;
+                    if files.len() > 1 {
+                        git_unstage_files(&config.nvim, files).await?;
+                    } else {
+                        let item = Item::parse(&item)?;
+                        let file = item.file();
+                        git_unstage_file(&config.nvim, file).await?;
+                    }
                 }
                 ExecOpts::Discard => {
                     let item = Item::parse(&item)?;
                     match item {
                         Item::StagedHunk { .. } => {
                             let (_temp, patch) = self.save_patch_to_temp(&item).await?;
-                            git_apply(&config.nvim, patch, vec!["--reverse", "--index"]).await?;
+                            if git_apply(&config.nvim, patch, vec!["--reverse", "--index"]).await? {
+                                self.push_discard_undo(&item, true).await?;
+                            }
                         }
                         Item::UnstagedHunk { .. } => {
                             let (_temp, patch) = self.save_patch_to_temp(&item).await?;
-                            git_apply(&config.nvim, patch, vec!["--reverse"]).await?;
+                            if git_apply(&config.nvim, patch, vec!["--reverse"]).await? {
+                                self.push_discard_undo(&item, false).await?;
+                            }
                         }
                         Item::StagedBinayChange { .. } => {
+                            // restore は元に戻せないので undo は積まない
                             git_restore_file(&config.nvim, item.file(), Some("HEAD")).await?;
                         }
                         Item::UnstagedBinayChange { .. } => {
@@ -402,6 +479,68 @@ impl ModeDef for GitDiff {
                         }
                     }
                 }
+                ExecOpts::StageLines => {
+                    let item = Item::parse(&item)?;
+                    let Item::UnstagedHunk { .. } = item else {
+                        // 行単位で stage できるのは unstaged hunk だけ
+                        return Ok(());
+                    };
+                    let hunk = self.hunk_of_item(&item).await?;
+                    let selected = select_hunk_lines(&hunk).await?;
+                    if selected.is_empty() {
+                        return Ok(());
+                    }
+                    let patch = patch_with_lines(&hunk.patch, &selected)?;
+                    let mut temp = NamedTempFile::new()?;
+                    temp.write_all(&patch)?;
+                    let path = temp.path().to_str().unwrap().to_string();
+                    git_apply(&config.nvim, path, vec!["--cached"]).await?;
+                }
+                ExecOpts::StageAll => {
+                    let output = git::stage_all().await?;
+                    notify_all_result(&config.nvim, "staged", output).await?;
+                }
+                ExecOpts::UnstageAll => {
+                    // index の変更は working tree に残るので確認はしない
+                    let output = git::unstage_all().await?;
+                    notify_all_result(&config.nvim, "unstaged", output).await?;
+                }
+                ExecOpts::DeleteUntracked => {
+                    let item = Item::parse(&item)?;
+                    match item {
+                        Item::UntrackedFile { ref file } => {
+                            if !confirm_delete(file).await? {
+                                return Ok(());
+                            }
+                            delete_file(&config.nvim, file).await?;
+                        }
+                        Item::AddedBinaryFile { ref file } => {
+                            // 断られたら index も触らない
+                            if !confirm_delete(file).await? {
+                                return Ok(());
+                            }
+                            // index から外してから消す
+                            git_unstage_file(&config.nvim, file).await?;
+                            delete_file(&config.nvim, file).await?;
+                        }
+                        _ => {
+                            // tracked file は discard を使う
+                        }
+                    }
+                }
+                ExecOpts::Amend { no_edit } => {
+                    if git::is_head_pushed().unwrap_or(false) {
+                        let header = "HEAD is already pushed. amend anyway?";
+                        if !fzf::confirm(header).await? {
+                            return Ok(());
+                        }
+                    }
+                    let output = git::amend(no_edit).await?;
+                    config
+                        .nvim
+                        .notify_command_result("git commit --amend", output)
+                        .await?;
+                }
                 ExecOpts::Commit => {
                     Command::new("git")
                         .arg("commit")
@@ -465,6 +604,16 @@ impl ModeDef for GitDiff {
                         .spawn()?
                         .wait()
                         .await?;
+                    // lazygit で何をされたか分からないので、古い hunk は捨てる
+                    self.clear().await;
+                }
+            }
+            if let (Some(description), Some(tree)) = (undo_description, index_before) {
+                if git::snapshot_index().ok().as_ref() != Some(&tree) {
+                    git::push_undo(git::UndoAction::RestoreIndex {
+                        description: description.to_string(),
+                        tree,
+                    });
                 }
             }
             Ok(())
@@ -502,19 +651,40 @@ impl ModeDef for GitDiff {
                 b.reload()
             ],
             "alt-s" => [
-                execute_silent!(b, |mode,config,state,_query,item| {
+                execute_silent_multi!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::StageFile.value();
                     mode.execute(config, state, item, opts).await
                 }),
                 b.reload()
             ],
             "alt-u" => [
-                execute_silent!(b, |mode,config,state,_query,item| {
+                execute_silent_multi!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::UnstageFile.value();
                     mode.execute(config, state, item, opts).await
                 }),
                 b.reload()
             ],
+            "alt-l" => [
+                execute!(b, |mode,config,state,_query,item| {
+                    let opts = ExecOpts::StageLines.value();
+                    mode.execute(config, state, item, opts).await
+                }),
+                b.reload()
+            ],
+            "ctrl-alt-s" => [
+                execute_silent!(b, |mode,config,state,_query,item| {
+                    let opts = ExecOpts::StageAll.value();
+                    mode.execute(config, state, item, opts).await
+                }),
+                b.reload()
+            ],
+            "ctrl-alt-u" => [
+                execute_silent!(b, |mode,config,state,_query,item| {
+                    let opts = ExecOpts::UnstageAll.value();
+                    mode.execute(config, state, item, opts).await
+                }),
+                b.reload()
+            ],
             "ctrl-x" => [
                 execute_silent!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::Discard.value();
@@ -522,6 +692,13 @@ impl ModeDef for GitDiff {
                 }),
                 b.reload()
             ],
+            "ctrl-delete" => [
+                execute!(b, |mode,config,state,_query,item| {
+                    let opts = ExecOpts::DeleteUntracked.value();
+                    mode.execute(config, state, item, opts).await
+                }),
+                b.reload()
+            ],
             "ctrl-y" => [
                 execute_silent!(b, |_mode,_config,_state,_query,item| {
                     let item = Item::parse(&item)?;
@@ -530,26 +707,75 @@ impl ModeDef for GitDiff {
                 }),
                 b.reload()
             ],
+            "ctrl-z" => [
+                execute_without_item!(b, |_mode,config,_state,_query,_item| {
+                    super::undo_git_action(config).await
+                }),
+                b.reload()
+            ],
             "ctrl-a" => [
-                execute_silent!(b, |mode,config,state,_query,item| {
+                execute_silent_refreshing!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::Commit.value();
                     mode.execute(config, state, item, opts).await
                 }),
-                b.reload()
+                b.refresh()
+            ],
+            "ctrl-alt-a" => [
+                execute_refreshing!(b, |mode,config,state,_query,item| {
+                    let opts = ExecOpts::Amend { no_edit: true }.value();
+                    mode.execute(config, state, item, opts).await
+                }),
+                b.refresh()
             ],
             "ctrl-v" => [
-                execute!(b, |mode,config,state,_query,item| {
+                execute_refreshing!(b, |mode,config,state,_query,item| {
                     let opts = ExecOpts::LazyGit.value();
                     mode.execute(config, state, item, opts).await
                 }),
-                b.reload()
+                b.refresh()
+            ],
+            "ctrl-n" => [
+                b.transform(|_mode,_config,state,query,_item,index| {
+                    async move { Ok(jump_to_hunk(state, &query, index, Direction::Next)) }.boxed()
+                })
+            ],
+            "ctrl-p" => [
+                b.transform(|_mode,_config,state,query,_item,index| {
+                    async move { Ok(jump_to_hunk(state, &query, index, Direction::Prev)) }.boxed()
+                })
+            ],
+            "ctrl-alt-c" => [
+                execute_silent_without_item!(b, |_mode,config,_state,_query,_item| {
+                    if !git::rebase_in_progress() {
+                        return config.nvim.notify_warn("no rebase in progress").await;
+                    }
+                    let output = git::rebase_continue().await?;
+                    config.nvim.notify_command_result("git rebase --continue", output).await
+                }),
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "ctrl-alt-x" => [
+                execute_silent_without_item!(b, |_mode,config,_state,_query,_item| {
+                    if !git::rebase_in_progress() {
+                        return config.nvim.notify_warn("no rebase in progress").await;
+                    }
+                    let output = git::rebase_abort().await?;
+                    config.nvim.notify_command_result("git rebase --abort", output).await
+                }),
+                b.reload(),
+                b.refresh_prompt(),
             ],
             "pgup" => [
-                select_and_execute!{b, |mode,config,state,_query,item|
+                select_and_execute_refreshing!{b, |mode,config,state,_query,item|
                     "commit" => {
                         let opts = ExecOpts::Commit.value();
                         mode.execute(config, state, item, opts).await
                     },
+                    "commit(amend)" => {
+                        let opts = ExecOpts::Amend { no_edit: false }.value();
+                        mode.execute(config, state, item, opts).await
+                    },
                     "commit(fixup)" => {
                         let opts = ExecOpts::CommitFixup.value();
                         mode.execute(config, state, item, opts).await
@@ -559,6 +785,8 @@ impl ModeDef for GitDiff {
                         mode.execute(config, state, item, opts).await
                     },
                 }
+            ,
+                b.refresh(),
             ]
         }
     }
@@ -573,8 +801,13 @@ enum ExecOpts {
     StageFile,
     Unstage,
     UnstageFile,
+    StageLines,
+    StageAll,
+    UnstageAll,
     Discard,
+    DeleteUntracked,
     Commit,
+    Amend { no_edit: bool },
     CommitFixup,
     CommitInstantFixup,
     Open { tabedit: bool },
@@ -585,6 +818,20 @@ impl ExecOpts {
     fn value(&self) -> serde_json::Value {
         to_value(self).unwrap()
     }
+
+    // index だけを変更する操作なら undo 用の説明を返す
+    fn index_undo_description(&self) -> Option<&'static str> {
+        match self {
+            ExecOpts::Stage => Some("stage"),
+            ExecOpts::StageLines => Some("stage lines"),
+            ExecOpts::StageFile => Some("stage files"),
+            ExecOpts::StageAll => Some("stage all"),
+            ExecOpts::Unstage => Some("unstage"),
+            ExecOpts::UnstageFile => Some("unstage files"),
+            ExecOpts::UnstageAll => Some("unstage all"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -604,6 +851,19 @@ enum Item {
 }
 
 impl Item {
+    // hunk の並び順。ファイルごとにまとめ、その中では行順 (同じ行なら unstaged が先)
+    fn hunk_order(&self) -> Option<(String, usize, bool)> {
+        match self {
+            Item::UnstagedHunk { file, target_start } => Some((file.clone(), *target_start, false)),
+            Item::StagedHunk { file, target_start } => Some((file.clone(), *target_start, true)),
+            _ => None,
+        }
+    }
+
+    fn is_hunk(&self) -> bool {
+        self.hunk_order().is_some()
+    }
+
     fn file(&self) -> &str {
         match self {
             Item::StagedHunk { file, .. } => file,
@@ -702,6 +962,80 @@ impl Item {
     }
 }
 
+// ヘッダーに出す変更の概要。例: "unstaged: 3 files +10 -2, staged: 1 file +5 -0"
+async fn diff_summary() -> Result<String> {
+    let unstaged = git::shortstat(false).await?;
+    let staged = git::shortstat(true).await?;
+    let summary = [("unstaged", unstaged), ("staged", staged)]
+        .into_iter()
+        .filter(|(_, stat)| !stat.is_empty())
+        .map(|(label, stat)| format!("{label}: {}", stat.render()))
+        .collect::<Vec<_>>();
+    if summary.is_empty() {
+        Ok("no changes".to_string())
+    } else {
+        Ok(summary.join(", "))
+    }
+}
+
+enum Direction {
+    Next,
+    Prev,
+}
+
+// 同じファイルの次 (前) の hunk へ移動する fzf の action を返す。端まで行ったら反対側に戻る。
+// fzf の pos は絞り込み後の位置なので、クエリがあるときは単に上下に動かす
+fn jump_to_hunk(state: &State, query: &str, index: Option<usize>, direction: Direction) -> String {
+    let fallback = match direction {
+        Direction::Next => "down",
+        Direction::Prev => "up",
+    };
+    let (Some(index), Some(resp)) = (index, state.last_load_resp.as_ref()) else {
+        return fallback.to_string();
+    };
+    if !query.is_empty() {
+        return fallback.to_string();
+    }
+    let items = resp
+        .items
+        .iter()
+        .map(|i| Item::parse(i).ok())
+        .collect::<Vec<_>>();
+    let Some(current) = items.get(index).cloned().flatten().filter(Item::is_hunk) else {
+        return fallback.to_string();
+    };
+    let same_file = items
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| {
+            i.as_ref()
+                .is_some_and(|i| i.is_hunk() && i.file() == current.file())
+        })
+        .map(|(n, _)| n)
+        .collect::<Vec<_>>();
+    let Some(k) = same_file.iter().position(|n| *n == index) else {
+        return fallback.to_string();
+    };
+    let target = match direction {
+        Direction::Next => same_file[(k + 1) % same_file.len()],
+        Direction::Prev => same_file[(k + same_file.len() - 1) % same_file.len()],
+    };
+    // pos は 1-based
+    format!("pos({})", target + 1)
+}
+
+impl Hunk {
+    // FZFW_DIFF_PAGER があればそれを使い、無ければ colorize
+    async fn render(&self, win: &PreviewWindow) -> String {
+        if let Some(patch) = display_bytes(&self.patch) {
+            if let Some(rendered) = diff_pager::render(&patch, win).await {
+                return rendered;
+            }
+        }
+        self.colorize()
+    }
+}
+
 trait HunkExt {
     fn colorize(&self) -> String;
 }
@@ -811,12 +1145,175 @@ fn parse_diff(diff: Diff) -> Result<Vec<Hunk>> {
     Ok(hunks)
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// Line-level staging
+////////////////////////////////////////////////////////////////////////////////
+
+// hunk の +/- 行を選ばせて、選ばれた行の (本文中の) 番号を返す
+async fn select_hunk_lines(hunk: &Hunk) -> Result<HashSet<usize>> {
+    let items = hunk_body(&hunk.patch)?
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| matches!(line.first(), Some(b'+' | b'-')))
+        .map(|(i, line)| {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end_matches('\n');
+            let line = if line.starts_with('+') {
+                ansi_term::Colour::Green.paint(line).to_string()
+            } else {
+                ansi_term::Colour::Red.paint(line).to_string()
+            };
+            format!("{i:>4} {line}")
+        })
+        .collect::<Vec<_>>();
+    let header = format!("select lines of {} to stage (tab to select)", hunk.new_file);
+    let selected = fzf::select_multi_with_header(header, items)
+        .await?
+        .iter()
+        .filter_map(|s| s.split_whitespace().next()?.parse::<usize>().ok())
+        .collect();
+    Ok(selected)
+}
+
+// "@@ -a,b +c,d @@ ..." の a, c と残りの部分
+fn parse_hunk_header(header: &str) -> Option<(usize, usize, &str)> {
+    let rest = header.strip_prefix("@@ -")?;
+    let (old, rest) = rest.split_once(" +")?;
+    let (new, section) = rest.split_once(" @@")?;
+    let start = |range: &str| range.split(',').next()?.parse::<usize>().ok();
+    Some((start(old)?, start(new)?, section))
+}
+
+// patch の "---", "+++", "@@" 以降の本文の行 (改行込み)
+fn hunk_body(patch: &[u8]) -> Result<Vec<&[u8]>> {
+    let lines = patch.split_inclusive(|b| *b == b'\n').collect::<Vec<_>>();
+    if lines.len() < 3 || !lines[2].starts_with(b"@@ ") {
+        return Err(anyhow!("invalid patch"));
+    }
+    Ok(lines[3..].to_vec())
+}
+
+/// Builds a patch containing only the selected `+`/`-` lines of a single-hunk patch,
+/// to be applied with `git apply --cached`.
+/// Unselected `-` lines become context and unselected `+` lines are dropped,
+/// and the hunk header is recomputed accordingly.
+fn patch_with_lines(patch: &[u8], selected: &HashSet<usize>) -> Result<Vec<u8>> {
+    let lines = patch.split_inclusive(|b| *b == b'\n').collect::<Vec<_>>();
+    let body = hunk_body(patch)?;
+    let header = String::from_utf8_lossy(lines[2]);
+    let (old_start, new_start, section) =
+        parse_hunk_header(header.trim_end()).ok_or(anyhow!("invalid hunk header: {header}"))?;
+
+    let mut new_body: Vec<u8> = vec![];
+    let (mut old_count, mut new_count) = (0, 0);
+    // 直前の行を残したかどうか ("\ No newline at end of file" 用)
+    let mut kept_prev = true;
+    for (i, line) in body.iter().enumerate() {
+        match line.first() {
+            Some(b' ') => {
+                new_body.extend_from_slice(line);
+                old_count += 1;
+                new_count += 1;
+                kept_prev = true;
+            }
+            Some(b'-') if selected.contains(&i) => {
+                new_body.extend_from_slice(line);
+                old_count += 1;
+                kept_prev = true;
+            }
+            Some(b'-') => {
+                new_body.push(b' ');
+                new_body.extend_from_slice(&line[1..]);
+                old_count += 1;
+                new_count += 1;
+                kept_prev = true;
+            }
+            Some(b'+') if selected.contains(&i) => {
+                new_body.extend_from_slice(line);
+                new_count += 1;
+                kept_prev = true;
+            }
+            Some(b'+') => {
+                kept_prev = false;
+            }
+            _ => {
+                if kept_prev {
+                    new_body.extend_from_slice(line);
+                }
+            }
+        }
+    }
+
+    let mut result = vec![];
+    result.extend_from_slice(lines[0]);
+    result.extend_from_slice(lines[1]);
+    result.extend_from_slice(
+        format!("@@ -{old_start},{old_count} +{new_start},{new_count} @@{section}\n").as_bytes(),
+    );
+    result.extend_from_slice(&new_body);
+    Ok(result)
+}
+
 async fn git_stage_file(nvim: &Neovim, file: impl AsRef<str>) -> Result<()> {
     let output = git::stage_file(file).await?;
     nvim.notify_command_result_if_error("git_stage_file", output)
         .await
 }
 
+// 複数選択時は1回の git コマンドにまとめ、通知も1回だけ出す
+fn selected_files(state: &State) -> Result<Vec<String>> {
+    let mut files = vec![];
+    for item in &state.selected_items {
+        let file = Item::parse(item)?.file().to_string();
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+async fn git_stage_files(nvim: &Neovim, files: Vec<String>) -> Result<()> {
+    let output = git::stage_files(&files).await?;
+    notify_files_result(nvim, "staged", files, output).await
+}
+
+async fn git_unstage_files(nvim: &Neovim, files: Vec<String>) -> Result<()> {
+    let output = git::unstage_files(&files).await?;
+    notify_files_result(nvim, "unstaged", files, output).await
+}
+
+async fn notify_files_result(
+    nvim: &Neovim,
+    action: &str,
+    files: Vec<String>,
+    output: std::process::Output,
+) -> Result<()> {
+    if output.status.success() {
+        nvim.notify_info(format!(
+            "{action} {} files\n{}",
+            files.len(),
+            files.join("\n")
+        ))
+        .await
+    } else {
+        nvim.notify_command_result_if_error(format!("git ({action})"), output)
+            .await
+    }
+}
+
+async fn notify_all_result(
+    nvim: &Neovim,
+    action: &str,
+    output: std::process::Output,
+) -> Result<()> {
+    if output.status.success() {
+        nvim.notify_info(format!("{action} all changes")).await
+    } else {
+        nvim.notify_command_result_if_error(format!("git ({action})"), output)
+            .await
+    }
+}
+
 async fn git_unstage_file(nvim: &Neovim, file: impl AsRef<str>) -> Result<()> {
     let output = git::unstage_file(file).await?;
     nvim.notify_command_result_if_error("git_unstage_file", output)
@@ -833,8 +1330,83 @@ async fn git_restore_file(
         .await
 }
 
-async fn git_apply(nvim: &Neovim, patch: String, args: Vec<&str>) -> Result<()> {
+async fn confirm_delete(file: &str) -> Result<bool> {
+    fzf::confirm(format!("delete {file}?")).await
+}
+
+// 確認は confirm_delete で済ませておく
+async fn delete_file(nvim: &Neovim, file: &str) -> Result<()> {
+    let path = format!("{}/{file}", git::workdir()?);
+    match trash::remove(path).await {
+        Ok(()) => nvim.notify_info(format!("deleted {file}")).await,
+        Err(e) => {
+            nvim.notify_error(format!("failed to delete {file}: {e}"))
+                .await
+        }
+    }
+}
+
+async fn git_apply(nvim: &Neovim, patch: String, args: Vec<&str>) -> Result<bool> {
     let output = git::apply(patch, args).await?;
+    let success = output.status.success();
     nvim.notify_command_result_if_error("git apply", output)
-        .await
+        .await?;
+    Ok(success)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(patch: &str, selected: &[usize]) -> String {
+        let selected = selected.iter().copied().collect();
+        String::from_utf8(patch_with_lines(patch.as_bytes(), &selected).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_hunk_header_keeps_section() {
+        assert_eq!(
+            parse_hunk_header("@@ -12,3 +14,5 @@ fn main() {"),
+            Some((12, 14, " fn main() {"))
+        );
+        assert_eq!(parse_hunk_header("@@ -1 +1 @@"), Some((1, 1, "")));
+        assert_eq!(parse_hunk_header("diff --git a/f b/f"), None);
+    }
+
+    #[test]
+    fn partial_add_drops_unselected_lines() {
+        let patch = "--- a/f\n+++ b/f\n@@ -1,2 +1,5 @@ fn f\n a\n+x\n+y\n+z\n b\n";
+        assert_eq!(
+            partial(patch, &[2]),
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,3 @@ fn f\n a\n+y\n b\n"
+        );
+    }
+
+    #[test]
+    fn partial_delete_keeps_unselected_lines_as_context() {
+        let patch = "--- a/f\n+++ b/f\n@@ -3,4 +3,1 @@\n a\n-x\n-y\n-z\n";
+        assert_eq!(
+            partial(patch, &[1]),
+            "--- a/f\n+++ b/f\n@@ -3,4 +3,3 @@\n a\n-x\n y\n z\n"
+        );
+    }
+
+    #[test]
+    fn partial_change_counts_both_sides() {
+        let patch = "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n";
+        assert_eq!(
+            partial(patch, &[2]),
+            "--- a/f\n+++ b/f\n@@ -1,3 +1,4 @@\n a\n b\n+B\n c\n"
+        );
+        assert_eq!(partial(patch, &[1, 2]), patch);
+    }
+
+    #[test]
+    fn no_newline_marker_follows_its_line() {
+        let patch = "--- a/f\n+++ b/f\n@@ -1,1 +1,2 @@\n a\n+b\n\\ No newline at end of file\n";
+        assert_eq!(
+            partial(patch, &[]),
+            "--- a/f\n+++ b/f\n@@ -1,1 +1,1 @@\n a\n"
+        );
+    }
 }
@@ -1,3 +1,7 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
 use anyhow::anyhow;
 use anyhow::Result;
 use futures::future::BoxFuture;
@@ -13,22 +17,70 @@ use crate::mode::ModeDef;
 use crate::nvim::Neovim;
 use crate::nvim::NeovimExt;
 use crate::state::State;
+use crate::utils::diff_pager;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::gh;
 use crate::utils::git;
+use crate::utils::path::to_relpath;
 use crate::utils::xsel;
 
 #[derive(Clone)]
-pub enum GitLog {
+pub struct GitLog {
+    target: Target,
+    // preview をコミット全体 (false) にするか patch だけ (true) にするか
+    preview_patch: Arc<AtomicBool>,
+}
+
+#[derive(Clone)]
+enum Target {
     Head,
     All,
+    /// HEAD のうち、change-mode の `--arg` で渡されたファイルに触れるコミットだけ
+    Path(Option<String>),
+}
+
+impl GitLog {
+    pub fn head() -> Self {
+        Self::new(Target::Head)
+    }
+    pub fn all() -> Self {
+        Self::new(Target::All)
+    }
+    pub fn path() -> Self {
+        Self::new(Target::Path(None))
+    }
+    fn new(target: Target) -> Self {
+        Self {
+            target,
+            preview_patch: Arc::new(AtomicBool::new(false)),
+        }
+    }
 }
 
 impl ModeDef for GitLog {
     fn name(&self) -> &'static str {
-        match self {
-            GitLog::Head => "git-log",
-            GitLog::All => "git-log(all)",
+        match self.target {
+            Target::Head => "git-log",
+            Target::All => "git-log(all)",
+            Target::Path(_) => "git-log(path)",
+        }
+    }
+    fn fzf_prompt(&self) -> String {
+        match &self.target {
+            Target::Path(Some(path)) => format!("{}[{}]>", self.name(), to_relpath(path)),
+            _ => format!("{}>", self.name()),
+        }
+    }
+    fn set_arg(&mut self, arg: String) {
+        if let Target::Path(path) = &mut self.target {
+            *path = Some(arg);
+        }
+    }
+    fn arg(&self) -> Option<String> {
+        match &self.target {
+            Target::Path(path) => path.clone(),
+            _ => None,
         }
     }
     fn load<'a>(
@@ -39,9 +91,13 @@ impl ModeDef for GitLog {
         _item: String,
     ) -> super::LoadStream<'a> {
         Box::pin(async_stream::stream! {
-            let mut commits = match self {
-                GitLog::Head => git::log_graph("HEAD").await?,
-                GitLog::All => git::log_graph("--all").await?,
+            let mut commits = match &self.target {
+                Target::Head => git::log_graph("HEAD").await?,
+                Target::All => git::log_graph("--all").await?,
+                Target::Path(path) => match path {
+                    Some(path) => git::log_graph_for_path("HEAD", path).await?,
+                    None => git::log_graph("HEAD").await?,
+                },
             };
             // reset color to white
             commits.push(ansi_term::Colour::White.normal().paint("").to_string());
@@ -51,20 +107,33 @@ impl ModeDef for GitLog {
     fn preview(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &PreviewWindow,
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
-        async move {
-            let commit = git::parse_short_commit(&item)?;
-            let message = git::show_commit(commit).await?;
-            Ok(PreviewResp { message })
+        if self.preview_patch.load(Ordering::SeqCst) {
+            preview_patch(item, *win)
+        } else {
+            preview_full(item)
         }
-        .boxed()
     }
     fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
         use config_builder::*;
         bindings! {
             b <= default_bindings(),
+            "alt-p" => [
+                {
+                    // full と patch の preview を切り替える
+                    let full = b.preview_with(|_mode,_config,_win,item,_index,_payload| preview_full(item));
+                    let patch = b.preview_with(|_mode,_config,win,item,_index,_payload| preview_patch(item, *win));
+                    let preview_patch = self.preview_patch.clone();
+                    b.transform(move |_mode,config,_state,_query,_item,_index| {
+                        let patch_now = !preview_patch.fetch_xor(true, Ordering::SeqCst);
+                        let action = if patch_now { &patch } else { &full };
+                        let action = action.render(&config.myself);
+                        async move { Ok(action) }.boxed()
+                    })
+                }
+            ],
             "ctrl-l" => [
                 execute_silent!{b, |_mode,config,_state,_query,item| {
                     let query = match branches_of(&item)? {
@@ -82,25 +151,16 @@ impl ModeDef for GitLog {
                         }
                     };
 
-                    // TODO ad-hoc なので何か考えたい
-                    // fzf 0.45 で追加された transform を使うのが良さそう
-                    let myself = config.myself.clone();
-                    let socket = config.socket.clone();
-                    tokio::spawn(async move {
-                        let _ = Command::new(myself)
-                            .arg("change-mode")
-                            .arg("git-branch")
-                            .arg(query)
-                            .env("FZFW_SOCKET", socket)
-                            .stdout(std::process::Stdio::null())
-                            .stderr(std::process::Stdio::null())
-                            .output()
-                            .await
-                            .map_err(|e| e.to_string());
-                    });
+                    super::spawn_change_mode(config, "git-branch", query);
                     Ok(())
                 }}
             ],
+            "ctrl-z" => [
+                execute_without_item!(b, |_mode,config,_state,_query,_item| {
+                    super::undo_git_action(config).await
+                }),
+                b.reload(),
+            ],
             "ctrl-y" => [
                 execute_silent!{b, |_mode,_config,_state,_query,item| {
                     let commit = git::parse_short_commit(&item)?;
@@ -108,6 +168,40 @@ impl ModeDef for GitLog {
                     Ok(())
                 }}
             ],
+            "ctrl-w" => [
+                execute_silent!{b, |_mode,config,_state,_query,item| {
+                    let commit = git::parse_short_commit(&item)?;
+                    if let Err(e) = gh::browse_commit(commit).await {
+                        config.nvim.notify_error(e.to_string()).await?;
+                    }
+                    Ok(())
+                }}
+            ],
+            "ctrl-alt-y" => [
+                execute_silent!{b, |_mode,_config,_state,_query,item| {
+                    let commit = git::parse_short_commit(&item)?;
+                    let message = git::commit_message(commit).await?;
+                    xsel::yank(message).await?;
+                    Ok(())
+                }}
+            ],
+            "ctrl-alt-d" => [
+                execute_silent_multi!(b, |_mode,config,state,_query,_item| {
+                    diff_selected(&config.nvim, &state.selected_items).await
+                }),
+            ],
+            "ctrl-alt-f" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    fixup(&config.nvim, &item, false).await
+                }),
+                b.reload(),
+            ],
+            "alt-f" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    fixup(&config.nvim, &item, true).await
+                }),
+                b.reload(),
+            ],
             "enter" => [
                 select_and_execute!{b, |_mode,config,_state,_query,item|
                     "diffview" => {
@@ -179,14 +273,23 @@ impl ModeDef for GitLog {
                         config.nvim.notify_command_result("git revert", output)
                             .await
                     },
+                    "cherry-pick" => {
+                        cherry_pick(&config.nvim, &item, false).await
+                    },
+                    "cherry-pick --no-commit" => {
+                        cherry_pick(&config.nvim, &item, true).await
+                    },
                     "new branch" => {
                         let branch = fzf::input("Enter branch name").await?;
                         let output = Command::new("git")
                             .arg("branch")
-                            .arg(branch)
+                            .arg(&branch)
                             .arg(git::parse_short_commit(&item)?)
                             .output()
                             .await?;
+                        if output.status.success() {
+                            git::push_undo(git::UndoAction::DeleteBranch { branch });
+                        }
                         config.nvim.notify_command_result("git branch", output)
                             .await
                     },
@@ -206,8 +309,11 @@ impl ModeDef for GitLog {
             ],
         }
     }
+    fn fzf_sort(&self) -> Option<bool> {
+        Some(false)
+    }
     fn fzf_extra_opts(&self) -> Vec<&str> {
-        vec!["--no-sort"]
+        vec!["--multi"]
     }
 }
 
@@ -235,6 +341,119 @@ async fn push_to_remote(nvim: &Neovim, item: &String, force: bool) -> Result<()>
     nvim.notify_command_result("git push", output).await
 }
 
+async fn cherry_pick(nvim: &Neovim, item: &str, no_commit: bool) -> Result<()> {
+    let commit = git::parse_short_commit(item)?;
+    let output = Command::new("git")
+        .arg("cherry-pick")
+        .args(if no_commit {
+            vec!["--no-commit"]
+        } else {
+            vec![]
+        })
+        .arg(&commit)
+        .output()
+        .await?;
+    if !output.status.success() && !git::conflicted_files()?.is_empty() {
+        let git_diff = super::git_diff::GitDiff::new();
+        return nvim
+            .notify_warn(format!(
+                "git cherry-pick {commit}: conflicts occurred. Resolve them in {} mode\n{}",
+                git_diff.name(),
+                String::from_utf8_lossy(output.stderr.as_slice())
+            ))
+            .await;
+    }
+    nvim.notify_command_result("git cherry-pick", output).await
+}
+
+// 2つ選ばれたコミットの差分を diffview で開く。diffview が無ければ --stat を通知する
+async fn diff_selected(nvim: &Neovim, items: &[String]) -> Result<()> {
+    let [a, b] = items else {
+        return nvim
+            .notify_warn(format!(
+                "select exactly two commits (selected: {})",
+                items.len()
+            ))
+            .await;
+    };
+    let (a, b) = (git::parse_short_commit(a)?, git::parse_short_commit(b)?);
+    // 古い方を左にする
+    let (a, b) = if git::is_ancestor(&b, &a).await? {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    let has_diffview = nvim.eval("exists(':DiffviewOpen')").await?.as_i64() == Some(2);
+    if has_diffview {
+        nvim.hide_floaterm().await?;
+        nvim.command(&format!("DiffviewOpen {a}..{b}")).await?;
+        Ok(())
+    } else {
+        let output = Command::new("git")
+            .arg("diff")
+            .arg(&a)
+            .arg(&b)
+            .arg("--stat")
+            .output()
+            .await?;
+        nvim.notify_command_result(format!("git diff {a} {b} --stat"), output)
+            .await
+    }
+}
+
+// staged な変更を item のコミットへの fixup コミットにする。
+// autosquash なら続けて rebase して取り込む (git-diff の CommitInstantFixup と同じ)
+async fn fixup(nvim: &Neovim, item: &str, autosquash: bool) -> Result<()> {
+    let commit = git::parse_short_commit(item)?;
+    if !git::has_staged_changes().await? {
+        return nvim
+            .notify_warn("git commit --fixup: no staged changes")
+            .await;
+    }
+    let output = Command::new("git")
+        .arg("commit")
+        .arg(format!("--fixup={commit}"))
+        .output()
+        .await?;
+    let success = output.status.success();
+    nvim.notify_command_result(format!("git commit --fixup={commit}"), output)
+        .await?;
+    if !success || !autosquash {
+        return Ok(());
+    }
+    let output = Command::new("git")
+        .arg("rebase")
+        .arg("--update-refs")
+        .arg("--autosquash")
+        .arg("--autostash")
+        .arg("-i")
+        .arg(format!("{}^", commit))
+        .env("GIT_SEQUENCE_EDITOR", ":")
+        .output()
+        .await?;
+    nvim.notify_command_result("git rebase --autosquash", output)
+        .await
+}
+
+fn preview_full(item: String) -> BoxFuture<'static, Result<PreviewResp>> {
+    async move {
+        let commit = git::parse_short_commit(&item)?;
+        let message = git::show_commit(commit).await?;
+        Ok(PreviewResp { message })
+    }
+    .boxed()
+}
+
+fn preview_patch(item: String, win: PreviewWindow) -> BoxFuture<'static, Result<PreviewResp>> {
+    async move {
+        let commit = git::parse_short_commit(&item)?;
+        let message = git::show_patch(commit).await?;
+        let message = diff_pager::render(&message, &win).await.unwrap_or(message);
+        Ok(PreviewResp { message })
+    }
+    .boxed()
+}
+
 fn branches_of(item: &str) -> Result<Vec<String>> {
     let branches = git::parse_branches_of_log(item);
     let remotes = git::remotes()?;
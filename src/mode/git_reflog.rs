@@ -13,6 +13,7 @@ use crate::nvim::NeovimExt;
 use crate::state::State;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::gh;
 use crate::utils::git;
 use crate::utils::xsel;
 
@@ -29,7 +30,7 @@ impl ModeDef for GitReflog {
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         Box::pin(async_stream::stream! {
             let mut commits = git::reflog_graph("HEAD").await?;
             // reset color to white
@@ -106,6 +107,15 @@ impl ModeDef for GitReflog {
                     },
                 }
             ],
+            "ctrl-w" => [
+                execute_silent!{b, |_mode,config,_state,_query,item| {
+                    let commit = git::parse_short_commit(&item)?;
+                    if let Err(e) = gh::browse_commit(commit).await {
+                        config.nvim.notify_error(e.to_string()).await?;
+                    }
+                    Ok(())
+                }}
+            ],
             "ctrl-y" => [
                 execute_silent!{b, |_mode,_config,_state,_query,item| {
                     let commit = git::parse_short_commit(&item)?;
@@ -0,0 +1,169 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::Neovim;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::diff_pager;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::git;
+use crate::utils::xsel;
+
+#[derive(Clone)]
+pub struct GitStash;
+
+impl ModeDef for GitStash {
+    fn name(&self) -> &'static str {
+        "git-stash"
+    }
+    fn fzf_sort(&self) -> Option<bool> {
+        Some(false)
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let items = git::stash_list().await?;
+            if items.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no stashes"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview<'a>(
+        &self,
+        _config: &Config,
+        win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            let stash = parse_stash(&item)?;
+            let output = command::output_with_timeout(
+                "preview",
+                Command::new("git")
+                    .arg("stash")
+                    .arg("show")
+                    .arg("--stat")
+                    .arg("--patch")
+                    .arg("--color=always")
+                    .arg(&stash),
+                command::preview_timeout(),
+            )
+            .await?;
+            let diff = String::from_utf8_lossy(&output.stdout).into_owned();
+            let message = match diff_pager::render(&diff, win).await {
+                Some(rendered) => rendered,
+                None => diff,
+            };
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                select_and_execute!{b, |_mode,config,_state,_query,item|
+                    "apply" => {
+                        stash_command(&config.nvim, "apply", &item).await
+                    },
+                    "pop" => {
+                        stash_command(&config.nvim, "pop", &item).await
+                    },
+                    "drop" => {
+                        // 取り消せないので確認する
+                        if !fzf::confirm(format!("drop {}?", parse_stash(&item)?)).await? {
+                            return Ok(());
+                        }
+                        stash_command(&config.nvim, "drop", &item).await
+                    },
+                    "restore files" => {
+                        restore_files(&config.nvim, &item).await
+                    },
+                },
+                b.reload(),
+            ],
+            "ctrl-f" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    restore_files(&config.nvim, &item).await
+                }),
+                b.reload(),
+            ],
+            "ctrl-y" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    xsel::yank(parse_stash(&item)?).await?;
+                    Ok(())
+                })
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// "stash@{0}  2 hours ago  WIP on main: ..." => "stash@{0}"
+fn parse_stash(item: &str) -> Result<String> {
+    item.split("  ")
+        .next()
+        .filter(|s| s.starts_with("stash@{"))
+        .map(|s| s.to_string())
+        .ok_or(anyhow!("invalid item: {item}"))
+}
+
+async fn stash_command(nvim: &Neovim, subcommand: &str, item: &str) -> Result<()> {
+    let stash = parse_stash(item)?;
+    let output = Command::new("git")
+        .arg("stash")
+        .arg(subcommand)
+        .arg(&stash)
+        .output()
+        .await?;
+    nvim.notify_command_result(format!("git stash {subcommand} {stash}"), output)
+        .await
+}
+
+// stash に含まれるファイルのうち選んだものだけを作業ツリーに戻す
+async fn restore_files(nvim: &Neovim, item: &str) -> Result<()> {
+    let stash = parse_stash(item)?;
+    let files = git::stash_files(&stash).await?;
+    if files.is_empty() {
+        return nvim.notify_warn(format!("{stash}: no files")).await;
+    }
+    let files =
+        fzf::select_multi_with_header(format!("files to restore from {stash}"), files).await?;
+    if files.is_empty() {
+        return Ok(());
+    }
+    let output = Command::new("git")
+        .current_dir(git::workdir()?)
+        .arg("checkout")
+        .arg(&stash)
+        .arg("--")
+        .args(&files)
+        .output()
+        .await?;
+    nvim.notify_command_result(
+        format!("git checkout {stash} -- {}", files.join(" ")),
+        output,
+    )
+    .await
+}
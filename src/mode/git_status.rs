@@ -11,12 +11,20 @@ use crate::mode::config_builder;
 use crate::mode::CallbackMap;
 use crate::mode::ModeDef;
 use crate::nvim;
+use crate::nvim::Neovim;
 use crate::nvim::NeovimExt;
 use crate::state::State;
+use crate::utils::command;
+use crate::utils::diff_pager;
+use crate::utils::file_manager;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
 use crate::utils::gh;
 use crate::utils::git;
+use crate::utils::icons;
+use crate::utils::path::parent_dir;
+use crate::utils::path::to_relpath;
+use crate::utils::xsel;
 
 #[derive(Clone)]
 pub struct GitStatus;
@@ -31,7 +39,7 @@ impl ModeDef for GitStatus {
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         load([
             Status::INDEX_NEW,
             Status::INDEX_MODIFIED,
@@ -42,14 +50,17 @@ impl ModeDef for GitStatus {
     fn preview(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &PreviewWindow,
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
-        preview(item)
+        preview(item, *win)
     }
     fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
         fzf_bindings()
     }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        vec!["--multi"]
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -58,26 +69,35 @@ fn load(statuses: impl IntoIterator<Item = Status>) -> super::LoadStream<'static
     let files = git::files_with_status(statuses);
     Box::pin(async_stream::stream! {
         match files {
-            Ok(files) => yield Ok(LoadResp::new_with_default_header(files)),
+            Ok(files) if files.is_empty() => yield Ok(LoadResp::empty_with_message("no changes")),
+            Ok(files) => {
+                let files = files.into_iter().map(icons::decorate).collect();
+                yield Ok(LoadResp::new_with_default_header(files))
+            }
             Err(e) => yield Err(e),
         }
     })
 }
 
-fn preview(path: String) -> BoxFuture<'static, Result<PreviewResp>> {
+fn preview(path: String, win: PreviewWindow) -> BoxFuture<'static, Result<PreviewResp>> {
     async move {
+        let path = icons::strip(&path);
         let workdir = git::workdir()?;
-        let message = Command::new("git")
-            .arg("diff")
-            .arg("HEAD")
-            .arg("--color=always")
-            .arg("--no-ext")
-            .arg("--")
-            .arg(format!("{workdir}{path}"))
-            .output()
-            .await?
-            .stdout;
+        let message = command::output_with_timeout(
+            "preview",
+            Command::new("git")
+                .arg("diff")
+                .arg("HEAD")
+                .arg("--color=always")
+                .arg("--no-ext")
+                .arg("--")
+                .arg(format!("{workdir}{path}")),
+            command::preview_timeout(),
+        )
+        .await?
+        .stdout;
         let message = String::from_utf8_lossy(message.as_slice()).into_owned();
+        let message = diff_pager::render(&message, &win).await.unwrap_or(message);
         Ok(PreviewResp { message })
     }
     .boxed()
@@ -105,12 +125,53 @@ fn fzf_bindings() -> (fzf::Bindings, CallbackMap) {
                 open(config, item, opts).await
             })
         ],
+        "alt-enter" => [
+            execute_silent_multi!(b, |_mode,config,state,_query,_item| {
+                open_tabs(config, &state.selected_items).await
+            })
+        ],
+        "alt-a" => [
+            execute_silent_multi!(b, |_mode,config,state,_query,item| {
+                // :argdo で一括操作できるように arglist にする
+                let workdir = git::workdir()?;
+                let files = state.selected_or(item)
+                    .iter()
+                    .map(|item| format!("{workdir}{}", icons::strip(item)))
+                    .collect::<Vec<_>>();
+                config.nvim.set_arglist(&files).await
+            })
+        ],
         "ctrl-v" => [
             execute!(b, |_mode,config,_state,_query,item| {
                 let opts = OpenOpts::Vifm;
                 open(config, item, opts).await
             })
         ],
+        "ctrl-d" => [
+            execute!(b, |_mode,config,_state,_query,item| {
+                let opts = OpenOpts::Diff;
+                open(config, item, opts).await
+            })
+        ],
+        "ctrl-alt-g" => [
+            execute_silent!(b, |_mode,config,_state,_query,item| {
+                let workdir = git::workdir()?;
+                let revision = git::rev_parse("HEAD")?;
+                let file = format!("{workdir}{}", icons::strip(&item));
+                let url = gh::permalink(file, &revision, None)?;
+                xsel::yank(&url).await?;
+                config.nvim.notify_info(format!("yanked {url}")).await
+            })
+        ],
+        "ctrl-o" => [
+            // ファイルの履歴を git-log で見る
+            execute_silent!(b, |_mode,config,_state,_query,item| {
+                let workdir = git::workdir()?;
+                let path = format!("{workdir}{}", icons::strip(&item));
+                super::spawn_change_mode_with_arg(config, super::git_log::GitLog::path().name(), "", path);
+                Ok(())
+            })
+        ],
         "pgup" => [
             select_and_execute!{b, |_mode,config,_state,_query,item|
                 "neovim" => {
@@ -121,30 +182,62 @@ fn fzf_bindings() -> (fzf::Bindings, CallbackMap) {
                     let opts = OpenOpts::Vifm;
                     open(config, item, opts).await
                 },
+                "oil" => {
+                    let opts = OpenOpts::Oil;
+                    open(config, item, opts).await
+                },
                 "browse-github" => {
                     let opts = OpenOpts::BrowseGithub;
                     open(config, item, opts).await
                 },
+                "reveal in file manager" => {
+                    let opts = OpenOpts::FileManager;
+                    open(config, item, opts).await
+                },
             }
         ]
     }
 }
 
 enum OpenOpts {
-    Neovim { tabedit: bool },
+    Neovim {
+        tabedit: bool,
+    },
     Vifm,
+    Oil,
     BrowseGithub,
+    FileManager,
+    /// diffview があれば DiffviewOpen、無ければ scratch buffer に git diff を出す
+    Diff,
+}
+
+async fn open_tabs(config: &Config, items: &[String]) -> Result<()> {
+    let workdir = git::workdir()?;
+    let targets = items
+        .iter()
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let file = format!("{}{}", workdir, icons::strip(item));
+            (file.into(), nvim::OpenOpts::default())
+        })
+        .collect();
+    config.nvim.open_tabs(targets).await
 }
 
 async fn open(config: &Config, file: String, opts: OpenOpts) -> Result<()> {
+    if file.is_empty() {
+        return Ok(());
+    }
     let workdir = git::workdir()?;
-    let file = format!("{}{}", workdir, file);
+    let file = format!("{}{}", workdir, icons::strip(&file));
     match opts {
         OpenOpts::Neovim { tabedit } => {
             let nvim = config.nvim.clone();
             let nvim_opts = nvim::OpenOpts {
                 line: None,
+                col: None,
                 tabedit,
+                window: None,
             };
             nvim.open(file.into(), nvim_opts).await?
         }
@@ -152,9 +245,76 @@ async fn open(config: &Config, file: String, opts: OpenOpts) -> Result<()> {
             let pwd = std::env::current_dir().unwrap().into_os_string();
             Command::new("vifm").arg(&pwd).spawn()?.wait().await?;
         }
+        OpenOpts::Oil => {
+            config.nvim.open_oil(parent_dir(&file)).await?;
+        }
         OpenOpts::BrowseGithub => {
             gh::browse_github(file).await?;
         }
+        OpenOpts::FileManager => {
+            file_manager::reveal(&file)?;
+        }
+        OpenOpts::Diff => {
+            open_diff(&config.nvim, &file).await?;
+        }
     }
     Ok(())
 }
+
+async fn open_diff(nvim: &Neovim, file: &str) -> Result<()> {
+    let has_diffview = nvim.eval("exists(':DiffviewOpen')").await?.as_i64() == Some(2);
+    if has_diffview {
+        nvim.hide_floaterm().await?;
+        let r = nvim
+            .eval_lua_with_args(
+                "vim.cmd({ cmd = 'DiffviewOpen', args = { '--', (...) } })",
+                vec![file.into()],
+            )
+            .await;
+        match r {
+            Ok(_) => return Ok(()),
+            // diffview が壊れていても scratch buffer で見られるようにする
+            Err(e) => warn!("git-status: DiffviewOpen failed"; "error" => e.to_string()),
+        }
+    }
+
+    let output = Command::new("git")
+        .arg("diff")
+        .arg("HEAD")
+        .arg("--no-color")
+        .arg("--no-ext")
+        .arg("--")
+        .arg(file)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return nvim.notify_command_result("git diff", output).await;
+    }
+    let diff = String::from_utf8_lossy(&output.stdout).into_owned();
+    if diff.is_empty() {
+        return nvim.notify_info(format!("no diff: {file}")).await;
+    }
+    let lines = diff.lines().map(rmpv::Value::from).collect::<Vec<_>>();
+    nvim.hide_floaterm().await?;
+    nvim.eval_lua_with_args(
+        r#"
+        local name, lines = ...
+        vim.cmd.tabnew()
+        local buf = vim.api.nvim_get_current_buf()
+        vim.bo[buf].buftype = 'nofile'
+        vim.bo[buf].bufhidden = 'wipe'
+        vim.bo[buf].swapfile = false
+        vim.api.nvim_buf_set_lines(buf, 0, -1, false, lines)
+        vim.bo[buf].modifiable = false
+        vim.bo[buf].filetype = 'diff'
+        -- 同名のバッファが残っていると失敗するが名前は無くても困らない
+        pcall(vim.api.nvim_buf_set_name, buf, name)
+        "#,
+        vec![
+            format!("git diff HEAD -- {}", to_relpath(file)).into(),
+            lines.into(),
+        ],
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::git;
+use crate::utils::xsel;
+
+#[derive(Clone)]
+pub struct GitSubmodule;
+
+// name  path  sha  status
+struct Item {
+    name: String,
+    path: String,
+    sha: String,
+    status: &'static str,
+}
+
+const SEPARATOR: &str = "  ";
+
+impl Item {
+    fn render(&self) -> String {
+        [
+            self.name.as_str(),
+            self.path.as_str(),
+            self.sha.as_str(),
+            self.status,
+        ]
+        .join(SEPARATOR)
+    }
+    fn parse_path(item: &str) -> Result<String> {
+        // sha と status は空白を含まないので右から切る。path 内の空白はそのまま残る
+        let name_and_path = item
+            .rsplitn(3, SEPARATOR)
+            .nth(2)
+            .ok_or(anyhow!("invalid item: {item}"))?;
+        let (_name, path) = name_and_path
+            .split_once(SEPARATOR)
+            .ok_or(anyhow!("invalid item: {item}"))?;
+        Ok(path.to_string())
+    }
+}
+
+impl ModeDef for GitSubmodule {
+    fn name(&self) -> &'static str {
+        "git-submodule"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let items = submodules()
+                .await?
+                .iter()
+                .map(Item::render)
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no submodules"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        _win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        async move {
+            let path = Item::parse_path(&item)?;
+            let output = command::output_with_timeout(
+                "preview",
+                Command::new("git")
+                    .arg("-C")
+                    .arg(format!("{}{path}", git::workdir()?))
+                    .arg("log")
+                    .arg("--color=always")
+                    .arg("--oneline")
+                    .arg("-n")
+                    .arg("20"),
+                command::preview_timeout(),
+            )
+            .await?;
+            let message = if output.status.success() {
+                String::from_utf8_lossy(output.stdout.as_slice()).into_owned()
+            } else {
+                "(not initialized)".to_string()
+            };
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute_silent!(b, |_mode,config,_state,_query,item| {
+                    let path = Item::parse_path(&item)?;
+                    super::change_directory(config, format!("{}{path}", git::workdir()?)).await
+                }),
+                b.change_mode(super::git_status::GitStatus.name(), false),
+            ],
+            "ctrl-u" => [
+                execute_silent!(b, |_mode,config,_state,_query,item| {
+                    let path = Item::parse_path(&item)?;
+                    let output = Command::new("git")
+                        .current_dir(git::workdir()?)
+                        .arg("submodule")
+                        .arg("update")
+                        .arg("--init")
+                        .arg("--")
+                        .arg(&path)
+                        .output()
+                        .await?;
+                    config
+                        .nvim
+                        .notify_command_result(format!("git submodule update --init {path}"), output)
+                        .await
+                }),
+                b.reload(),
+            ],
+            "ctrl-y" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    xsel::yank(Item::parse_path(&item)?).await?;
+                    Ok(())
+                })
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+async fn submodules() -> Result<Vec<Item>> {
+    let workdir = git::workdir()?;
+    let names = submodule_names(&workdir).await?;
+    let output = Command::new("git")
+        .current_dir(&workdir)
+        .arg("submodule")
+        .arg("status")
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git submodule status: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    // 例: "+1234abcd... path/to/sub (v1.0-1-g1234abc)"
+    let items = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (status, sha, path) = parse_status_line(line)?;
+            let name = names.get(&path).cloned().unwrap_or_else(|| path.clone());
+            Some(Item {
+                name,
+                path,
+                sha,
+                status,
+            })
+        })
+        .collect();
+    Ok(items)
+}
+
+// "<flag><sha> <path>[ (<describe>)]" を (status, 短縮 sha, path) にする。path は空白を含みうる
+fn parse_status_line(line: &str) -> Option<(&'static str, String, String)> {
+    let mut chars = line.chars();
+    let status = match chars.next()? {
+        '-' => "uninitialized",
+        '+' => "modified",
+        'U' => "conflict",
+        _ => "ok",
+    };
+    let (sha, rest) = chars.as_str().split_once(' ')?;
+    let path = match rest.rfind(" (") {
+        Some(i) if rest.ends_with(')') => &rest[..i],
+        _ => rest,
+    };
+    Some((status, sha.chars().take(8).collect(), path.to_string()))
+}
+
+// .gitmodules から path -> name の対応を取る
+async fn submodule_names(workdir: &str) -> Result<HashMap<String, String>> {
+    let output = Command::new("git")
+        .current_dir(workdir)
+        .arg("config")
+        .arg("--file")
+        .arg(".gitmodules")
+        .arg("--get-regexp")
+        .arg(r"^submodule\..*\.path$")
+        .output()
+        .await?;
+    // .gitmodules が無い場合は空
+    let names = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (key, path) = line.split_once(' ')?;
+            let name = key.strip_prefix("submodule.")?.strip_suffix(".path")?;
+            Some((path.to_string(), name.to_string()))
+        })
+        .collect();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_line_keeps_spaces_in_path() {
+        assert_eq!(
+            parse_status_line("+1234567890abcdef my sub/dir (v1.0-1-g1234abc)"),
+            Some(("modified", "12345678".to_string(), "my sub/dir".to_string()))
+        );
+        // 未初期化なら describe が付かない
+        assert_eq!(
+            parse_status_line("-1234567890abcdef my sub"),
+            Some((
+                "uninitialized",
+                "12345678".to_string(),
+                "my sub".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_path_round_trips_render() {
+        let item = Item {
+            name: "my sub".to_string(),
+            path: "lib/my  sub".to_string(),
+            sha: "12345678".to_string(),
+            status: "ok",
+        };
+        assert_eq!(Item::parse_path(&item.render()).unwrap(), "lib/my  sub");
+    }
+}
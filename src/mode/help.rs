@@ -0,0 +1,181 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rmpv::ext::from_value;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::Neovim;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// neovim's help tags (`doc/tags` in runtimepath)
+#[derive(Clone)]
+pub struct Help {
+    // fzf 上で n 番目 ({n}) の item が items[n]
+    items: Arc<Mutex<Option<Vec<HelpTag>>>>,
+}
+
+impl Help {
+    pub fn new() -> Self {
+        Self {
+            items: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn lookup(&self, index: Option<usize>) -> Result<HelpTag> {
+        let items = self.items.lock().await;
+        let items = items.as_ref().ok_or(anyhow!("help tags not loaded"))?;
+        index
+            .and_then(|n| items.get(n))
+            .cloned()
+            .ok_or(anyhow!("no help tag at index {index:?}"))
+    }
+}
+
+impl ModeDef for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+    fn replayable(&self) -> bool {
+        false
+    }
+    fn load<'a>(
+        &'a self,
+        config: &Config,
+        _state: &'a mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'a> {
+        let nvim = config.nvim.clone();
+        Box::pin(async_stream::stream! {
+            let items = HelpTag::gather(&nvim).await?;
+            let rendered = items.iter().map(HelpTag::render).collect::<Vec<_>>();
+            self.items.lock().await.replace(items);
+            yield Ok(LoadResp::new_with_default_header(rendered))
+        })
+    }
+    fn preview<'a>(
+        &'a self,
+        config: &'a Config,
+        win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        self.preview_with_index(config, win, item, None)
+    }
+    fn preview_with_index<'a>(
+        &'a self,
+        _config: &'a Config,
+        _win: &'a PreviewWindow,
+        _item: String,
+        index: Option<usize>,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            let item = self.lookup(index).await?;
+            let content = tokio::fs::read_to_string(&item.file).await?;
+            let message = extract_section(&content, &item.tag)
+                .unwrap_or_else(|| format!("*{}* not found in {}", item.tag, item.file));
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [{
+                let self_ = self.clone();
+                b.execute(move |_mode,config,state,_query,_item| {
+                    let self_ = self_.clone();
+                    async move {
+                        let item = self_.lookup(state.index).await?;
+                        let _ = config.nvim.hide_floaterm().await;
+                        // | などを含むタグもあるので Ex コマンドとしてパースさせない
+                        config.nvim.eval_lua_with_args(
+                            "vim.cmd.help((...))",
+                            vec![item.tag.into()],
+                        ).await?;
+                        Ok(())
+                    }.boxed()
+                })
+            }],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Deserialize)]
+struct HelpTag {
+    tag: String,
+    file: String,
+}
+
+impl HelpTag {
+    // 同じタグが複数の tags にある場合は :help と同じく runtimepath で先にあるものを採る
+    async fn gather(nvim: &Neovim) -> Result<Vec<HelpTag>> {
+        Ok(from_value(
+            nvim.eval_lua_retry(
+                r#"
+                    local seen, items = {}, {}
+                    for _, tags in ipairs(vim.api.nvim_get_runtime_file('doc/tags', true)) do
+                      local dir = vim.fn.fnamemodify(tags, ':h')
+                      for _, line in ipairs(vim.fn.readfile(tags)) do
+                        local tag, file = line:match('^([^\t]+)\t([^\t]+)\t')
+                        if tag and not seen[tag] and not vim.startswith(tag, '!_TAG_') then
+                          seen[tag] = true
+                          table.insert(items, { tag = tag, file = dir .. '/' .. file })
+                        end
+                      end
+                    end
+                    return items
+                "#,
+            )
+            .await?,
+        )?)
+    }
+
+    fn render(&self) -> String {
+        let file = std::path::Path::new(&self.file)
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        format!(
+            "{:<40} {}",
+            self.tag,
+            ansi_term::Colour::Fixed(8).paint(file)
+        )
+    }
+}
+
+// 大きすぎる section は途中で切る
+const MAX_SECTION_LINES: usize = 200;
+
+// `*tag*` のある行から次の区切り線 (=== や ---) までを取り出す
+fn extract_section(content: &str, tag: &str) -> Option<String> {
+    let needle = format!("*{tag}*");
+    let lines = content.lines().collect::<Vec<_>>();
+    let start = lines.iter().position(|l| l.contains(&needle))?;
+    let is_rule =
+        |l: &str| l.len() >= 10 && (l.chars().all(|c| c == '=') || l.chars().all(|c| c == '-'));
+    let section = lines[start..]
+        .iter()
+        .enumerate()
+        .take_while(|(i, l)| *i == 0 || !is_rule(l))
+        .take(MAX_SECTION_LINES)
+        .map(|(_, l)| *l)
+        .collect::<Vec<_>>();
+    Some(section.join("\n"))
+}
@@ -0,0 +1,245 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rmpv::ext::from_value;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim;
+use crate::nvim::Neovim;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::bat;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::path::to_relpath;
+
+/// neovim's jumplist / changelist of the window fzfw was opened from
+#[derive(Clone)]
+pub struct Jumps {
+    kind: Kind,
+    // fzf 上で n 番目 ({n}) の item が items[n]
+    items: Arc<Mutex<Option<Vec<JumpItem>>>>,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Jump,
+    Change,
+}
+
+impl Jumps {
+    pub fn jumplist() -> Self {
+        Self {
+            kind: Kind::Jump,
+            items: Arc::new(Mutex::new(None)),
+        }
+    }
+    pub fn changelist() -> Self {
+        Self {
+            kind: Kind::Change,
+            items: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn lookup(&self, index: Option<usize>) -> Result<JumpItem> {
+        let items = self.items.lock().await;
+        let items = items
+            .as_ref()
+            .ok_or(anyhow!("{} not loaded", self.name()))?;
+        index
+            .and_then(|n| items.get(n))
+            .cloned()
+            .ok_or(anyhow!("no entry at index {index:?}"))
+    }
+}
+
+impl ModeDef for Jumps {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            Kind::Jump => "jumps",
+            Kind::Change => "changes",
+        }
+    }
+    fn replayable(&self) -> bool {
+        false
+    }
+    fn fzf_sort(&self) -> Option<bool> {
+        // 新しい順を保つ
+        Some(false)
+    }
+    fn load<'a>(
+        &'a self,
+        config: &Config,
+        _state: &'a mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'a> {
+        let nvim = config.nvim.clone();
+        Box::pin(async_stream::stream! {
+            let items = JumpItem::gather(&nvim, self.kind).await?;
+            let rendered = items.iter().map(JumpItem::render).collect::<Vec<_>>();
+            self.items.lock().await.replace(items);
+            if rendered.is_empty() {
+                yield Ok(LoadResp::empty_with_message(format!("no {}", self.name())));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(rendered))
+            }
+        })
+    }
+    fn preview<'a>(
+        &'a self,
+        config: &'a Config,
+        win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        self.preview_with_index(config, win, item, None)
+    }
+    fn preview_with_index<'a>(
+        &'a self,
+        _config: &'a Config,
+        _win: &'a PreviewWindow,
+        _item: String,
+        index: Option<usize>,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            let item = self.lookup(index).await?;
+            let message = bat::render_file_with_highlight(&item.file, item.lnum as isize).await?;
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [{
+                let self_ = self.clone();
+                b.execute(move |_mode,config,state,_query,_item| {
+                    let self_ = self_.clone();
+                    async move {
+                        let item = self_.lookup(state.index).await?;
+                        jump(&config.nvim, item, false).await
+                    }.boxed()
+                })
+            }],
+            "ctrl-t" => [{
+                let self_ = self.clone();
+                b.execute(move |_mode,config,state,_query,_item| {
+                    let self_ = self_.clone();
+                    async move {
+                        let item = self_.lookup(state.index).await?;
+                        jump(&config.nvim, item, true).await
+                    }.boxed()
+                })
+            }],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Deserialize)]
+struct RawJumpItem {
+    bufnr: u64,
+    lnum: u64,
+    col: u64, // 0-based
+    text: String,
+}
+
+#[derive(Debug, Clone)]
+struct JumpItem {
+    bufnr: u64,
+    file: String,
+    lnum: u64,
+    col: u64,
+    text: String,
+}
+
+impl JumpItem {
+    async fn gather(nvim: &Neovim, kind: Kind) -> Result<Vec<JumpItem>> {
+        // fzfw を開く前のウィンドウのリストを取る
+        let list = match kind {
+            Kind::Jump => "vim.fn.getjumplist(vim.g.fzfw_last_win)",
+            Kind::Change => "vim.fn.getchangelist(vim.fn.winbufnr(vim.g.fzfw_last_win))",
+        };
+        let raw: Vec<RawJumpItem> = from_value(
+            nvim.eval_lua_retry(format!(
+                r#"
+                    local bufnr = vim.fn.winbufnr(vim.g.fzfw_last_win)
+                    local entries = {{}}
+                    for _, e in ipairs({list}[1] or {{}}) do
+                      local b = e.bufnr or bufnr
+                      if vim.api.nvim_buf_is_valid(b) then
+                        table.insert(entries, {{
+                          bufnr = b,
+                          lnum = e.lnum,
+                          col = e.col,
+                          text = vim.fn.getbufline(b, e.lnum)[1] or "",
+                        }})
+                      end
+                    end
+                    return entries
+                "#
+            ))
+            .await?,
+        )?;
+
+        // bufnr -> ファイル名は同じものが多いのでまとめて引く
+        let mut names: HashMap<u64, String> = HashMap::new();
+        let mut items = vec![];
+        // 新しいものが後ろにあるので逆順にする
+        for r in raw.into_iter().rev() {
+            let file = match names.get(&r.bufnr) {
+                Some(file) => file.clone(),
+                None => {
+                    let file = nvim.get_buf_name(r.bufnr as usize).await?;
+                    names.insert(r.bufnr, file.clone());
+                    file
+                }
+            };
+            if file.is_empty() {
+                continue;
+            }
+            items.push(JumpItem {
+                bufnr: r.bufnr,
+                file,
+                lnum: r.lnum,
+                col: r.col,
+                text: r.text,
+            });
+        }
+        Ok(items)
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "{}:{}  {}",
+            to_relpath(&self.file),
+            self.lnum,
+            self.text.trim()
+        )
+    }
+}
+
+async fn jump(nvim: &Neovim, item: JumpItem, tabedit: bool) -> Result<()> {
+    let opts = nvim::OpenOpts {
+        line: Some(item.lnum as usize),
+        // zero-indexed なので +1 する
+        col: Some(item.col as usize + 1),
+        tabedit,
+        window: None,
+    };
+    nvim.open((item.bufnr as usize).into(), opts).await
+}
@@ -1,3 +1,8 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::anyhow;
 use anyhow::Result;
 use clap::Parser;
 use futures::future::BoxFuture;
@@ -16,14 +21,17 @@ use crate::mode::ModeDef;
 use crate::nvim;
 use crate::nvim::NeovimExt;
 use crate::state::State;
+use crate::utils::ansi;
 use crate::utils::bat;
 use crate::utils::command;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
 use crate::utils::gh;
 use crate::utils::git;
+use crate::utils::query_history;
 use crate::utils::rg;
 use crate::utils::vscode;
+use crate::utils::xsel;
 
 ////////////////////////////////////////////////////////////////////////////////
 // Livegrep
@@ -33,6 +41,16 @@ use crate::utils::vscode;
 pub struct LiveGrep {
     name: &'static str,
     rg_opts: Vec<String>,
+    // クエリを空白で区切り、全ての語にマッチする行だけを残す。alt-a で切り替える
+    and_terms: Arc<AtomicBool>,
+    // git ls-files に含まれるファイルだけを検索する
+    tracked: bool,
+}
+
+// 初期状態だけ FZFW_LIVEGREP_AND=1 で変えられる
+fn initial_and_terms() -> Arc<AtomicBool> {
+    let on = std::env::var("FZFW_LIVEGREP_AND").is_ok_and(|v| v == "1");
+    Arc::new(AtomicBool::new(on))
 }
 
 impl LiveGrep {
@@ -40,36 +58,59 @@ impl LiveGrep {
         Self {
             name: "livegrep",
             rg_opts: vec!["--glob".to_string(), "!.git".to_string()],
+            and_terms: initial_and_terms(),
+            tracked: false,
         }
     }
     pub fn new_no_ignore() -> Self {
         Self {
             name: "livegrep(no-ignore)",
             rg_opts: vec!["--no-ignore".to_string()],
+            and_terms: initial_and_terms(),
+            tracked: false,
+        }
+    }
+    pub fn new_tracked() -> Self {
+        Self {
+            name: "livegrep(tracked)",
+            rg_opts: vec![],
+            and_terms: initial_and_terms(),
+            tracked: true,
         }
     }
+    fn and_terms(&self) -> bool {
+        self.and_terms.load(Ordering::SeqCst)
+    }
 }
 
 impl ModeDef for LiveGrep {
     fn name(&self) -> &'static str {
         self.name
     }
+    fn fzf_prompt(&self) -> String {
+        if self.and_terms() {
+            format!("{}(and)>", self.name)
+        } else {
+            format!("{}>", self.name)
+        }
+    }
     fn load(
         &self,
         _config: &Config,
         _state: &mut State,
         query: String,
         _item: String,
-    ) -> super::LoadStream {
-        load(query, &self.rg_opts)
+    ) -> super::LoadStream<'_> {
+        load(query, &self.rg_opts, self.and_terms(), self.tracked)
     }
     fn preview(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &PreviewWindow,
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
-        async move { preview(item).await }.boxed()
+        let win = *win;
+        async move { preview(item, &win).await }.boxed()
     }
     fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
         use config_builder::*;
@@ -81,8 +122,26 @@ impl ModeDef for LiveGrep {
             "esc" => [
                 b.change_mode(LiveGrepF.name(), false),
             ],
+            "alt-a" => [
+                {
+                    let and_terms = self.and_terms.clone();
+                    b.toggle_option(move || {
+                        and_terms.fetch_xor(true, Ordering::SeqCst);
+                    })
+                },
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "alt-f" => [
+                if self.tracked {
+                    b.change_mode(LiveGrep::new().name(), true)
+                } else {
+                    b.change_mode(LiveGrep::new_tracked().name(), true)
+                }
+            ],
             "enter" => [
-                execute!(b, |_mode,config,_state,_query,item| {
+                execute!(b, |_mode,config,_state,query,item| {
+                    record_query(&query);
                     let opts = if vscode::in_vscode() {
                         OpenOpts::VSCode
                     } else {
@@ -92,11 +151,37 @@ impl ModeDef for LiveGrep {
                 })
             ],
             "ctrl-t" => [
-                execute!(b, |_mode,config,_state,_query,item| {
+                execute!(b, |_mode,config,_state,query,item| {
+                    record_query(&query);
                     let opts = OpenOpts::Neovim { tabedit: true };
                     open(config, item, opts).await
                 })
             ],
+            "alt-enter" => [
+                execute_silent_multi!(b, |_mode,config,state,query,_item| {
+                    record_query(&query);
+                    open_tabs(config, &state.selected_items).await
+                })
+            ],
+            "ctrl-alt-g" => [
+                execute_silent_multi!(b, |_mode,config,state,_query,_item| {
+                    yank_permalink(config, &state.selected_items).await
+                })
+            ],
+            "ctrl-alt-h" => [
+                execute_without_item!(b, |mode,config,_state,_query,_item| {
+                    let queries = query_history::list()?;
+                    if queries.is_empty() {
+                        return config.nvim.notify_info("no livegrep history").await;
+                    }
+                    let query = fzf::select(queries.iter().map(|q| q.as_str()).collect()).await?;
+                    if !query.is_empty() {
+                        // クエリを差し替えるには fzf を起動し直すしかない
+                        super::spawn_change_mode(config, mode.name(), query);
+                    }
+                    Ok(())
+                })
+            ],
             "pgup" => [
                 select_and_execute!{b, |_mode,config,_state,_query,item|
                     "neovim" => {
@@ -112,7 +197,7 @@ impl ModeDef for LiveGrep {
         }
     }
     fn fzf_extra_opts(&self) -> Vec<&str> {
-        vec!["--disabled"]
+        vec!["--disabled", "--multi"]
     }
 }
 
@@ -122,34 +207,169 @@ pub struct LoadOpts {
     pub query: String,
 }
 
-fn load(query: String, opts: &Vec<String>) -> super::LoadStream {
-    let mut rg_cmd = rg::new();
-    rg_cmd.args(opts);
-    rg_cmd.arg("--");
-    rg_cmd.arg(query);
+fn load(
+    query: String,
+    opts: &Vec<String>,
+    and_terms: bool,
+    tracked: bool,
+) -> super::LoadStream<'_> {
+    let (query, filters) = if and_terms {
+        let mut terms = split_query(&query);
+        if terms.is_empty() {
+            (query, vec![])
+        } else {
+            let first = terms.remove(0);
+            (first, terms.iter().map(|t| term_regex(t)).collect())
+        }
+    } else {
+        (query, vec![])
+    };
     Box::pin(async_stream::stream! {
-        let stream = command::command_output_stream(rg_cmd).chunks(100); // tekito
-        tokio::pin!(stream);
-        let mut has_error = false;
-        while let Some(r) = stream.next().await {
-            let r = r.into_iter().collect::<Result<Vec<String>>>();
-            match r {
-                Ok(lines) => {
-                    yield Ok(LoadResp::wip_with_default_header(lines));
+        // git 管理下でなければ通常の livegrep と同じ検索にする
+        let (rg_cmds, notice) = if tracked {
+            match git_ls_files().await {
+                Ok(files) => {
+                    let cmds = files
+                        .chunks(TRACKED_FILES_PER_RG)
+                        .map(|files| {
+                            let mut rg_cmd = rg::new();
+                            rg_cmd.args(opts);
+                            rg_cmd.arg("--");
+                            rg_cmd.arg(&query);
+                            rg_cmd.args(files);
+                            rg_cmd
+                        })
+                        .collect::<Vec<_>>();
+                    (cmds, None)
                 }
                 Err(e) => {
+                    warn!("livegrep: git ls-files failed"; "error" => e.to_string());
+                    let mut rg_cmd = rg::new();
+                    rg_cmd.args(LiveGrep::new().rg_opts);
+                    rg_cmd.arg("--");
+                    rg_cmd.arg(&query);
+                    (vec![rg_cmd], Some("[not a git repository: searching all files]"))
+                }
+            }
+        } else {
+            let mut rg_cmd = rg::new();
+            rg_cmd.args(opts);
+            rg_cmd.arg("--");
+            rg_cmd.arg(&query);
+            (vec![rg_cmd], None)
+        };
+        for rg_cmd in rg_cmds {
+            let stream = command::command_output_stream(rg_cmd).chunks(100); // tekito
+            tokio::pin!(stream);
+            while let Some(r) = stream.next().await {
+                let (lines, error) = command::split_chunk(r);
+                let lines = lines
+                    .into_iter()
+                    .map(normalize_line)
+                    .filter(|line| matches_all(line, &filters))
+                    .collect();
+                let mut resp = LoadResp::wip_with_default_header(lines);
+                if let Some(notice) = notice {
+                    resp.header = Some(notice.to_string());
+                }
+                yield Ok(resp);
+                if let Some(e) = error {
                     yield Ok(LoadResp::error(e.to_string()));
-                    has_error = true;
-                    break;
+                    return;
                 }
             }
         }
-        if !has_error {
-            yield Ok(LoadResp::last())
-        }
+        yield Ok(LoadResp::last())
     })
 }
 
+// 引数が長くなりすぎないように分割して rg に渡す
+const TRACKED_FILES_PER_RG: usize = 1000;
+
+// 削除済みでまだ commit していないファイルは rg がエラーにするので除く
+async fn git_ls_files() -> Result<Vec<String>> {
+    let output = tokio::process::Command::new("git")
+        .args(["ls-files", "-z"])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git ls-files: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|file| !file.is_empty() && std::path::Path::new(file).is_file())
+        .map(|file| file.to_string())
+        .collect())
+}
+
+// `foo "bar baz" qux\ quux` => ["foo", "bar baz", "qux quux"]
+fn split_query(query: &str) -> Vec<String> {
+    let mut terms = vec![];
+    let mut term = String::new();
+    let mut in_quote = false;
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(' ') => term.push(' '),
+                Some('"') => term.push('"'),
+                Some(c) => {
+                    term.push('\\');
+                    term.push(c);
+                }
+                None => term.push('\\'),
+            },
+            '"' => in_quote = !in_quote,
+            ' ' if !in_quote => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.push(c),
+        }
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+    terms
+}
+
+// rg の --smart-case に合わせる。正規表現として不正ならリテラルとして扱う
+fn term_regex(term: &str) -> Regex {
+    let case_insensitive = !term.chars().any(|c| c.is_uppercase());
+    let flags = if case_insensitive { "(?i)" } else { "" };
+    Regex::new(&format!("{flags}{term}"))
+        .unwrap_or_else(|_| Regex::new(&format!("{flags}{}", regex::escape(term))).unwrap())
+}
+
+static ANSI_ESCAPE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+// CRLF のファイルだと行末に \r が残り (色付きだと \x1b[0m の手前なので lines() でも落ちない)、
+// rg に BOM を解釈させなかった場合は 1 行目に BOM が残る。どちらも fzf 上で表示が崩れるので消す
+fn normalize_line(line: String) -> String {
+    if line.contains(['\r', '\u{feff}']) {
+        line.replace(['\r', '\u{feff}'], "")
+    } else {
+        line
+    }
+}
+
+fn matches_all(line: &str, filters: &[Regex]) -> bool {
+    if filters.is_empty() {
+        return true;
+    }
+    let plain = ANSI_ESCAPE.replace_all(line, "");
+    // file:line:col: の部分にはマッチさせない
+    let text = match ITEM_PATTERN.find(&plain) {
+        Some(m) => &plain[m.end()..],
+        None => &plain,
+    };
+    filters.iter().all(|r| r.is_match(text))
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Fuzzy search after livegrep
 ////////////////////////////////////////////////////////////////////////////////
@@ -167,7 +387,7 @@ impl ModeDef for LiveGrepF {
         state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         let livegrep_result = state.last_load_resp.clone();
         Box::pin(async_stream::stream! {
             let items = match livegrep_result {
@@ -180,10 +400,11 @@ impl ModeDef for LiveGrepF {
     fn preview(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &PreviewWindow,
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
-        async move { preview(item).await }.boxed()
+        let win = *win;
+        async move { preview(item, &win).await }.boxed()
     }
     fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
         use config_builder::*;
@@ -201,6 +422,16 @@ impl ModeDef for LiveGrepF {
                     open(config, item, opts).await
                 })
             ],
+            "alt-enter" => [
+                execute_silent_multi!(b, |_mode,config,state,_query,_item| {
+                    open_tabs(config, &state.selected_items).await
+                })
+            ],
+            "ctrl-alt-g" => [
+                execute_silent_multi!(b, |_mode,config,state,_query,_item| {
+                    yank_permalink(config, &state.selected_items).await
+                })
+            ],
             "pgup" => [
                 select_and_execute!{b, |_mode,config,_state,_query,item|
                     "neovim" => {
@@ -215,31 +446,59 @@ impl ModeDef for LiveGrepF {
             ]
         }
     }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        vec!["--multi"]
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 // Common
 ////////////////////////////////////////////////////////////////////////////////
 
-static ITEM_PATTERN: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"(?P<file>[^:]*):(?P<line>\d+):(?P<col>\d+):.*").unwrap());
+// --column 無しの出力 (file:line:text) も受け付ける。
+// file は最短一致にして Windows 形式のパス (C:\foo) も扱えるようにする
+pub(super) static ITEM_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?P<file>.+?):(?P<line>\d+):(?:(?P<col>\d+):)?").unwrap());
+
+struct Item {
+    file: String,
+    line: usize,
+    col: usize,
+}
 
-async fn preview(item: String) -> Result<PreviewResp> {
-    let file = ITEM_PATTERN.replace(&item, "$file").into_owned();
-    let line = ITEM_PATTERN.replace(&item, "$line").into_owned();
-    let col = ITEM_PATTERN.replace(&item, "$col").into_owned();
-    match line.parse::<isize>() {
-        Ok(line) => {
+impl Item {
+    fn parse(item: &str) -> Result<Self> {
+        let c = ITEM_PATTERN
+            .captures(item)
+            .ok_or(anyhow!("invalid item: {item}"))?;
+        let file = c["file"].to_string();
+        let line = c["line"].parse()?;
+        let col = match c.name("col") {
+            Some(col) => col.as_str().parse()?,
+            None => 1,
+        };
+        Ok(Item { file, line, col })
+    }
+}
+
+pub(super) async fn preview(item: String, win: &PreviewWindow) -> Result<PreviewResp> {
+    match Item::parse(&item) {
+        Ok(Item { file, line, col }) => {
             info!("rg.preview"; "parsed" => Serde(json!({
                 "file": file,
                 "line": line,
                 "col": col
             })));
-            let message = bat::render_file_with_highlight(&file, line).await?;
+            let message = bat::render_file_with_highlight(&file, line as isize).await?;
+            // minify されたファイルなどの長すぎる行は fzf の折り返しで読めなくなるので切る
+            let message = match win.max_line_width() {
+                Some(max) => ansi::truncate_lines(&message, max),
+                None => message,
+            };
             Ok(PreviewResp { message })
         }
         Err(e) => {
-            error!("rg: preview: parse line failed"; "error" => e.to_string(), "line" => line);
+            error!("rg: preview: parse item failed"; "error" => e.to_string(), "item" => item);
             Ok(PreviewResp {
                 message: "".to_string(),
             })
@@ -247,35 +506,146 @@ async fn preview(item: String) -> Result<PreviewResp> {
     }
 }
 
-enum OpenOpts {
+// 複数選択されていれば、先頭と同じファイルの行をまとめて範囲にする
+async fn yank_permalink(config: &Config, items: &[String]) -> Result<()> {
+    let items = items
+        .iter()
+        .map(|item| Item::parse(item))
+        .collect::<Result<Vec<_>>>()?;
+    let file = &items.first().ok_or(anyhow!("no item selected"))?.file;
+    let lines = items.iter().filter(|i| &i.file == file).map(|i| i.line);
+    let range = (lines.clone().min().unwrap(), lines.max().unwrap());
+    let revision = git::rev_parse("HEAD")?;
+    let url = gh::permalink(file, &revision, Some(range))?;
+    xsel::yank(&url).await?;
+    config.nvim.notify_info(format!("yanked {url}")).await
+}
+
+pub(super) enum OpenOpts {
     Neovim { tabedit: bool },
     VSCode,
     BrowseGithub,
 }
 
-async fn open(config: &Config, item: String, opts: OpenOpts) -> Result<()> {
-    let file = ITEM_PATTERN.replace(&item, "$file").into_owned();
-    let line = ITEM_PATTERN.replace(&item, "$line").into_owned();
+// 打鍵ごとの load では記録せず、ファイルを開くのに使ったクエリだけを残す
+fn record_query(query: &str) {
+    if let Err(e) = query_history::record(query) {
+        warn!("livegrep: failed to record query"; "error" => e.to_string());
+    }
+}
+
+async fn open_tabs(config: &Config, items: &[String]) -> Result<()> {
+    let targets = items
+        .iter()
+        .map(|item| {
+            let Item { file, line, col } = Item::parse(item)?;
+            let opts = nvim::OpenOpts {
+                line: Some(line),
+                col: Some(col),
+                ..Default::default()
+            };
+            Ok((file.into(), opts))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    config.nvim.open_tabs(targets).await
+}
+
+pub(super) async fn open(config: &Config, item: String, opts: OpenOpts) -> Result<()> {
+    let Item { file, line, col } = Item::parse(&item)?;
 
     match opts {
         OpenOpts::Neovim { tabedit } => {
             let nvim = config.nvim.clone();
             let nvim_opts = nvim::OpenOpts {
-                line: line.parse::<usize>().ok(),
+                line: Some(line),
+                col: Some(col),
                 tabedit,
+                window: None,
             };
             nvim.open(file.into(), nvim_opts).await?;
         }
         OpenOpts::VSCode => {
-            let line = line.parse::<usize>().ok();
-            let output = vscode::open(file, line).await?;
+            let output = vscode::open(file, Some(line)).await?;
             config.nvim.notify_command_result("code", output).await?;
         }
         OpenOpts::BrowseGithub => {
             let revision = git::rev_parse("HEAD")?;
-            gh::browse_github_line(file, &revision, line.parse::<usize>().unwrap()).await?;
+            gh::browse_github_line(file, &revision, line).await?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(item: &str) -> (String, usize, usize) {
+        let Item { file, line, col } = Item::parse(item).unwrap();
+        (file, line, col)
+    }
+
+    #[test]
+    fn parse_rg_output() {
+        assert_eq!(
+            parse("src/main.rs:12:5:fn main() {"),
+            ("src/main.rs".to_string(), 12, 5)
+        );
+    }
+
+    #[test]
+    fn parse_without_column() {
+        assert_eq!(
+            parse("src/main.rs:12:fn main() {"),
+            ("src/main.rs".to_string(), 12, 1)
+        );
+    }
+
+    #[test]
+    fn parse_file_without_extension() {
+        assert_eq!(
+            parse("Makefile:3:1:all: build"),
+            ("Makefile".to_string(), 3, 1)
+        );
+    }
+
+    #[test]
+    fn parse_windows_path() {
+        assert_eq!(
+            parse(r"C:\src\main.rs:12:5:fn main() {"),
+            (r"C:\src\main.rs".to_string(), 12, 5)
+        );
+    }
+
+    #[test]
+    fn split_query_on_spaces() {
+        assert_eq!(split_query("  foo bar  baz "), ["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn split_query_keeps_quoted_and_escaped_spaces() {
+        assert_eq!(
+            split_query(r#"foo "bar baz" qux\ quux"#),
+            ["foo", "bar baz", "qux quux"]
+        );
+    }
+
+    #[test]
+    fn split_query_keeps_regex_escapes() {
+        assert_eq!(split_query(r"\bfoo\b \d+"), [r"\bfoo\b", r"\d+"]);
+    }
+
+    #[test]
+    fn matches_all_ignores_location() {
+        let filters = vec![term_regex("bar"), term_regex("Baz")];
+        assert!(matches_all("src/foo.rs:1:2:foo bar Baz", &filters));
+        assert!(!matches_all("src/foo.rs:1:2:foo bar baz", &filters));
+        assert!(!matches_all("bar/Baz.rs:1:2:foo", &filters));
+    }
+
+    #[test]
+    fn parse_rejects_non_rg_line() {
+        assert!(Item::parse("no line number here").is_err());
+    }
+}
@@ -5,8 +5,6 @@ use anyhow::anyhow;
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use once_cell::sync::Lazy;
-use regex::Regex;
 use rmpv::ext::from_value;
 use serde::Deserialize;
 use serde::Serialize;
@@ -26,6 +24,7 @@ use crate::state::State;
 use crate::utils::bat;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::item;
 use crate::utils::xsel;
 
 #[derive(Clone)]
@@ -45,6 +44,9 @@ impl ModeDef for Mark {
     fn name(&self) -> &'static str {
         "mark"
     }
+    fn fzf_extra_opts(&self) -> Vec<&str> {
+        item::FZF_OPTS.to_vec()
+    }
     fn load<'a>(
         &'a self,
         config: &Config,
@@ -78,7 +80,12 @@ impl ModeDef for Mark {
                 .ok_or(anyhow!("marks not loaded"))?;
             let item = MarkItem::lookup(&marks, &item).ok_or(anyhow!("invalid item"))?;
             let file = shellexpand::tilde(&item.file).to_string();
-            let message = bat::render_file_with_highlight(file, item.line as isize).await?;
+            let message = bat::render_file_with_column_indicator(
+                &file,
+                item.line as isize,
+                item.col as usize,
+            )
+            .await?;
             Ok(PreviewResp { message })
         }
         .boxed()
@@ -115,13 +122,19 @@ impl ModeDef for Mark {
                     }.boxed()
                 })
             }],
-            "ctrl-y" => [
-                execute!(b, |_mode,_config,_state,_query,item| {
-                    let file = ITEM_PATTERN.replace(&item, "$file");
-                    xsel::yank(file).await?;
-                    Ok(())
+            "ctrl-y" => [{
+                let self_ = self.clone();
+                b.execute(move |_mode,_config,_state,_query,item| {
+                    let self_ = self_.clone();
+                    async move {
+                        let marks = self_.marks.lock().await.clone().ok_or(anyhow!("marks not loaded"))?;
+                        let mark = MarkItem::lookup(&marks, &item)
+                            .ok_or(anyhow!("invalid item"))?;
+                        xsel::yank(mark.file).await?;
+                        Ok(())
+                    }.boxed()
                 })
-            ],
+            }],
         }
     }
 }
@@ -131,10 +144,11 @@ impl ModeDef for Mark {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 async fn get_nvim_marks(nvim: &Neovim) -> Result<Vec<MarkItem>> {
-    let marks: Vec<MarkItem> = from_value::<Vec<RawMarkItem>>(nvim.eval("getmarklist()").await?)?
-        .into_iter()
-        .map(|b| b.into())
-        .collect();
+    let marks: Vec<MarkItem> =
+        from_value::<Vec<RawMarkItem>>(nvim.eval_retry("getmarklist()").await?)?
+            .into_iter()
+            .map(|b| b.into())
+            .collect();
     trace!("mark: get_nvim_marks: marks"; "marks" => Serde(marks.clone()));
     Ok(marks)
 }
@@ -149,7 +163,9 @@ async fn exec(mark: MarkItem, config: &Config, opts: ExecOpts) -> Result<()> {
             let nvim = config.nvim.clone();
             let nvim_opts = nvim::OpenOpts {
                 line: Some(mark.line as usize),
+                col: None,
                 tabedit,
+                window: None,
             };
             let file = shellexpand::tilde(&mark.file).to_string();
             let r = nvim.open(file.into(), nvim_opts).await;
@@ -161,9 +177,6 @@ async fn exec(mark: MarkItem, config: &Config, opts: ExecOpts) -> Result<()> {
     Ok(())
 }
 
-static ITEM_PATTERN: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"(?P<mark>\S+) (?P<file>\S*) (?P<line>\d+)").unwrap());
-
 #[derive(Debug, Clone, Deserialize)]
 struct RawMarkItem {
     mark: String,
@@ -181,12 +194,12 @@ struct MarkItem {
 
 impl MarkItem {
     fn render(&self) -> String {
-        format!("{:>2} {} {}", self.mark, self.file, self.line)
+        let display = format!("{:>2} {} {}", self.mark, self.file, self.line);
+        item::render_item(display, &self.mark)
     }
     fn lookup(map: &HashMap<String, Self>, item: &str) -> Option<Self> {
-        let c = ITEM_PATTERN.captures(item)?;
-        let mark = c.name("mark")?.as_str().to_owned();
-        map.get(&mark).cloned()
+        let (_, mark) = item::parse_item(item);
+        map.get(mark).cloned()
     }
 }
 
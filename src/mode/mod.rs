@@ -1,20 +1,38 @@
+pub mod actions;
 pub mod bookmark;
 pub mod browser_bookmark;
 pub mod browser_history;
 pub mod buffer;
+pub mod cargo_deps;
+pub mod clip_diff;
 pub mod diagnostics;
+pub mod dirs;
 pub mod fd;
+pub mod gh_run;
 pub mod git_branch;
+pub mod git_command;
 pub mod git_diff;
 pub mod git_log;
 pub mod git_reflog;
+pub mod git_stash;
 pub mod git_status;
+pub mod git_submodule;
+pub mod help;
+pub mod jumps;
 pub mod livegrep;
 pub mod mark;
 pub mod menu;
 pub mod mru;
+pub mod nix;
 pub mod nvim_session;
+pub mod pins;
+pub mod ports;
 pub mod process_compose;
+pub mod registers;
+pub mod snippets;
+pub mod tmux;
+pub mod todos;
+pub mod tool_versions;
 pub mod visits;
 pub mod zoxide;
 
@@ -22,14 +40,18 @@ use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use futures::Stream;
+use serde::de::DeserializeOwned;
+use std::path::Path;
 use std::pin::Pin;
 
 use crate::config::Config;
 use crate::method::LoadResp;
 use crate::method::PreviewResp;
+use crate::nvim::NeovimExt;
 use crate::state::State;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::git;
 
 pub type MkMode = Pin<Box<dyn (Fn() -> Mode) + Send + Sync>>;
 
@@ -41,28 +63,50 @@ pub fn all_modes() -> Vec<(String, MkMode)> {
     }
     let modes: Vec<MkMode> = vec![
         Box::pin(|| f(menu::Menu)),
-        Box::pin(|| f(fd::Fd)),
+        Box::pin(|| f(fd::Fd::new())),
         Box::pin(|| f(buffer::Buffer)),
         Box::pin(|| f(bookmark::Bookmark::new())),
         Box::pin(|| f(mark::Mark::new())),
+        Box::pin(|| f(jumps::Jumps::jumplist())),
+        Box::pin(|| f(jumps::Jumps::changelist())),
+        Box::pin(|| f(registers::Registers)),
+        Box::pin(|| f(help::Help::new())),
+        Box::pin(|| f(snippets::Snippets::new())),
         Box::pin(|| f(zoxide::Zoxide)),
-        Box::pin(|| f(mru::Mru)),
+        Box::pin(|| f(dirs::Dirs)),
+        Box::pin(|| f(mru::Mru::new())),
+        Box::pin(|| f(pins::Pins)),
         Box::pin(|| f(diagnostics::Diagnostics::new())),
+        Box::pin(|| f(todos::Todos)),
         Box::pin(|| f(browser_history::BrowserHistory::new())),
         Box::pin(|| f(browser_bookmark::BrowserBookmark::new())),
-        Box::pin(|| f(git_branch::GitBranch)),
-        Box::pin(|| f(git_log::GitLog::Head)),
-        Box::pin(|| f(git_log::GitLog::All)),
+        Box::pin(|| f(git_branch::GitBranch::new())),
+        Box::pin(|| f(git_command::GitCommand)),
+        Box::pin(|| f(git_log::GitLog::head())),
+        Box::pin(|| f(git_log::GitLog::all())),
+        Box::pin(|| f(git_log::GitLog::path())),
         Box::pin(|| f(git_reflog::GitReflog)),
         Box::pin(|| f(git_status::GitStatus)),
         Box::pin(|| f(git_diff::GitDiff::new())),
+        Box::pin(|| f(git_stash::GitStash)),
+        Box::pin(|| f(git_submodule::GitSubmodule)),
+        Box::pin(|| f(gh_run::GhRun)),
         Box::pin(|| f(nvim_session::NeovimSession)),
         Box::pin(|| f(livegrep::LiveGrep::new())),
         Box::pin(|| f(livegrep::LiveGrep::new_no_ignore())),
+        Box::pin(|| f(livegrep::LiveGrep::new_tracked())),
         Box::pin(|| f(livegrep::LiveGrepF)),
+        Box::pin(|| f(fd::FdF)),
         Box::pin(|| f(visits::Visits::all())),
         Box::pin(|| f(visits::Visits::project())),
+        Box::pin(|| f(ports::Ports)),
+        Box::pin(|| f(tmux::Tmux)),
+        Box::pin(|| f(tool_versions::ToolVersions)),
+        Box::pin(|| f(clip_diff::ClipDiff)),
+        Box::pin(|| f(actions::Actions::new())),
         Box::pin(|| f(process_compose::ProcessCompose::new())),
+        Box::pin(|| f(cargo_deps::CargoDeps)),
+        Box::pin(|| f(nix::Nix::new())),
     ];
     modes
         .into_iter()
@@ -80,7 +124,20 @@ impl Mode {
     }
 
     pub fn callbacks(&self) -> CallbackMap {
-        let mut callback_map = self.mode_def.fzf_bindings().1;
+        let (bindings, mut callback_map) = self.mode_def.fzf_bindings();
+        // action log に押されたキーを残すため。execute の度に bindings を作り直さない
+        for (key, actions) in bindings.0 {
+            for action in actions {
+                if let fzf::Action::Execute(cmd) | fzf::Action::ExecuteSilent(cmd) = action {
+                    if let Some(name) = cmd
+                        .split_whitespace()
+                        .find(|w| callback_map.execute.contains_key(*w))
+                    {
+                        callback_map.keys.insert(name.to_string(), key.clone());
+                    }
+                }
+            }
+        }
         callback_map.load.insert(
             "default".to_string(),
             LoadCallback {
@@ -92,8 +149,8 @@ impl Mode {
         callback_map.preview.insert(
             "default".to_string(),
             PreviewCallback {
-                callback: Box::new(|mode_def, config, win, item| {
-                    mode_def.preview(config, win, item)
+                callback: Box::new(|mode_def, config, win, item, index, payload| {
+                    mode_def.preview_with_payload(config, win, item, index, payload)
                 }),
             },
         );
@@ -106,6 +163,7 @@ impl Mode {
             myself: args.myself,
             socket: args.socket,
             log_file: args.log_file,
+            generation: args.generation,
             load: vec![
                 "load",
                 "default",
@@ -118,14 +176,56 @@ impl Mode {
             initial_prompt: self.mode_def.fzf_prompt(),
             initial_query: args.initial_query,
             bindings,
-            extra_opts: self
-                .mode_def
-                .fzf_extra_opts()
-                .into_iter()
-                .map(|s| s.to_string())
-                .collect(),
+            extra_opts: self.extra_opts(self.sort_enabled()),
         }
     }
+
+    fn extra_opts(&self, sort: bool) -> Vec<String> {
+        self.mode_def
+            .fzf_extra_opts()
+            .into_iter()
+            .chain((!sort).then_some("--no-sort"))
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    /// Whether fzf sorts the items initially (ctrl-s toggles it from this state).
+    /// Resolved in the following order:
+    /// 1. `FZFW_MODE_SORT` (e.g. `git-log=fzf,fd=none`)
+    /// 2. `ModeDef::fzf_sort`
+    /// 3. `FZFW_DEFAULT_SORT` (`fzf` | `none`)
+    /// 4. fzf (sorted)
+    fn sort_enabled(&self) -> bool {
+        resolve_sort(
+            self.name(),
+            self.mode_def.fzf_sort(),
+            std::env::var("FZFW_MODE_SORT").ok(),
+            std::env::var("FZFW_DEFAULT_SORT").ok(),
+        )
+    }
+}
+
+fn resolve_sort(
+    name: &str,
+    mode_sort: Option<bool>,
+    per_mode_env: Option<String>,
+    default_env: Option<String>,
+) -> bool {
+    let parse = |s: &str| match s.trim() {
+        "fzf" => Some(true),
+        "none" => Some(false),
+        _ => None,
+    };
+    let per_mode = per_mode_env.and_then(|v| {
+        v.split(',').find_map(|kv| match kv.split_once('=') {
+            Some((mode, sort)) if mode.trim() == name => parse(sort),
+            _ => None,
+        })
+    });
+    per_mode
+        .or(mode_sort)
+        .or_else(|| default_env.and_then(|v| parse(&v)))
+        .unwrap_or(true)
 }
 
 pub type LoadStream<'a> = Pin<Box<dyn Stream<Item = Result<LoadResp>> + Send + 'a>>;
@@ -146,6 +246,28 @@ pub trait ModeDef {
         vec![]
     }
 
+    /// Whether execute callbacks work on a fresh instance of the mode that has never loaded,
+    /// i.e. they only look at the item. The `actions` mode re-runs logged callbacks only then.
+    /// Modes that look up items in what they loaded (by `{n}`, payloads, caches) return false.
+    fn replayable(&self) -> bool {
+        true
+    }
+
+    /// Receives `change-mode --arg` (e.g. the file `git-log(path)` shows the history of).
+    /// Modes are rebuilt from their names, so this is the only way to hand them data.
+    fn set_arg(&mut self, _arg: String) {}
+
+    /// The argument given by `set_arg`, kept when the mode is rebuilt by reset-mode.
+    fn arg(&self) -> Option<String> {
+        None
+    }
+
+    /// Initial sort state of fzf. `None` follows `FZFW_DEFAULT_SORT`.
+    /// Modes whose item order is meaningful (e.g. chronological) return `Some(false)`.
+    fn fzf_sort(&self) -> Option<bool> {
+        None
+    }
+
     /// Load items into fzf
     fn load<'a>(
         &'a self,
@@ -163,6 +285,31 @@ pub trait ModeDef {
         item: String,
     ) -> BoxFuture<'a, Result<PreviewResp>>;
 
+    /// Like `preview`, but also receives the index of the item (`{n}`).
+    /// Override this instead of `preview` to look up items by index.
+    fn preview_with_index<'a>(
+        &'a self,
+        config: &'a Config,
+        win: &'a PreviewWindow,
+        item: String,
+        _index: Option<usize>,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        self.preview(config, win, item)
+    }
+
+    /// Like `preview_with_index`, but also receives the payload `load` attached to the item
+    /// with `State::set_payload`. Decode it with `decode_payload`.
+    fn preview_with_payload<'a>(
+        &'a self,
+        config: &'a Config,
+        win: &'a PreviewWindow,
+        item: String,
+        index: Option<usize>,
+        _payload: Option<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        self.preview_with_index(config, win, item, index)
+    }
+
     /// Execute the currently selected item
     /// (Optional. Intended to be used by the callback of fzf_bindings)
     fn execute<'a>(
@@ -176,17 +323,102 @@ pub trait ModeDef {
     }
 }
 
+impl dyn ModeDef + Sync + Send + '_ {
+    /// The payload `load` attached to `item` with `State::set_payload`,
+    /// for execute callbacks (e.g. `mode.payload::<Payload>(state, &item)?`).
+    pub fn payload<T: DeserializeOwned>(&self, state: &State, item: &str) -> Result<T> {
+        decode_payload(state.payload_value(item), item)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", self.name()))
+    }
+}
+
+/// Decodes the payload given to `ModeDef::preview_with_payload`.
+pub fn decode_payload<T: DeserializeOwned>(
+    payload: Option<serde_json::Value>,
+    item: &str,
+) -> Result<T> {
+    let payload = payload.ok_or(anyhow::anyhow!("no payload for item: {item}"))?;
+    Ok(serde_json::from_value(payload)?)
+}
+
+/// Reverts the last git action recorded with `git::push_undo`, after confirmation.
+pub async fn undo_git_action(config: &Config) -> Result<()> {
+    let Some(action) = git::last_undo() else {
+        return config.nvim.notify_info("nothing to undo").await;
+    };
+    let description = action.description();
+    let header = format!("undo: {description}?");
+    if !fzf::confirm(header).await? {
+        return Ok(());
+    }
+    git::pop_undo();
+    let output = git::undo(&action).await?;
+    config
+        .nvim
+        .notify_command_result(format!("undo: {description}"), output)
+        .await
+}
+
+/// Changes mode from inside an execute callback.
+/// change-mode kills the current fzf, which is waiting for the callback to finish,
+/// so it has to be requested from a detached process.
+// TODO ad-hoc なので何か考えたい
+// fzf 0.45 で追加された transform を使うのが良さそう
+pub fn spawn_change_mode(config: &Config, mode: impl Into<String>, query: impl Into<String>) {
+    spawn_change_mode_impl(config, mode.into(), query.into(), None);
+}
+
+/// `spawn_change_mode` with an argument for the new mode (see `ModeDef::set_arg`).
+pub fn spawn_change_mode_with_arg(
+    config: &Config,
+    mode: impl Into<String>,
+    query: impl Into<String>,
+    arg: impl Into<String>,
+) {
+    spawn_change_mode_impl(config, mode.into(), query.into(), Some(arg.into()));
+}
+
+fn spawn_change_mode_impl(config: &Config, mode: String, query: String, arg: Option<String>) {
+    let myself = config.myself.clone();
+    let socket = config.socket.clone();
+    tokio::spawn(async move {
+        let _ = tokio::process::Command::new(myself)
+            .arg("change-mode")
+            .arg(mode)
+            .arg(query)
+            .args(arg.map(|arg| format!("--arg={arg}")))
+            .env("FZFW_SOCKET", socket)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .output()
+            .await
+            .map_err(|e| e.to_string());
+    });
+}
+
+/// Same as `change-directory --dir` for execute callbacks: moves the cwd of
+/// fzfw and neovim to `dir` and records it in the directory history.
+pub async fn change_directory(config: &Config, dir: impl AsRef<Path>) -> Result<()> {
+    let dir = std::fs::canonicalize(dir)?;
+    crate::utils::dirs::change_to(&dir)?;
+    config.nvim.set_cwd(dir.to_string_lossy()).await
+}
+
 pub struct FzfArgs {
     pub myself: String,
     pub initial_query: String,
     pub socket: String,
     pub log_file: String,
+    pub generation: u64,
 }
 
 pub struct CallbackMap {
     pub load: std::collections::HashMap<String, LoadCallback>,
     pub preview: std::collections::HashMap<String, PreviewCallback>,
     pub execute: std::collections::HashMap<String, ExecuteCallback>,
+    pub transform: std::collections::HashMap<String, TransformCallback>,
+    /// execute callback name => the key bound to it (filled by `Mode::callbacks`)
+    pub keys: std::collections::HashMap<String, fzf::Key>,
 }
 impl CallbackMap {
     pub fn empty() -> Self {
@@ -194,8 +426,15 @@ impl CallbackMap {
             load: std::collections::HashMap::new(),
             preview: std::collections::HashMap::new(),
             execute: std::collections::HashMap::new(),
+            transform: std::collections::HashMap::new(),
+            keys: std::collections::HashMap::new(),
         }
     }
+
+    /// The key bound to the execute callback `registered_name` (e.g. `ctrl-x`).
+    pub fn key_of(&self, registered_name: &str) -> Option<&str> {
+        self.keys.get(registered_name).map(|key| key.as_str())
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -221,6 +460,8 @@ pub struct PreviewCallback {
                 &'a Config,
                 &'a PreviewWindow,
                 String,
+                Option<usize>,
+                Option<serde_json::Value>,
             ) -> BoxFuture<'a, Result<PreviewResp>>
             + Sync
             + Send,
@@ -240,16 +481,44 @@ pub struct ExecuteCallback {
             + Sync
             + Send,
     >,
+    /// Reload after the callback succeeds. Set by `ConfigBuilder::execute_refreshing`.
+    pub refresh_after: bool,
+    /// The callback looks at neither the item nor `State`, so it also runs when the list
+    /// only has the line of `LoadResp::empty_with_message`.
+    /// Set by `ConfigBuilder::execute_without_item` (and its `execute_silent` version).
+    pub without_item: bool,
+}
+
+#[allow(clippy::type_complexity)]
+pub struct TransformCallback {
+    pub callback: Box<
+        dyn for<'a> Fn(
+                &'a (dyn ModeDef + Sync + Send),
+                &'a Config,
+                &'a mut State,
+                String,
+                String,
+                Option<usize>,
+            ) -> BoxFuture<'a, Result<String>>
+            + Sync
+            + Send,
+    >,
 }
 
 pub mod config_builder {
     #![allow(dead_code)]
     use crate::config::Config;
+    use crate::method::PreviewResp;
     use crate::mode::ModeDef;
+    use crate::nvim::NeovimExt;
     use crate::state::State;
     use crate::utils::fzf;
+    use crate::utils::fzf::PreviewWindow;
+    use crate::utils::xsel;
     use anyhow::Result;
     use futures::future::BoxFuture;
+    use futures::FutureExt;
+    use tokio::process::Command;
 
     pub struct ConfigBuilder {
         pub callback_map: super::CallbackMap,
@@ -277,12 +546,8 @@ pub mod config_builder {
                 + Sync
                 + 'static,
         {
-            let name = self.gen_name();
-            let callback = Box::new(callback);
-            self.callback_map
-                .execute
-                .insert(name.clone(), super::ExecuteCallback { callback });
-            fzf::Action::Execute(format!("execute {name} {{q}} {{}}"))
+            let name = self.register_execute(callback, false, false);
+            fzf::Action::Execute(format!("execute --index {{n}} {name} {{q}} {{}}"))
         }
 
         pub fn execute_silent<F>(&mut self, callback: F) -> fzf::Action
@@ -297,17 +562,163 @@ pub mod config_builder {
                 + Send
                 + Sync
                 + 'static,
+        {
+            let name = self.register_execute(callback, false, false);
+            fzf::Action::ExecuteSilent(format!("execute --index {{n}} {name} {{q}} {{}}"))
+        }
+
+        /// Like `execute`, but the callback also runs when the list only has the line of
+        /// `LoadResp::empty_with_message`. For callbacks that look at neither the item nor
+        /// `State` (e.g. undo). Other callbacks are skipped on an empty result.
+        pub fn execute_without_item<F>(&mut self, callback: F) -> fzf::Action
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a mut State,
+                    String,
+                    String,
+                ) -> BoxFuture<'a, Result<()>>
+                + Send
+                + Sync
+                + 'static,
+        {
+            let name = self.register_execute(callback, true, false);
+            fzf::Action::Execute(format!("execute --index {{n}} {name} {{q}} {{}}"))
+        }
+
+        /// `execute_silent` version of `execute_without_item`.
+        pub fn execute_silent_without_item<F>(&mut self, callback: F) -> fzf::Action
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a mut State,
+                    String,
+                    String,
+                ) -> BoxFuture<'a, Result<()>>
+                + Send
+                + Sync
+                + 'static,
+        {
+            let name = self.register_execute(callback, true, false);
+            fzf::Action::ExecuteSilent(format!("execute --index {{n}} {name} {{q}} {{}}"))
+        }
+
+        /// Like `execute`, but asks `refresh` to reload once the callback succeeds.
+        /// For callbacks that change what the mode loads from outside fzfw
+        /// (lazygit, git commit, ...).
+        /// e.g. `"ctrl-v" => [ execute_refreshing!(b, ...), b.refresh() ]`
+        pub fn execute_refreshing<F>(&mut self, callback: F) -> fzf::Action
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a mut State,
+                    String,
+                    String,
+                ) -> BoxFuture<'a, Result<()>>
+                + Send
+                + Sync
+                + 'static,
+        {
+            let name = self.register_execute(callback, false, true);
+            fzf::Action::Execute(format!("execute --index {{n}} {name} {{q}} {{}}"))
+        }
+
+        /// `execute_silent` version of `execute_refreshing`.
+        pub fn execute_silent_refreshing<F>(&mut self, callback: F) -> fzf::Action
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a mut State,
+                    String,
+                    String,
+                ) -> BoxFuture<'a, Result<()>>
+                + Send
+                + Sync
+                + 'static,
+        {
+            let name = self.register_execute(callback, false, true);
+            fzf::Action::ExecuteSilent(format!("execute --index {{n}} {name} {{q}} {{}}"))
+        }
+
+        /// Like `execute_silent`, but also passes the selected items (`{+}`),
+        /// which the callback can read from `State::selected_items`.
+        pub fn execute_silent_multi<F>(&mut self, callback: F) -> fzf::Action
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a mut State,
+                    String,
+                    String,
+                ) -> BoxFuture<'a, Result<()>>
+                + Send
+                + Sync
+                + 'static,
+        {
+            let name = self.register_execute(callback, false, false);
+            fzf::Action::ExecuteSilent(format!("execute --index {{n}} {name} {{q}} {{}} {{+}}"))
+        }
+
+        /// Runs the fzf actions returned by `callback` (fzf's `transform`).
+        /// The callback also receives the index of the item (`{n}`).
+        pub fn transform<F>(&mut self, callback: F) -> fzf::Action
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a mut State,
+                    String,
+                    String,
+                    Option<usize>,
+                ) -> BoxFuture<'a, Result<String>>
+                + Send
+                + Sync
+                + 'static,
         {
             let name = self.gen_name();
             let callback = Box::new(callback);
             self.callback_map
-                .execute
-                .insert(name.clone(), super::ExecuteCallback { callback });
-            fzf::Action::ExecuteSilent(format!("execute {name} {{q}} {{}}"))
+                .transform
+                .insert(name.clone(), super::TransformCallback { callback });
+            fzf::Action::Transform(format!("transform --index {{n}} {name} {{q}} {{}}"))
+        }
+
+        /// Runs `toggle` without looking at the item, e.g. to flip an option of the mode.
+        /// Follow it with `reload` when the option changes what the mode loads.
+        pub fn toggle_option<F>(&mut self, toggle: F) -> fzf::Action
+        where
+            F: Fn() + Send + Sync + 'static,
+        {
+            self.execute_silent_without_item(move |_mode, _config, _state, _query, _item| {
+                toggle();
+                async { Ok(()) }.boxed()
+            })
+        }
+
+        /// Reloads if the execute callback that just ran was registered by `execute_refreshing`
+        /// (or its silent version) and succeeded, so that a failed callback keeps the list.
+        /// Put it after such an execute in the same binding. Otherwise it does nothing.
+        pub fn refresh(&mut self) -> fzf::Action {
+            // load 中は transform 自体が何もしない (server.rs)。execute は走っていた load を
+            // 止めるので、そのとき走っている load は execute の後に始まったもの
+            self.transform(|_mode, config, state, _query, _item, _index| {
+                async move {
+                    if !std::mem::take(&mut state.refresh_requested) {
+                        return Ok(String::new());
+                    }
+                    let reload = fzf::Action::Reload("load --index {n} default {q} {}".to_string());
+                    Ok(reload.render(&config.myself))
+                }
+                .boxed()
+            })
         }
 
         pub fn reload(&mut self) -> fzf::Action {
-            self.reload_raw("load default {q} {}")
+            self.reload_raw("load --index {n} default {q} {}")
         }
 
         pub fn reload_with<F>(&mut self, callback: F) -> fzf::Action
@@ -328,7 +739,34 @@ pub mod config_builder {
             self.callback_map
                 .load
                 .insert(name.clone(), super::LoadCallback { callback });
-            self.reload_raw(format!("load {name} {{q}} {{}}"))
+            self.reload_raw(format!("load --index {{n}} {name} {{q}} {{}}"))
+        }
+
+        /// Switch the preview to `callback` (until `default_preview` or mode change).
+        pub fn preview_with<F>(&mut self, callback: F) -> fzf::Action
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a PreviewWindow,
+                    String,
+                    Option<usize>,
+                    Option<serde_json::Value>,
+                ) -> BoxFuture<'a, Result<PreviewResp>>
+                + Send
+                + Sync
+                + 'static,
+        {
+            let name = self.gen_name();
+            let callback = Box::new(callback);
+            self.callback_map
+                .preview
+                .insert(name.clone(), super::PreviewCallback { callback });
+            fzf::Action::ChangePreview(format!("preview --callback {name} --index {{n}} {{}}"))
+        }
+
+        pub fn default_preview(&self) -> fzf::Action {
+            fzf::Action::ChangePreview("preview --index {n} {}".to_string())
         }
 
         pub fn reload_raw(&self, cmd: impl AsRef<str>) -> fzf::Action {
@@ -351,10 +789,22 @@ pub mod config_builder {
             ))
         }
 
+        /// Re-instantiate the current mode (dropping its caches) without restarting fzf.
+        /// Unlike `reload`, the existing mode instance is not reused.
+        pub fn reset_mode(&self) -> fzf::Action {
+            fzf::Action::ExecuteSilent("reset-mode".to_string())
+        }
+
         pub fn change_prompt(&self, prompt: impl Into<String>) -> fzf::Action {
             fzf::Action::ChangePrompt(prompt.into())
         }
 
+        /// Re-render the prompt with `ModeDef::fzf_prompt` of the current mode.
+        /// Useful when the prompt reflects mutable state of the mode.
+        pub fn refresh_prompt(&self) -> fzf::Action {
+            fzf::Action::TransformPrompt("get-prompt".to_string())
+        }
+
         pub fn toggle_sort(&self) -> fzf::Action {
             fzf::Action::ToggleSort
         }
@@ -379,6 +829,36 @@ pub mod config_builder {
             fzf::Action::Raw(cmd.into())
         }
 
+        fn register_execute<F>(
+            &mut self,
+            callback: F,
+            without_item: bool,
+            refresh_after: bool,
+        ) -> String
+        where
+            for<'a> F: Fn(
+                    &'a (dyn ModeDef + Sync + Send),
+                    &'a Config,
+                    &'a mut State,
+                    String,
+                    String,
+                ) -> BoxFuture<'a, Result<()>>
+                + Send
+                + Sync
+                + 'static,
+        {
+            let name = self.gen_name();
+            self.callback_map.execute.insert(
+                name.clone(),
+                super::ExecuteCallback {
+                    callback: Box::new(callback),
+                    refresh_after,
+                    without_item,
+                },
+            );
+            name
+        }
+
         fn gen_name(&mut self) -> String {
             self.callback_counter += 1;
             format!("callback{}", self.callback_counter)
@@ -392,7 +872,9 @@ pub mod config_builder {
          $($k:expr => [ $($v:expr),* $(,)? ] ),* $(,)?) => {{
             let (bindings, callback_map) = $init;
             let mut $builder = $crate::mode::config_builder::ConfigBuilder::new();
-            $builder.callback_counter = callback_map.execute.len() + callback_map.load.len();
+            $builder.callback_counter = callback_map.execute.len()
+                + callback_map.load.len()
+                + callback_map.transform.len();
             $builder.callback_map = callback_map;
             let bindings = bindings.merge(
                 $crate::utils::fzf::Bindings(core::convert::From::from([$(
@@ -421,6 +903,56 @@ pub mod config_builder {
     }
     pub use execute_silent;
 
+    #[macro_export]
+    macro_rules! execute_without_item {
+        ($builder:ident, |$mode:ident, $config:ident, $state:ident, $query:ident, $item:ident| $v:expr) => {
+            $builder.execute_without_item(|$mode, $config, $state, $query, $item| {
+                async move { $v }.boxed()
+            })
+        };
+    }
+    pub use execute_without_item;
+
+    #[macro_export]
+    macro_rules! execute_silent_without_item {
+        ($builder:ident, |$mode:ident, $config:ident, $state:ident, $query:ident, $item:ident| $v:expr) => {
+            $builder.execute_silent_without_item(|$mode, $config, $state, $query, $item| {
+                async move { $v }.boxed()
+            })
+        };
+    }
+    pub use execute_silent_without_item;
+
+    #[macro_export]
+    macro_rules! execute_refreshing {
+        ($builder:ident, |$mode:ident, $config:ident, $state:ident, $query:ident, $item:ident| $v:expr) => {
+            $builder.execute_refreshing(|$mode, $config, $state, $query, $item| {
+                async move { $v }.boxed()
+            })
+        };
+    }
+    pub use execute_refreshing;
+
+    #[macro_export]
+    macro_rules! execute_silent_refreshing {
+        ($builder:ident, |$mode:ident, $config:ident, $state:ident, $query:ident, $item:ident| $v:expr) => {
+            $builder.execute_silent_refreshing(|$mode, $config, $state, $query, $item| {
+                async move { $v }.boxed()
+            })
+        };
+    }
+    pub use execute_silent_refreshing;
+
+    #[macro_export]
+    macro_rules! execute_silent_multi {
+        ($builder:ident, |$mode:ident, $config:ident, $state:ident, $query:ident, $item:ident| $v:expr) => {
+            $builder.execute_silent_multi(|$mode, $config, $state, $query, $item| {
+                async move { $v }.boxed()
+            })
+        };
+    }
+    pub use execute_silent_multi;
+
     #[macro_export]
     macro_rules! select_and_execute {
         ($builder:ident, |$mode:ident, $config:ident, $state:ident, $query:ident, $item:ident|
@@ -435,15 +967,36 @@ pub mod config_builder {
     }
     pub use select_and_execute;
 
+    #[macro_export]
+    macro_rules! select_and_execute_refreshing {
+        ($builder:ident, |$mode:ident, $config:ident, $state:ident, $query:ident, $item:ident|
+         $($k:expr => $v:expr),* $(,)?) => {
+            $builder.execute_refreshing(|$mode, $config, $state, $query, $item| async move {
+                match &*$crate::utils::fzf::select(vec![$($k),*]).await? {
+                    $($k => { $v })*
+                    _ => { Ok(()) }
+                }
+            }.boxed())
+        };
+    }
+    pub use select_and_execute_refreshing;
+
     pub fn default_bindings() -> (fzf::Bindings, super::CallbackMap) {
-        bindings! {
+        let (mut bindings, callbacks) = bindings! {
             b <= (fzf::Bindings::empty(), super::CallbackMap::empty()),
             "change" => [ b.first() ],
+            // 読み込んだ件数を prompt に出す
+            "load" => [ b.refresh_prompt() ],
             "ctrl-s" => [ b.toggle_sort() ],
             "ctrl-r" => [
                 b.reload(),
                 b.clear_screen(),
             ],
+            "ctrl-alt-r" => [
+                b.reset_mode(),
+                b.reload(),
+                b.clear_screen(),
+            ],
             "shift-right" => [
                 b.raw("change-preview-window[bottom:90%:border-top|right:50%:noborder]"),
             ],
@@ -451,7 +1004,7 @@ pub mod config_builder {
                 b.change_mode(super::menu::Menu.name(), false),
             ],
             "ctrl-f" => [
-                b.change_mode(super::fd::Fd.name(), false),
+                b.change_mode(super::fd::Fd::new().name(), false),
             ],
             "ctrl-h" => [
                 b.change_mode(super::visits::Visits::project().name(), false),
@@ -466,10 +1019,10 @@ pub mod config_builder {
                 b.change_mode(super::git_diff::GitDiff::new().name(), false),
             ],
             "ctrl-k" => [
-                b.change_mode(super::git_branch::GitBranch.name(), false),
+                b.change_mode(super::git_branch::GitBranch::new().name(), false),
             ],
             "ctrl-o" => [
-                b.change_mode(super::git_log::GitLog::Head.name(), false),
+                b.change_mode(super::git_log::GitLog::head().name(), false),
             ],
             "ctrl-g" => [
                 b.change_mode(super::livegrep::LiveGrep::new().name(), true),
@@ -477,18 +1030,61 @@ pub mod config_builder {
             "alt-d" => [
                 b.change_mode(super::zoxide::Zoxide.name(), false),
             ],
+            "alt-e" => [
+                b.change_mode(super::dirs::Dirs.name(), false),
+            ],
             "alt-h" => [
                 b.change_mode(super::visits::Visits::all().name(), false),
             ],
             "alt-w" => [
                 b.change_mode(super::diagnostics::Diagnostics::new().name(), false),
             ],
+            "alt-o" => [
+                b.change_mode(super::todos::Todos.name(), false),
+            ],
+            "f1" => [
+                b.change_mode(super::help::Help::new().name(), false),
+            ],
             "ctrl-alt-h" => [
                 b.change_mode(super::browser_history::BrowserHistory::new().name(), false),
             ],
             "ctrl-alt-n" => [
                 b.change_mode(super::browser_bookmark::BrowserBookmark::new().name(), false),
             ],
+            "alt-g" => [
+                execute_without_item!(b, |_mode,config,_state,_query,_item| {
+                    let modes = config.get_mode_names();
+                    let mode = fzf::select_with_header("select mode", modes).await?;
+                    if !mode.is_empty() {
+                        super::spawn_change_mode(config, mode, "");
+                    }
+                    Ok(())
+                }),
+            ],
+            "alt-G" => [
+                // カーソル下の単語で livegrep する。単語が無ければ何もしない
+                execute_silent_without_item!(b, |_mode,config,_state,_query,_item| {
+                    let word = config.nvim.cword().await?;
+                    if !word.is_empty() {
+                        super::spawn_change_mode(config, super::livegrep::LiveGrep::new().name(), word);
+                    }
+                    Ok(())
+                }),
+            ],
+            "alt-t" => [
+                // 現在の mode で独立した picker (別サーバー) を開く
+                execute_without_item!(b, |mode,config,_state,_query,_item| {
+                    Command::new(&config.myself)
+                        .env("FZFW_INITIAL_MODE", mode.name())
+                        .env("NVIM", &config.nvim_address)
+                        .env_remove("FZFW_SOCKET")
+                        .args(vec!["--fzfw-self", &config.myself])
+                        .spawn()?
+                        .wait()
+                        .await?;
+                    Ok(())
+                }),
+            ],
             "ctrl-u" => [
                 b.execute_silent_raw("change-directory --to-parent"),
                 b.reload(),
@@ -502,6 +1098,91 @@ pub mod config_builder {
                 b.execute_silent_raw("change-directory --to-last-file-dir"),
                 b.reload(),
             ],
+            "alt-y" => [
+                execute_silent_without_item!(b, |_mode,_config,_state,query,_item| {
+                    xsel::yank(query).await?;
+                    Ok(())
+                }),
+            ],
+            "alt-/" => [
+                // neovim の検索レジスタに入れる。正規表現ではなく文字列として検索されるようにする
+                execute_silent_without_item!(b, |_mode,config,_state,query,_item| {
+                    config
+                        .nvim
+                        .eval_lua_with_args(
+                            r"vim.fn.setreg('/', '\\V' .. vim.fn.escape(..., '\\/'))",
+                            vec![query.into()],
+                        )
+                        .await?;
+                    Ok(())
+                }),
+            ],
+        };
+        // config.toml の mode_keys で上書き
+        let b = ConfigBuilder::new();
+        for (key, mode) in &crate::config::file().mode_keys {
+            bindings
+                .0
+                .insert(key.clone(), vec![b.change_mode(mode, false)]);
         }
+        (bindings, callbacks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn default_sort_env_applies_unless_mode_opts_out() {
+        assert!(resolve_sort("fd", None, None, None));
+        assert!(!resolve_sort("fd", None, None, env("none")));
+        assert!(resolve_sort("fd", None, None, env("fzf")));
+        assert!(resolve_sort("fd", None, None, env("bogus")));
+        // git-log などは時系列を保つ
+        assert!(!resolve_sort("git-log", Some(false), None, env("fzf")));
+    }
+
+    #[test]
+    fn per_mode_sort_env_wins() {
+        let per_mode = env("git-log=fzf, fd = none");
+        assert!(resolve_sort("git-log", Some(false), per_mode.clone(), None));
+        assert!(!resolve_sort("fd", None, per_mode.clone(), env("fzf")));
+        assert!(!resolve_sort("livegrep", None, per_mode, env("none")));
+    }
+
+    #[test]
+    fn refresh_after_is_chosen_at_registration() {
+        use config_builder::*;
+
+        let (_, callbacks) = bindings! {
+            b <= (fzf::Bindings::empty(), CallbackMap::empty()),
+            "ctrl-v" => [
+                execute_refreshing!(b, |_mode,_config,_state,_query,_item| Ok(())),
+                b.refresh(),
+            ],
+            // refresh の位置で他の callback に付いたりしない
+            "ctrl-a" => [
+                b.refresh(),
+                execute!(b, |_mode,_config,_state,_query,_item| Ok(())),
+            ],
+        };
+        let refresh_after = |name: &str| callbacks.execute[name].refresh_after;
+        assert!(refresh_after("callback1"));
+        assert!(!refresh_after("callback4"));
+        assert_eq!(callbacks.transform.len(), 2);
+    }
+
+    #[test]
+    fn no_sort_is_passed_only_when_sort_is_off() {
+        let mode = Mode {
+            mode_def: Box::new(fd::Fd::new()),
+        };
+        assert!(!mode.extra_opts(true).contains(&"--no-sort".to_string()));
+        assert!(mode.extra_opts(false).contains(&"--no-sort".to_string()));
     }
 }
@@ -6,8 +6,8 @@ use futures::FutureExt;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use rmpv::ext::from_value;
-use serde::Deserialize;
-use serde::Serialize;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 use crate::config::Config;
 use crate::logger::Serde;
@@ -21,12 +21,36 @@ use crate::nvim::Neovim;
 use crate::nvim::NeovimExt;
 use crate::state::State;
 use crate::utils::bat;
+use crate::utils::dedup;
+use crate::utils::file_sort::FileSort;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::icons;
+use crate::utils::path::parent_dir;
+use crate::utils::pins;
 use crate::utils::xsel;
 
 #[derive(Clone)]
-pub struct Mru;
+pub struct Mru {
+    sort: Arc<Mutex<FileSort>>,
+}
+
+impl Mru {
+    pub fn new() -> Self {
+        Self {
+            sort: Arc::new(Mutex::new(FileSort::default())),
+        }
+    }
+
+    fn sort(&self) -> FileSort {
+        *self.sort.lock().unwrap()
+    }
+
+    fn toggle_sort(&self) {
+        let mut sort = self.sort.lock().unwrap();
+        *sort = sort.next();
+    }
+}
 
 static ITEM_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"\s*(?P<bufnr>\d+):(?P<path>.*)").unwrap());
@@ -35,16 +59,24 @@ impl ModeDef for Mru {
     fn name(&self) -> &'static str {
         "mru"
     }
+    fn fzf_prompt(&self) -> String {
+        match self.sort().label() {
+            Some(label) => format!("{}[{label}]>", self.name()),
+            None => format!("{}>", self.name()),
+        }
+    }
     fn load(
         &self,
         config: &Config,
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         let nvim = config.nvim.clone();
+        let sort = self.sort();
         Box::pin(async_stream::stream! {
-            let mru_items = get_nvim_oldefiles(&nvim).await?;
+            let mut mru_items = get_nvim_oldefiles(&nvim).await?;
+            sort.sort(&mut mru_items);
             yield Ok(LoadResp::new_with_default_header(mru_items))
         })
     }
@@ -55,6 +87,7 @@ impl ModeDef for Mru {
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
         async move {
+            let item = strip(&item).to_string();
             let bufnr = ITEM_PATTERN.replace(&item, "$bufnr").into_owned();
             let path = ITEM_PATTERN.replace(&item, "$path").into_owned();
             trace!("mru: preview"; "bufnr" => bufnr, "path" => &path);
@@ -92,10 +125,32 @@ impl ModeDef for Mru {
             ],
             "ctrl-y" => [
                 execute!(b, |_mode,_config,_state,_query,item| {
-                    xsel::yank(item).await?;
+                    xsel::yank(strip(&item)).await?;
                     Ok(())
                 })
             ],
+            "ctrl-p" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    pins::toggle(strip(&item))?;
+                    Ok(())
+                }),
+                b.reload(),
+            ],
+            "alt-m" => [
+                {
+                    let self_ = self.clone();
+                    b.toggle_option(move || self_.toggle_sort())
+                },
+                b.reload(),
+                b.refresh_prompt(),
+            ],
+            "pgup" => [
+                select_and_execute!{b, |_mode,config,_state,_query,item|
+                    "oil" => {
+                        config.nvim.open_oil(parent_dir(strip(&item))).await
+                    },
+                }
+            ],
         }
     }
 }
@@ -116,32 +171,33 @@ async fn get_nvim_oldefiles(nvim: &Neovim) -> Result<Vec<String>> {
         .collect::<Vec<_>>()
         .await;
     info!("mru: get_nvim_oldefiles: mrus"; "mrus" => Serde(mrus.clone()));
-    Ok(mrus)
-}
-
-// :h getbufinfo() から抜粋
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct MruItem {
-    bufnr: u64,
-    name: String,
-    lnum: u64,
-    lastused: u64,
-    listed: u64,
-    hidden: u64,
-    loaded: u64,
+    let pinned = pins::Pinned::load();
+    let mrus = mrus
+        .into_iter()
+        .map(|m| pinned.decorate(icons::decorate(&m), &m))
+        .collect();
+    Ok(dedup::dedup_items(mrus))
 }
 
 struct OpenOpts {
     tabedit: bool,
 }
 
+// アイコンと dedup の回数 (x3) を外す
+fn strip(item: &str) -> &str {
+    icons::strip(dedup::strip(item))
+}
+
 async fn open(config: &Config, item: String, opts: OpenOpts) -> Result<()> {
-    let bufnr = ITEM_PATTERN.replace(&item, "$bufnr").into_owned();
+    let item = strip(&item);
+    let bufnr = ITEM_PATTERN.replace(item, "$bufnr").into_owned();
     let OpenOpts { tabedit } = opts;
     let nvim = config.nvim.clone();
     let nvim_opts = nvim::OpenOpts {
         line: None,
+        col: None,
         tabedit,
+        window: None,
     };
     nvim.open(bufnr.into(), nvim_opts).await?;
     Ok(())
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::xsel;
+
+#[derive(Clone)]
+pub struct Nix {
+    // preview の度に nix flake show を走らせないよう load の結果を使い回す
+    flake: Arc<Mutex<Option<dto::FlakeShow>>>,
+}
+
+impl Nix {
+    pub fn new() -> Self {
+        Self {
+            flake: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+mod dto {
+    use std::collections::BTreeMap;
+
+    // nix flake show --json の出力から必要な部分だけ
+    #[derive(serde::Deserialize, Debug)]
+    pub(crate) struct FlakeShow {
+        #[serde(default)]
+        pub(crate) packages: BTreeMap<String, BTreeMap<String, Output>>,
+        #[serde(default, rename = "devShells")]
+        pub(crate) dev_shells: BTreeMap<String, BTreeMap<String, Output>>,
+    }
+    #[derive(serde::Deserialize, Debug)]
+    pub(crate) struct Output {
+        #[serde(rename = "type")]
+        pub(crate) type_: Option<String>,
+        pub(crate) name: Option<String>,
+        pub(crate) description: Option<String>,
+    }
+}
+
+const NOT_A_FLAKE: &str = "(not in a nix flake)";
+
+impl ModeDef for Nix {
+    fn name(&self) -> &'static str {
+        "nix"
+    }
+    fn load<'a>(
+        &'a self,
+        _config: &'a Config,
+        _state: &'a mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'a> {
+        Box::pin(async_stream::stream! {
+            let mut cache = self.flake.lock().await;
+            *cache = None;
+            match flake_show().await {
+                Ok(flake) => {
+                    let items = flake_attrs(&flake)
+                        .into_iter()
+                        .map(|(attr, _)| attr)
+                        .collect();
+                    *cache = Some(flake);
+                    drop(cache);
+                    yield Ok(LoadResp::new_with_default_header(items))
+                }
+                Err(e) => {
+                    info!("nix: flake show failed"; "error" => e.to_string());
+                    yield Ok(LoadResp::new_with_default_header(vec![NOT_A_FLAKE.to_string()]))
+                }
+            }
+        })
+    }
+    fn preview<'a>(
+        &'a self,
+        _config: &'a Config,
+        _win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            if item == NOT_A_FLAKE {
+                return Ok(PreviewResp {
+                    message: "Run fzfw in a directory containing flake.nix".to_string(),
+                });
+            }
+            let flake = self.flake.lock().await;
+            let flake = flake
+                .as_ref()
+                .ok_or(anyhow::anyhow!("nix flake not loaded"))?;
+            let message = match flake_attrs(flake).into_iter().find(|(a, _)| *a == item) {
+                Some((attr, output)) => format!(
+                    "ATTR:        {attr}\nTYPE:        {}\nNAME:        {}\nDESCRIPTION: {}",
+                    output.type_.as_deref().unwrap_or("-"),
+                    output.name.as_deref().unwrap_or("-"),
+                    output.description.as_deref().unwrap_or("-"),
+                ),
+                None => "No description".to_string(),
+            };
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                select_and_execute!{b, |_mode,config,_state,_query,item|
+                    "build" => {
+                        let output = Command::new("nix")
+                            .arg("build")
+                            .arg(format!(".#{item}"))
+                            .output()
+                            .await?;
+                        config.nvim.notify_command_result(format!("nix build .#{item}"), output)
+                            .await
+                    },
+                    "run" => {
+                        Command::new("nix")
+                            .arg("run")
+                            .arg(format!(".#{item}"))
+                            .spawn()?
+                            .wait()
+                            .await?;
+                        Ok(())
+                    },
+                    "develop" => {
+                        let _ = config.nvim.hide_floaterm().await;
+                        config.nvim.command(&format!("FloatermNew nix develop .#{item}"))
+                            .await?;
+                        Ok(())
+                    },
+                }
+            ],
+            "ctrl-y" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    xsel::yank(format!(".#{item}")).await?;
+                    Ok(())
+                })
+            ],
+        }
+    }
+}
+
+async fn flake_show() -> Result<dto::FlakeShow> {
+    let output = Command::new("nix")
+        .arg("flake")
+        .arg("show")
+        .arg("--json")
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{}",
+            String::from_utf8_lossy(output.stderr.as_slice())
+        ));
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+// packages.<system>.<name> と devShells.<system>.<name> を列挙する
+fn flake_attrs(flake: &dto::FlakeShow) -> Vec<(String, &dto::Output)> {
+    fn attrs<'a>(
+        prefix: &str,
+        outputs: &'a BTreeMap<String, BTreeMap<String, dto::Output>>,
+    ) -> Vec<(String, &'a dto::Output)> {
+        outputs
+            .iter()
+            .flat_map(|(system, outputs)| {
+                outputs
+                    .iter()
+                    .map(move |(name, output)| (format!("{prefix}.{system}.{name}"), output))
+            })
+            .collect()
+    }
+    let mut result = attrs("packages", &flake.packages);
+    result.extend(attrs("devShells", &flake.dev_shells));
+    result
+}
@@ -1,10 +1,11 @@
 use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
 
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 
-use crate::bindings;
 use crate::config::Config;
 use crate::method::LoadResp;
 use crate::method::PreviewResp;
@@ -34,6 +35,17 @@ impl ModeDef for NeovimSession {
                     Ok(())
                 })
             ],
+            "ctrl-x" => [
+                execute_silent!(b, |_mode,config,_state,_query,session| {
+                    let header = format!("delete session {session}?");
+                    if !fzf::confirm(header).await? {
+                        return Ok(());
+                    }
+                    fs::remove_file(session_dir().join(&session))?;
+                    config.nvim.notify_info(format!("deleted session {session}")).await
+                }),
+                b.reload(),
+            ],
             "space" => [
                 select_and_execute!{b, |_mode,config,_state,_query,session|
                     "switch" => {
@@ -54,13 +66,19 @@ impl ModeDef for NeovimSession {
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         Box::pin(async_stream::stream! {
-            let home = std::env::var("HOME").unwrap();
             // わざわざ tokio::fs にしなくていいかな
-            let sessions = fs::read_dir(format!("{home}/.local/share/nvim/session"))?
-                .filter_map(|x| x.ok().and_then(|x| x.file_name().into_string().ok()))
-                .collect::<Vec<String>>();
+            let mut sessions = fs::read_dir(session_dir())?
+                .filter_map(|x| x.ok())
+                .filter_map(|x| {
+                    let mtime = x.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+                    x.file_name().into_string().ok().map(|name| (mtime, name))
+                })
+                .collect::<Vec<_>>();
+            // 新しいものから
+            sessions.sort_by_key(|(mtime, _)| std::cmp::Reverse(*mtime));
+            let sessions = sessions.into_iter().map(|(_, name)| name).collect();
             yield Ok(LoadResp::new_with_default_header(sessions))
         })
     }
@@ -68,15 +86,89 @@ impl ModeDef for NeovimSession {
         &self,
         _config: &Config,
         _win: &PreviewWindow,
-        _item: String,
+        item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
         async move {
+            let content = tokio::fs::read_to_string(session_dir().join(&item)).await?;
+            let summary = SessionSummary::parse(&content);
             Ok(PreviewResp {
-                message: "No description".to_string(),
+                message: summary.render(),
             })
         }
         .boxed()
     }
+    fn fzf_sort(&self) -> Option<bool> {
+        Some(false)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn session_dir() -> PathBuf {
+    let home = std::env::var("HOME").unwrap();
+    PathBuf::from(format!("{home}/.local/share/nvim/session"))
+}
+
+/// What a session file (`:mksession` output) restores.
+#[derive(Debug, Default)]
+struct SessionSummary {
+    cwd: Option<String>,
+    files: Vec<String>,
+}
+
+impl SessionSummary {
+    fn parse(content: &str) -> Self {
+        let mut summary = SessionSummary::default();
+        for line in content.lines() {
+            let Some((cmd, arg)) = line.split_once(' ') else {
+                continue;
+            };
+            match cmd {
+                // 最初の cd が作業ディレクトリ
+                "cd" if summary.cwd.is_none() => {
+                    summary.cwd = Some(unescape(arg));
+                }
+                // badd +12 path / edit path
+                "badd" | "edit" => {
+                    let file = match arg.split_once(' ') {
+                        Some((pos, file)) if pos.starts_with('+') => file,
+                        _ => arg,
+                    };
+                    let file = unescape(file);
+                    if !summary.files.contains(&file) {
+                        summary.files.push(file);
+                    }
+                }
+                _ => {}
+            }
+        }
+        summary
+    }
+
+    fn render(&self) -> String {
+        let mut lines = vec![format!(
+            "cwd: {}",
+            self.cwd.as_deref().unwrap_or("(unknown)")
+        )];
+        lines.push(format!("buffers ({}):", self.files.len()));
+        lines.extend(self.files.iter().map(|f| format!("  {f}")));
+        lines.join("\n")
+    }
+}
+
+// mksession は空白などを `\ ` のようにエスケープする
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
 }
 
 async fn session_command(nvim: &Neovim, action: &str, session: String) {
@@ -100,3 +192,30 @@ async fn session_command(nvim: &Neovim, action: &str, session: String) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_summary_collects_cwd_and_buffers() {
+        let content = r#"let SessionLoad = 1
+cd ~/src/my\ project
+cd /somewhere/else
+badd +12 src/main.rs
+badd +1 docs/user\ guide.md
+edit src/main.rs
+argglobal
+"#;
+        let summary = SessionSummary::parse(content);
+        assert_eq!(summary.cwd.as_deref(), Some("~/src/my project"));
+        assert_eq!(summary.files, ["src/main.rs", "docs/user guide.md"]);
+        assert_eq!(
+            summary.render(),
+            "cwd: ~/src/my project\nbuffers (2):\n  src/main.rs\n  docs/user guide.md"
+        );
+
+        let summary = SessionSummary::parse("");
+        assert_eq!(summary.render(), "cwd: (unknown)\nbuffers (0):");
+    }
+}
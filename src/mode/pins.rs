@@ -0,0 +1,129 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rmpv::ext::from_value;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim;
+use crate::nvim::Neovim;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::bat;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::icons;
+use crate::utils::path::to_abspath;
+use crate::utils::path::to_relpath;
+use crate::utils::pins;
+use crate::utils::xsel;
+
+/// Pinned files (ctrl-p in fd / mru / visits) followed by neovim's oldfiles
+#[derive(Clone)]
+pub struct Pins;
+
+impl ModeDef for Pins {
+    fn name(&self) -> &'static str {
+        "pins"
+    }
+    fn fzf_sort(&self) -> Option<bool> {
+        // pin したファイルを先頭に保つ
+        Some(false)
+    }
+    fn load(
+        &self,
+        config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        let nvim = config.nvim.clone();
+        Box::pin(async_stream::stream! {
+            let pinned = pins::list()?
+                .into_iter()
+                .filter(|p| std::path::Path::new(p).is_file())
+                .collect::<Vec<_>>();
+            let oldfiles = oldfiles(&nvim)
+                .await?
+                .into_iter()
+                .filter(|f| !pinned.contains(&to_abspath(f)));
+            let items = pinned
+                .iter()
+                .map(|p| format!("{}{}", pins::MARK, icons::decorate(to_relpath(p))))
+                .chain(oldfiles.map(|f| icons::decorate(to_relpath(f))))
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no pinned files"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        _win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        async move {
+            let message = bat::render_file(icons::strip(&item)).await?;
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    open(&config.nvim, &item, false).await
+                })
+            ],
+            "ctrl-t" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    open(&config.nvim, &item, true).await
+                })
+            ],
+            "ctrl-p" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    pins::toggle(icons::strip(&item))?;
+                    Ok(())
+                }),
+                b.reload(),
+            ],
+            "ctrl-y" => [
+                execute!(b, |_mode,_config,_state,_query,item| {
+                    xsel::yank(icons::strip(&item)).await?;
+                    Ok(())
+                })
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+async fn oldfiles(nvim: &Neovim) -> Result<Vec<String>> {
+    let oldfiles: Vec<String> = from_value(nvim.eval_retry("v:oldfiles").await?)?;
+    Ok(oldfiles
+        .into_iter()
+        .filter(|f| std::path::Path::new(f).is_file())
+        .collect())
+}
+
+async fn open(nvim: &Neovim, item: &str, tabedit: bool) -> Result<()> {
+    let opts = nvim::OpenOpts {
+        line: None,
+        col: None,
+        tabedit,
+        window: None,
+    };
+    nvim.open(icons::strip(item).to_string().into(), opts).await
+}
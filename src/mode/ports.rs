@@ -0,0 +1,151 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// listen しているソケットの一覧 (`ss -tulpn`)
+#[derive(Clone)]
+pub struct Ports;
+
+impl ModeDef for Ports {
+    fn name(&self) -> &'static str {
+        "ports"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let output = Command::new("ss").arg("-tulpn").output().await?;
+            if !output.status.success() {
+                Err(anyhow!("ss: {}", String::from_utf8_lossy(&output.stderr).trim()))?;
+            }
+            let items = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .skip(1) // header
+                .filter_map(Socket::parse)
+                .map(|s| s.render())
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no listening sockets"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        _win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        async move {
+            let Some(pid) = parse_pid(&item) else {
+                return Ok(PreviewResp {
+                    message: "no process info (owned by another user?)".to_string(),
+                });
+            };
+            let output = command::output_with_timeout(
+                "preview",
+                Command::new("ps")
+                    .arg("-o")
+                    .arg("pid,ppid,user,etime,args")
+                    .arg("-p")
+                    .arg(pid.to_string()),
+                command::preview_timeout(),
+            )
+            .await?;
+            let mut message = String::from_utf8_lossy(&output.stdout).into_owned();
+            if let Ok(cwd) = std::fs::read_link(format!("/proc/{pid}/cwd")) {
+                message.push_str(&format!("\ncwd: {}\n", cwd.display()));
+            }
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                select_and_execute!{b, |_mode,config,_state,_query,item|
+                    "kill owning process" => {
+                        let Some(pid) = parse_pid(&item) else {
+                            return config.nvim.notify_warn("ports: no pid (owned by another user?)").await;
+                        };
+                        if !fzf::confirm(format!("kill {pid}?")).await? {
+                            return Ok(());
+                        }
+                        if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+                            let e = std::io::Error::last_os_error();
+                            return config.nvim.notify_error(format!("kill {pid}: {e}")).await;
+                        }
+                        config.nvim.notify_info(format!("sent SIGTERM to {pid}")).await
+                    },
+                },
+                b.reload(),
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+struct Socket {
+    proto: String,
+    local: String,
+    // 他ユーザーのプロセスは root でないと見えない
+    process: Option<(i32, String)>,
+}
+
+impl Socket {
+    // Netid State Recv-Q Send-Q Local_Address:Port Peer_Address:Port [Process]
+    fn parse(line: &str) -> Option<Self> {
+        static PROCESS: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r#"users:\(\("(?P<name>[^"]*)",pid=(?P<pid>\d+)"#).unwrap());
+        let columns = line.split_whitespace().collect::<Vec<_>>();
+        let (proto, local) = (columns.first()?, columns.get(4)?);
+        let process = PROCESS.captures(line).and_then(|c| {
+            let pid = c["pid"].parse().ok()?;
+            Some((pid, c["name"].to_string()))
+        });
+        Some(Socket {
+            proto: proto.to_string(),
+            local: local.to_string(),
+            process,
+        })
+    }
+
+    fn render(&self) -> String {
+        let process = match &self.process {
+            Some((pid, name)) => format!("{pid}/{name}"),
+            None => "-".to_string(),
+        };
+        format!("{:<5} {:<28} {}", self.proto, self.local, process)
+    }
+}
+
+fn parse_pid(item: &str) -> Option<i32> {
+    let process = item.split_whitespace().nth(2)?;
+    process.split_once('/')?.0.parse().ok()
+}
@@ -2,9 +2,7 @@ use anyhow::anyhow;
 use anyhow::Result;
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use unicode_width::UnicodeWidthStr;
 
-use crate::bindings;
 use crate::config::Config;
 use crate::method::LoadResp;
 use crate::method::PreviewResp;
@@ -12,6 +10,7 @@ use crate::mode::config_builder;
 use crate::mode::CallbackMap;
 use crate::mode::ModeDef;
 use crate::state::State;
+use crate::utils::ansi;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
 
@@ -59,7 +58,7 @@ impl ModeDef for ProcessCompose {
         _state: &'a mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'a> {
         Box::pin(async_stream::stream! {
             let host = get_host()?;
             if _query == "delay" {
@@ -100,7 +99,7 @@ impl ModeDef for ProcessCompose {
             // 折返しを考慮した上で再度高々lines行だけ残す
             let mut logs = logs
                 .iter()
-                .flat_map(|s| wrap(s, win.columns))
+                .flat_map(|s| ansi::wrap(s, win.columns))
                 .collect::<Vec<_>>();
             let offset = if logs.len() > lines {
                 logs.len() - lines
@@ -146,7 +145,8 @@ impl ModeDef for ProcessCompose {
                 b.reload()
             ],
             "load" => [
-                b.reload_raw("load default delay {}")
+                b.reload_raw("load --index {n} default delay {}"),
+                b.refresh_prompt(),
             ]
         }
     }
@@ -188,25 +188,3 @@ async fn stop(item: Item) -> Result<()> {
         .await?;
     Ok(())
 }
-
-// wrap("foobar", 3) => ["foo", "bar"]
-// wrap("犬猫", 3) => ["犬", "猫"]
-fn wrap(s: &str, columns: usize) -> Vec<String> {
-    let mut result = Vec::new();
-    let mut chunk = String::new();
-    let mut width = 0;
-    for c in s.chars() {
-        let c_width = UnicodeWidthStr::width(c.to_string().as_str());
-        if width + c_width > columns {
-            result.push(chunk);
-            chunk = String::new();
-            width = 0;
-        }
-        chunk.push(c);
-        width += c_width;
-    }
-    if !chunk.is_empty() {
-        result.push(chunk);
-    }
-    result
-}
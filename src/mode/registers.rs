@@ -0,0 +1,156 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rmpv::ext::from_value;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::Neovim;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::ansi;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::xsel;
+
+#[derive(Clone)]
+pub struct Registers;
+
+// 式レジスタ (=) は評価が走るので除外
+const REGISTER_NAMES: &str = "\"0123456789abcdefghijklmnopqrstuvwxyz-*+.:%/#";
+
+impl ModeDef for Registers {
+    fn name(&self) -> &'static str {
+        "registers"
+    }
+    fn load(
+        &self,
+        config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        let nvim = config.nvim.clone();
+        Box::pin(async_stream::stream! {
+            let registers = get_registers(&nvim).await?;
+            if registers.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no registers"));
+            } else {
+                let items = registers.iter().map(Register::render).collect();
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview<'a>(
+        &'a self,
+        config: &'a Config,
+        win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            let register = get_register(&config.nvim, Register::parse_name(&item)?).await?;
+            let message = register
+                .contents
+                .iter()
+                .flat_map(|line| ansi::wrap(line, win.columns))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let name = Register::parse_name(&item)?;
+                    let nvim = config.nvim.clone();
+                    nvim.stop_insert().await?;
+                    nvim.hide_floaterm().await?;
+                    // 直前にいた buffer に貼り付ける
+                    nvim.command(&format!("execute 'normal! \"{name}p'")).await?;
+                    Ok(())
+                })
+            ],
+            "ctrl-y" => [
+                execute_silent!(b, |_mode,config,_state,_query,item| {
+                    let register = get_register(&config.nvim, Register::parse_name(&item)?).await?;
+                    xsel::yank(register.contents.join("\n")).await?;
+                    Ok(())
+                })
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Deserialize)]
+struct Register {
+    name: char,
+    contents: Vec<String>,
+    // "v" (charwise), "V" (linewise) or "<C-v>{width}" (blockwise)
+    regtype: String,
+}
+
+impl Register {
+    fn render(&self) -> String {
+        let regtype = match self.regtype.chars().next() {
+            Some('v') => "char ",
+            Some('V') => "line ",
+            Some('\x16') => "block",
+            _ => "?    ",
+        };
+        // 改行は ⏎ に置き換えて1行に収める
+        let contents = self.contents.join("⏎");
+        let contents = contents.chars().take(200).collect::<String>();
+        format!("\"{}  {}  {}", self.name, regtype, contents)
+    }
+    fn parse_name(item: &str) -> Result<char> {
+        item.strip_prefix('"')
+            .and_then(|s| s.chars().next())
+            .ok_or(anyhow!("invalid item: {item}"))
+    }
+}
+
+async fn get_registers(nvim: &Neovim) -> Result<Vec<Register>> {
+    let registers = nvim
+        .eval_lua_with_args(
+            r#"
+            local names = ...
+            local registers = {}
+            for _, name in ipairs(vim.split(names, '')) do
+              local ok, info = pcall(vim.fn.getreginfo, name)
+              if ok and info.regcontents and #info.regcontents > 0 then
+                table.insert(registers, {
+                  name = name,
+                  contents = info.regcontents,
+                  regtype = info.regtype,
+                })
+              end
+            end
+            return registers
+            "#,
+            vec![REGISTER_NAMES.into()],
+        )
+        .await?;
+    Ok(from_value(registers)?)
+}
+
+async fn get_register(nvim: &Neovim, name: char) -> Result<Register> {
+    get_registers(nvim)
+        .await?
+        .into_iter()
+        .find(|r| r.name == name)
+        .ok_or(anyhow!("register {name} is empty"))
+}
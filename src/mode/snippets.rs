@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::config;
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::Neovim;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::bat;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// Snippets in `$XDG_CONFIG_HOME/fzfw/snippets/*.json`
+///
+/// ```json
+/// [
+///   { "name": "main function", "prefix": "main", "body": ["fn main() {", "    $0", "}"] }
+/// ]
+/// ```
+///
+/// `filetype` defaults to the file stem (`rust.json` => `rust`).
+#[derive(Clone)]
+pub struct Snippets {
+    // fzf 上で n 番目 ({n}) の item が items[n]
+    items: Arc<Mutex<Option<Vec<Snippet>>>>,
+}
+
+impl Snippets {
+    pub fn new() -> Self {
+        Self {
+            items: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn lookup(&self, index: Option<usize>) -> Result<Snippet> {
+        let items = self.items.lock().await;
+        let items = items.as_ref().ok_or(anyhow!("snippets not loaded"))?;
+        index
+            .and_then(|n| items.get(n))
+            .cloned()
+            .ok_or(anyhow!("no snippet at index {index:?}"))
+    }
+}
+
+impl ModeDef for Snippets {
+    fn name(&self) -> &'static str {
+        "snippets"
+    }
+    fn replayable(&self) -> bool {
+        false
+    }
+    fn load<'a>(
+        &'a self,
+        _config: &Config,
+        _state: &'a mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'a> {
+        Box::pin(async_stream::stream! {
+            let snippets = load_snippets()?;
+            let items = snippets.iter().map(Snippet::render).collect::<Vec<_>>();
+            self.items.lock().await.replace(snippets);
+            if items.is_empty() {
+                let dir = snippets_dir().unwrap_or_default();
+                yield Ok(LoadResp::empty_with_message(format!("no snippets in {dir}")));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview<'a>(
+        &'a self,
+        config: &'a Config,
+        win: &'a PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        self.preview_with_index(config, win, item, None)
+    }
+    fn preview_with_index<'a>(
+        &'a self,
+        _config: &'a Config,
+        _win: &'a PreviewWindow,
+        _item: String,
+        index: Option<usize>,
+    ) -> BoxFuture<'a, Result<PreviewResp>> {
+        async move {
+            let snippet = self.lookup(index).await?;
+            let message = bat::render_text(snippet.body(), snippet.filetype.as_deref()).await?;
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [{
+                let self_ = self.clone();
+                b.execute(move |_mode,config,state,_query,_item| {
+                    let self_ = self_.clone();
+                    async move {
+                        let snippet = self_.lookup(state.index).await?;
+                        insert(&config.nvim, &expand_tabstops(&snippet.body())).await
+                    }.boxed()
+                })
+            }],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Deserialize)]
+struct Snippet {
+    name: String,
+    prefix: String,
+    body: Body,
+    filetype: Option<String>,
+}
+
+// VSCode の snippet と同じく行のリストでも書ける
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Body {
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl Snippet {
+    fn render(&self) -> String {
+        format!("{}  {}", self.prefix, self.name)
+    }
+    fn body(&self) -> String {
+        match &self.body {
+            Body::Text(text) => text.clone(),
+            Body::Lines(lines) => lines.join("\n"),
+        }
+    }
+}
+
+fn snippets_dir() -> Option<String> {
+    config::config_dir().map(|dir| format!("{dir}/snippets"))
+}
+
+fn load_snippets() -> Result<Vec<Snippet>> {
+    let Some(dir) = snippets_dir() else {
+        return Ok(vec![]);
+    };
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut paths = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    let mut snippets = vec![];
+    for path in paths {
+        let content = std::fs::read_to_string(&path)?;
+        let parsed: Vec<Snippet> =
+            serde_json::from_str(&content).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+        snippets.extend(parsed.into_iter().map(|mut s| {
+            s.filetype = s.filetype.or_else(|| stem.clone());
+            s
+        }));
+    }
+    Ok(snippets)
+}
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{\d+:([^}]*)\}").unwrap());
+static TABSTOP: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{\d+\}|\$\d+").unwrap());
+
+// "${1:foo} $0" => "foo "
+fn expand_tabstops(body: &str) -> String {
+    let body = PLACEHOLDER.replace_all(body, "$1");
+    TABSTOP.replace_all(&body, "").into_owned()
+}
+
+// fzfw を開く前のウィンドウのカーソル位置に挿入する
+async fn insert(nvim: &Neovim, text: &str) -> Result<()> {
+    nvim.stop_insert().await?;
+    nvim.hide_floaterm().await?;
+    nvim.eval_lua_with_args(
+        "vim.api.nvim_put(vim.split(..., '\\n', { plain = true }), 'c', true, true)",
+        vec![text.into()],
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// tmux の全セッションの window 一覧
+#[derive(Clone)]
+pub struct Tmux;
+
+impl ModeDef for Tmux {
+    fn name(&self) -> &'static str {
+        "tmux"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            if !in_tmux() {
+                yield Ok(LoadResp::empty_with_message("not in tmux ($TMUX is not set)"));
+                return;
+            }
+            let output = Command::new("tmux")
+                .arg("list-windows")
+                .arg("-a")
+                .arg("-F")
+                .arg("#{session_name}:#{window_index} #{window_name}")
+                .output()
+                .await?;
+            if !output.status.success() {
+                Err(anyhow!("tmux: {}", String::from_utf8_lossy(&output.stderr).trim()))?;
+            }
+            let items = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|l| l.to_string())
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                yield Ok(LoadResp::empty_with_message("no tmux windows"));
+            } else {
+                yield Ok(LoadResp::new_with_default_header(items))
+            }
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        _win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        async move {
+            let target = parse_target(&item)?;
+            let output = command::output_with_timeout(
+                "preview",
+                Command::new("tmux")
+                    .arg("capture-pane")
+                    .arg("-ep")
+                    .arg("-t")
+                    .arg(target),
+                command::preview_timeout(),
+            )
+            .await?;
+            let message = if output.status.success() {
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            } else {
+                String::from_utf8_lossy(&output.stderr).into_owned()
+            };
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let target = parse_target(&item)?;
+                    // 別セッションの window でも switch-client ならセッションごと切り替わる
+                    let output = Command::new("tmux")
+                        .arg("switch-client")
+                        .arg("-t")
+                        .arg(target)
+                        .output()
+                        .await?;
+                    config.nvim.notify_command_result_if_error("tmux switch-client", output).await
+                })
+            ],
+            "ctrl-x" => [
+                execute_silent!(b, |_mode,config,_state,_query,item| {
+                    let target = parse_target(&item)?;
+                    if !fzf::confirm(format!("kill window {target}?")).await? {
+                        return Ok(());
+                    }
+                    let output = Command::new("tmux")
+                        .arg("kill-window")
+                        .arg("-t")
+                        .arg(target)
+                        .output()
+                        .await?;
+                    config.nvim.notify_command_result_if_error("tmux kill-window", output).await
+                }),
+                b.reload(),
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn in_tmux() -> bool {
+    std::env::var_os("TMUX").is_some_and(|v| !v.is_empty())
+}
+
+// session_name:window_index window_name
+// セッション名に空白が入っていてもよいように window_index までを取る
+fn parse_target(item: &str) -> Result<&str> {
+    static TARGET: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?P<target>.+?:\d+)(?: |$)").unwrap());
+    TARGET
+        .captures(item)
+        .and_then(|c| c.name("target"))
+        .map(|m| m.as_str())
+        .ok_or(anyhow!("invalid item: {item}"))
+}
@@ -0,0 +1,198 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use futures::StreamExt as _;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::livegrep;
+use crate::mode::livegrep::OpenOpts;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+use crate::utils::rg;
+use crate::utils::vscode;
+
+/// TODO/FIXME などのコメントの一覧
+#[derive(Clone)]
+pub struct Todos;
+
+impl ModeDef for Todos {
+    fn name(&self) -> &'static str {
+        "todos"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let tags = tags();
+            let tag_pattern = tag_pattern(&tags);
+            let mut rg_cmd = rg::new();
+            // parse するので色は自前で付ける (後に指定したものが優先される)
+            rg_cmd.arg("--color=never");
+            rg_cmd.arg("--glob");
+            rg_cmd.arg("!.git");
+            rg_cmd.arg("--");
+            rg_cmd.arg(tag_pattern.as_str());
+            let stream = command::command_output_stream(rg_cmd).chunks(100); // tekito
+            tokio::pin!(stream);
+            while let Some(r) = stream.next().await {
+                let (lines, error) = command::split_chunk(r);
+                let lines = lines
+                    .into_iter()
+                    .filter_map(|line| render(&line, &tag_pattern))
+                    .collect();
+                yield Ok(LoadResp::wip_with_default_header(lines));
+                if let Some(e) = error {
+                    yield Ok(LoadResp::error(e.to_string()));
+                    return;
+                }
+            }
+            yield Ok(LoadResp::last())
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        let win = *win;
+        async move { livegrep::preview(item, &win).await }.boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let opts = if vscode::in_vscode() {
+                        OpenOpts::VSCode
+                    } else {
+                        OpenOpts::Neovim { tabedit: false }
+                    };
+                    livegrep::open(config, item, opts).await
+                })
+            ],
+            "ctrl-t" => [
+                execute!(b, |_mode,config,_state,_query,item| {
+                    let opts = OpenOpts::Neovim { tabedit: true };
+                    livegrep::open(config, item, opts).await
+                })
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+const DEFAULT_TAGS: [&str; 5] = ["TODO", "FIXME", "HACK", "XXX", "NOTE"];
+
+// FZFW_TODO_TAGS=TODO,FIXME のようにカンマ区切りで上書きできる
+fn tags() -> Vec<String> {
+    match std::env::var("FZFW_TODO_TAGS") {
+        Ok(tags) if !tags.trim().is_empty() => tags
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect(),
+        _ => DEFAULT_TAGS.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+// rg と Rust の regex の両方で使える形にする
+fn tag_pattern(tags: &[String]) -> Regex {
+    let alternatives = tags
+        .iter()
+        .map(|t| regex::escape(t))
+        .collect::<Vec<_>>()
+        .join("|");
+    Regex::new(&format!(r"\b({alternatives})\b")).unwrap()
+}
+
+/// Splits the text of a matched line into the tag and the comment after it.
+/// `// TODO(foo): bar` => `("TODO", "bar")`
+fn parse_todo<'a>(text: &'a str, tag_pattern: &Regex) -> Option<(&'a str, &'a str)> {
+    let m = tag_pattern.find(text)?;
+    let rest = &text[m.end()..];
+    // TODO(name): や TODO: を読み飛ばす
+    static SUFFIX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\([^)]*\))?:?\s*").unwrap());
+    let rest = match SUFFIX.find(rest) {
+        Some(s) => &rest[s.end()..],
+        None => rest,
+    };
+    Some((m.as_str(), rest.trim_end()))
+}
+
+// rg の file:line:col:text を file:line:col: TAG: text にする
+fn render(line: &str, tag_pattern: &Regex) -> Option<String> {
+    let m = livegrep::ITEM_PATTERN.find(line)?;
+    let (location, text) = line.split_at(m.end());
+    let (tag, rest) = parse_todo(text, tag_pattern)?;
+    Some(format!("{location} {}: {rest}", paint_tag(tag)))
+}
+
+fn paint_tag(tag: &str) -> String {
+    use ansi_term::Colour;
+    match tag {
+        "FIXME" | "XXX" | "BUG" => Colour::Red.bold().paint(tag).to_string(),
+        "TODO" | "HACK" => Colour::Yellow.bold().paint(tag).to_string(),
+        "NOTE" => Colour::Blue.bold().paint(tag).to_string(),
+        _ => Colour::White.bold().paint(tag).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_pattern() -> Regex {
+        let tags = DEFAULT_TAGS
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        tag_pattern(&tags)
+    }
+
+    #[test]
+    fn parse_todo_skips_owner_and_colon() {
+        let pattern = default_pattern();
+        assert_eq!(
+            parse_todo("    // TODO(foo): bar baz  ", &pattern),
+            Some(("TODO", "bar baz"))
+        );
+        assert_eq!(
+            parse_todo("# FIXME: broken", &pattern),
+            Some(("FIXME", "broken"))
+        );
+        assert_eq!(parse_todo("/* XXX */", &pattern), Some(("XXX", "*/")));
+        // 単語の一部は拾わない
+        assert_eq!(parse_todo("let todos = NOTES;", &pattern), None);
+    }
+
+    #[test]
+    fn render_keeps_location() {
+        let pattern = tag_pattern(&["HACK".to_string()]);
+        assert_eq!(
+            render("src/main.rs:12:5:// HACK: works for now", &pattern),
+            Some(format!(
+                "src/main.rs:12:5: {}: works for now",
+                paint_tag("HACK")
+            ))
+        );
+        assert_eq!(render("src/main.rs:12:5:// TODO: later", &pattern), None);
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::method::LoadResp;
+use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
+use crate::mode::ModeDef;
+use crate::nvim::NeovimExt;
+use crate::state::State;
+use crate::utils::command;
+use crate::utils::fzf;
+use crate::utils::fzf::PreviewWindow;
+
+/// cwd の `.tool-versions` / `mise.toml` / `.mise.toml` に書かれたツールのバージョン
+#[derive(Clone)]
+pub struct ToolVersions;
+
+impl ModeDef for ToolVersions {
+    fn name(&self) -> &'static str {
+        "tool-versions"
+    }
+    fn load(
+        &self,
+        _config: &Config,
+        _state: &mut State,
+        _query: String,
+        _item: String,
+    ) -> super::LoadStream<'_> {
+        Box::pin(async_stream::stream! {
+            let Some(manager) = Manager::detect() else {
+                yield Ok(LoadResp::empty_with_message("neither mise nor asdf is on PATH"));
+                return;
+            };
+            let tools = read_tool_versions()?;
+            if tools.is_empty() {
+                yield Ok(LoadResp::empty_with_message(
+                    "no tools configured (.tool-versions, mise.toml or .mise.toml)",
+                ));
+                return;
+            }
+            let items = tools
+                .into_iter()
+                .map(|(tool, version)| format!("{tool} {version}"))
+                .collect();
+            let mut resp = LoadResp::new_with_default_header(items);
+            resp.header = Some(format!("{} [{}]", resp.header.unwrap_or_default(), manager.name()));
+            yield Ok(resp)
+        })
+    }
+    fn preview(
+        &self,
+        _config: &Config,
+        win: &PreviewWindow,
+        item: String,
+    ) -> BoxFuture<'static, Result<PreviewResp>> {
+        let lines = win.lines;
+        async move {
+            let manager = Manager::detect().ok_or(anyhow!("neither mise nor asdf is on PATH"))?;
+            let (tool, _) = parse_item(&item)?;
+            let installed = run_with_timeout(manager.list_installed(tool)).await;
+            let remote = match run_with_timeout(manager.list_remote(tool)).await {
+                // 新しいものが後ろに来るので逆順にして先頭だけ見せる
+                Ok(remote) => remote
+                    .lines()
+                    .rev()
+                    .take(lines.max(10))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Err(e) => e.to_string(),
+            };
+            let installed = installed.unwrap_or_else(|e| e.to_string());
+            let message = format!(
+                "{}\n{}\n\n{}\n{}",
+                ansi_term::Style::new().bold().paint("installed:"),
+                installed.trim_end(),
+                ansi_term::Style::new()
+                    .bold()
+                    .paint("available (newest first):"),
+                remote,
+            );
+            Ok(PreviewResp { message })
+        }
+        .boxed()
+    }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                select_and_execute!{b, |_mode,config,_state,_query,item|
+                    "install" => {
+                        let manager = Manager::detect().ok_or(anyhow!("neither mise nor asdf is on PATH"))?;
+                        let (tool, version) = parse_item(&item)?;
+                        let output = manager.install(tool, version).output().await?;
+                        config.nvim.notify_command_result(format!("install {tool} {version}"), output).await
+                    },
+                    "set local" => {
+                        let manager = Manager::detect().ok_or(anyhow!("neither mise nor asdf is on PATH"))?;
+                        let (tool, _) = parse_item(&item)?;
+                        let output = manager.list_remote(tool).output().await?;
+                        let versions = String::from_utf8_lossy(&output.stdout).into_owned();
+                        let version = fzf::select(versions.lines().rev().collect()).await?;
+                        if version.is_empty() {
+                            return Ok(());
+                        }
+                        let output = manager.set_local(tool, &version).output().await?;
+                        config.nvim.notify_command_result(format!("set {tool} {version}"), output).await
+                    },
+                },
+                b.reload(),
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Manager
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy)]
+enum Manager {
+    Mise,
+    Asdf,
+}
+
+impl Manager {
+    // mise は .tool-versions も読めるので優先する
+    fn detect() -> Option<Self> {
+        if command::on_path("mise") {
+            Some(Manager::Mise)
+        } else if command::on_path("asdf") {
+            Some(Manager::Asdf)
+        } else {
+            None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Manager::Mise => "mise",
+            Manager::Asdf => "asdf",
+        }
+    }
+
+    fn list_installed(&self, tool: &str) -> Command {
+        let mut cmd = Command::new(self.name());
+        match self {
+            Manager::Mise => cmd.arg("ls").arg(tool),
+            Manager::Asdf => cmd.arg("list").arg(tool),
+        };
+        cmd
+    }
+
+    fn list_remote(&self, tool: &str) -> Command {
+        let mut cmd = Command::new(self.name());
+        match self {
+            Manager::Mise => cmd.arg("ls-remote").arg(tool),
+            Manager::Asdf => cmd.arg("list").arg("all").arg(tool),
+        };
+        cmd
+    }
+
+    fn install(&self, tool: &str, version: &str) -> Command {
+        let mut cmd = Command::new(self.name());
+        match self {
+            Manager::Mise => cmd.arg("install").arg(format!("{tool}@{version}")),
+            Manager::Asdf => cmd.arg("install").arg(tool).arg(version),
+        };
+        cmd
+    }
+
+    // mise use は cwd の設定ファイルに書き込む (無ければ mise.toml を作る)
+    fn set_local(&self, tool: &str, version: &str) -> Command {
+        let mut cmd = Command::new(self.name());
+        match self {
+            Manager::Mise => cmd.arg("use").arg(format!("{tool}@{version}")),
+            Manager::Asdf => cmd.arg("local").arg(tool).arg(version),
+        };
+        cmd
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Util
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+const MISE_FILES: &[&str] = &["mise.toml", ".mise.toml"];
+
+// (tool, version)。同じツールが複数のファイルにあればそれぞれ出す
+fn read_tool_versions() -> Result<Vec<(String, String)>> {
+    let mut tools = vec![];
+    if let Ok(content) = std::fs::read_to_string(".tool-versions") {
+        tools.extend(parse_tool_versions(&content));
+    }
+    for file in MISE_FILES {
+        if let Ok(content) = std::fs::read_to_string(file) {
+            let parsed = parse_mise_toml(&content).map_err(|e| anyhow!("{file}: {e}"))?;
+            tools.extend(parsed);
+        }
+    }
+    Ok(tools)
+}
+
+// `nodejs 20.1.0 18.0.0 # comment`。複数書かれていれば先頭が優先されるので先頭を取る
+fn parse_tool_versions(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let tool = words.next()?;
+            let version = words.next()?;
+            Some((tool.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+// [tools]
+// node = "20"
+// python = ["3.11", "3.12"]
+// ruby = { version = "3.3" }
+fn parse_mise_toml(content: &str) -> Result<Vec<(String, String)>> {
+    let value: toml::Table = toml::from_str(content)?;
+    let Some(tools) = value.get("tools").and_then(|t| t.as_table()) else {
+        return Ok(vec![]);
+    };
+    let version_of = |v: &toml::Value| match v {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Array(a) => a.first().and_then(|v| v.as_str()).map(|s| s.to_string()),
+        toml::Value::Table(t) => t
+            .get("version")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        _ => None,
+    };
+    Ok(tools
+        .iter()
+        .filter_map(|(tool, v)| Some((tool.clone(), version_of(v)?)))
+        .collect())
+}
+
+fn parse_item(item: &str) -> Result<(&str, &str)> {
+    item.split_once(' ').ok_or(anyhow!("invalid item: {item}"))
+}
+
+async fn run_with_timeout(mut cmd: Command) -> Result<String> {
+    let output =
+        command::output_with_timeout("preview", &mut cmd, command::preview_timeout()).await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
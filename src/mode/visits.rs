@@ -18,9 +18,14 @@ use crate::nvim::NeovimExt;
 use crate::state::State;
 use crate::utils::bat;
 use crate::utils::command::edit_and_run;
+use crate::utils::dedup;
 use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
+use crate::utils::icons;
+use crate::utils::path::parent_dir;
+use crate::utils::path::to_abspath;
 use crate::utils::path::to_relpath;
+use crate::utils::pins;
 use crate::utils::vscode;
 use crate::utils::xsel;
 
@@ -60,7 +65,7 @@ impl ModeDef for Visits {
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         let nvim = config.nvim.clone();
         let kind = self.kind;
         Box::pin(async_stream::stream! {
@@ -71,14 +76,16 @@ impl ModeDef for Visits {
     fn preview(
         &self,
         _config: &Config,
-        _win: &PreviewWindow,
+        win: &PreviewWindow,
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
+        let columns = win.columns;
         async move {
+            let item = strip(&item).to_string();
             let meta = std::fs::metadata(&item);
             match meta {
                 Ok(meta) if meta.is_file() => {
-                    let message = bat::render_file(&item).await?;
+                    let message = bat::render_file_or_markdown(&item, columns).await?;
                     Ok(PreviewResp { message })
                 }
                 _ => Ok(PreviewResp {
@@ -110,14 +117,27 @@ impl ModeDef for Visits {
             ],
             "ctrl-y" => [
                 execute!(b, |_mode,_config,_state,_query,item| {
-                    xsel::yank(item).await?;
+                    xsel::yank(strip(&item)).await?;
                     Ok(())
                 })
             ],
+            "ctrl-alt-y" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    xsel::yank(to_abspath(strip(&item))).await?;
+                    Ok(())
+                })
+            ],
+            "ctrl-p" => [
+                execute_silent!(b, |_mode,_config,_state,_query,item| {
+                    pins::toggle(strip(&item))?;
+                    Ok(())
+                }),
+                b.reload(),
+            ],
             "ctrl-x" => [
                 execute_silent!(b, |_mode,config,_state,_query,item| {
                     config.nvim.eval_lua(
-                        format!("require'mini.visits'.remove_path('{}')", item)
+                        format!("require'mini.visits'.remove_path('{}')", strip(&item))
                     ).await?;
                     Ok(())
                 }),
@@ -126,17 +146,11 @@ impl ModeDef for Visits {
             "pgup" => [
                 select_and_execute!{b, |_mode,config,_state,_query,item|
                     "oil" => {
-                        let cwd = std::env::current_dir().unwrap();
-                        config.nvim.hide_floaterm().await?;
-                        config
-                            .nvim
-                            .command(&format!("Oil --float {}", cwd.display()))
-                            .await?;
-                        Ok(())
+                        config.nvim.open_oil(parent_dir(strip(&item))).await
                     },
                     "new file" => {
                         let cwd = std::env::current_dir().unwrap();
-                        let fname = fzf::input_with_placeholder("Enter file name", &item).await?;
+                        let fname = fzf::input_with_placeholder("Enter file name", strip(&item)).await?;
                         let fname = fname.trim();
                         let path = format!("{}/{}", cwd.display(), fname);
                         let dir = std::path::Path::new(&path).parent().unwrap();
@@ -157,7 +171,7 @@ impl ModeDef for Visits {
                         open(config, path, opts).await
                     },
                     "execute any command" => {
-                        let (cmd, output) = edit_and_run(format!(" {item}"))
+                        let (cmd, output) = edit_and_run(format!(" {}", strip(&item)))
                             .await?;
                         config.nvim.notify_command_result(&cmd, output)
                             .await?;
@@ -167,8 +181,8 @@ impl ModeDef for Visits {
             ]
         }
     }
-    fn fzf_extra_opts(&self) -> Vec<&str> {
-        vec!["--no-sort"]
+    fn fzf_sort(&self) -> Option<bool> {
+        Some(false)
     }
 }
 
@@ -183,7 +197,7 @@ async fn is_file(path: String) -> bool {
 
 async fn get_visits(nvim: &Neovim, kind: VisitsKind) -> Result<Vec<String>> {
     let mrus: Vec<String> = from_value(
-        nvim.eval_lua(format!(
+        nvim.eval_lua_retry(format!(
             "return require'mini.visits'.list_paths({})",
             match kind {
                 VisitsKind::All => "''",      // empty string for all project
@@ -197,7 +211,12 @@ async fn get_visits(nvim: &Neovim, kind: VisitsKind) -> Result<Vec<String>> {
         .map(to_relpath)
         .collect::<Vec<_>>()
         .await;
-    Ok(mrus)
+    let pinned = pins::Pinned::load();
+    let mrus = mrus
+        .into_iter()
+        .map(|m| pinned.decorate(m.clone(), &m))
+        .collect();
+    Ok(dedup::dedup_items(mrus))
 }
 
 enum OpenOpts {
@@ -205,17 +224,25 @@ enum OpenOpts {
     VSCode,
 }
 
+// アイコンと dedup の回数 (x3) を外す
+fn strip(item: &str) -> &str {
+    icons::strip(dedup::strip(item))
+}
+
 async fn open(config: &Config, item: String, opts: OpenOpts) -> Result<()> {
+    let item = strip(&item).to_string();
     match opts {
         OpenOpts::Neovim { tabedit } => {
             let nvim_opts = nvim::OpenOpts {
                 line: None,
+                col: None,
                 tabedit,
+                window: None,
             };
             config.nvim.open(item.into(), nvim_opts).await?;
         }
         OpenOpts::VSCode => {
-            let output = vscode::open(item.into(), None).await?;
+            let output = vscode::open(item, None).await?;
             config.nvim.notify_command_result("code", output).await?;
         }
     }
@@ -6,8 +6,12 @@ use tokio::process::Command;
 use crate::config::Config;
 use crate::method::LoadResp;
 use crate::method::PreviewResp;
+use crate::mode::config_builder;
+use crate::mode::CallbackMap;
 use crate::mode::ModeDef;
 use crate::state::State;
+use crate::utils::command;
+use crate::utils::fzf;
 use crate::utils::fzf::PreviewWindow;
 use crate::utils::zoxide;
 
@@ -24,7 +28,7 @@ impl ModeDef for Zoxide {
         _state: &mut State,
         _query: String,
         _item: String,
-    ) -> super::LoadStream {
+    ) -> super::LoadStream<'_> {
         Box::pin(async_stream::stream! {
             let zoxide_output = zoxide::new().output().await?;
             let zoxide_output = String::from_utf8_lossy(&zoxide_output.stdout)
@@ -41,24 +45,36 @@ impl ModeDef for Zoxide {
         item: String,
     ) -> BoxFuture<'static, Result<PreviewResp>> {
         async move {
-            let output = Command::new("eza")
-                .args(vec!["--color", "always"])
-                .args(vec!["--all"])
-                .args(vec!["--sort", "name"])
-                .args(vec!["--tree"])
-                .args(vec!["--level", "1"])
-                .args(vec!["--classify"])
-                .args(vec!["--git"])
-                .args(vec!["--color=always"])
-                .arg(&item)
-                .output()
-                .await
-                .map_err(|e| e.to_string())
-                .expect("zoxide: preview:")
-                .stdout;
+            let output = command::output_with_timeout(
+                "preview",
+                Command::new("eza")
+                    .args(vec!["--color", "always"])
+                    .args(vec!["--all"])
+                    .args(vec!["--sort", "name"])
+                    .args(vec!["--tree"])
+                    .args(vec!["--level", "1"])
+                    .args(vec!["--classify"])
+                    .args(vec!["--git"])
+                    .args(vec!["--color=always"])
+                    .arg(&item),
+                command::preview_timeout(),
+            )
+            .await?
+            .stdout;
             let output = String::from_utf8_lossy(output.as_slice()).into_owned();
             Ok(PreviewResp { message: output })
         }
         .boxed()
     }
+    fn fzf_bindings(&self) -> (fzf::Bindings, CallbackMap) {
+        use config_builder::*;
+        bindings! {
+            b <= default_bindings(),
+            "enter" => [
+                // server と neovim の cwd を両方移動して fd で開く
+                b.execute_silent_raw("change-directory --dir {}"),
+                b.change_mode(super::fd::Fd::new().name(), false),
+            ],
+        }
+    }
 }
@@ -4,6 +4,7 @@ use std::process::Output;
 use nvim_rs::call_args;
 use nvim_rs::compat::tokio::Compat as TokioCompat;
 use nvim_rs::create::tokio as nvim_tokio;
+use nvim_rs::error::CallError;
 use nvim_rs::rpc::model::IntoVal;
 use nvim_rs::Handler;
 
@@ -37,25 +38,48 @@ pub async fn start_nvim(nvim_listen_address: &str) -> Result<Neovim> {
     Ok(nvim)
 }
 
+/// Connects to neovim without setting it up, and returns `v:version`.
+/// Used by `fzfw doctor`; unlike `start_nvim` a failure is an error, not a panic.
+pub async fn check_connection(nvim_listen_address: &str) -> Result<u64> {
+    let handler: NeovimHandler = NeovimHandler {};
+    let (nvim, _io_handler) = nvim_tokio::new_path(nvim_listen_address, handler).await?;
+    let version = nvim.eval("v:version").await.map_err(|e| anyhow!("{e}"))?;
+    Ok(from_value(version)?)
+}
+
 pub type Neovim = nvim_rs::Neovim<TokioCompat<WriteHalf<Connection>>>;
 
 pub trait NeovimExt {
     async fn setup_nvim_config(&self) -> Result<()>;
 
+    #[allow(dead_code)]
     async fn start_insert(&self) -> Result<()>;
 
     async fn stop_insert(&self) -> Result<()>;
 
+    #[allow(dead_code)]
     async fn move_to_last_win(&self) -> Result<()>;
 
     async fn move_to_last_tab(&self) -> Result<()>;
 
     async fn last_opened_file(&self) -> Result<String>;
 
+    async fn set_cwd(&self, path: impl AsRef<str>) -> Result<()>;
+
     async fn hide_floaterm(&self) -> Result<()>;
 
+    /// Hides floaterm and opens `dir` with `:Oil --float`
+    async fn open_oil(&self, dir: impl AsRef<str>) -> Result<()>;
+
     async fn open(&self, target: OpenTarget, opts: OpenOpts) -> Result<()>;
 
+    /// Opens each target in a new tab in the given order and moves back to the
+    /// fzf tab once at the end. `tabedit` and `window` of the opts are ignored.
+    async fn open_tabs(&self, targets: Vec<(OpenTarget, OpenOpts)>) -> Result<()>;
+
+    /// Hides floaterm and replaces the argument list with `files` (`:args`)
+    async fn set_arglist(&self, files: &[String]) -> Result<()>;
+
     async fn notify_info(&self, msg: impl AsRef<str>) -> Result<()>;
 
     async fn notify_warn(&self, msg: impl AsRef<str>) -> Result<()>;
@@ -84,7 +108,21 @@ pub trait NeovimExt {
         args: Vec<rmpv::Value>,
     ) -> Result<rmpv::Value>;
 
+    /// Like `eval_lua`, but retries a few times while neovim refuses the call for the
+    /// moment (textlock, fast event, ...). Lua errors and broken connections are not retried.
+    async fn eval_lua_retry(&self, expr: impl AsRef<str>) -> Result<rmpv::Value>;
+
+    /// `nvim_eval` with the same retry policy as `eval_lua_retry`
+    async fn eval_retry(&self, expr: impl AsRef<str>) -> Result<rmpv::Value>;
+
     async fn get_buf_name(&self, bufnr: usize) -> Result<String>;
+
+    /// Window id of the window that was active before the picker (floaterm) opened.
+    /// Intended to be passed to `OpenOpts::window`.
+    async fn origin_window(&self) -> Result<usize>;
+
+    /// `expand('<cword>')` in the origin window (not in floaterm)
+    async fn cword(&self) -> Result<String>;
 }
 
 impl NeovimExt for nvim_rs::Neovim<TokioCompat<WriteHalf<Connection>>> {
@@ -164,48 +202,107 @@ impl NeovimExt for nvim_rs::Neovim<TokioCompat<WriteHalf<Connection>>> {
         }
     }
 
+    async fn set_cwd(&self, path: impl AsRef<str>) -> Result<()> {
+        // vim の文字列リテラル中では ' を '' と書く
+        let path = path.as_ref().replace('\'', "''");
+        self.command(&format!("execute 'cd '.fnameescape('{path}')"))
+            .await?;
+        Ok(())
+    }
+
     async fn hide_floaterm(&self) -> Result<()> {
         self.command("FloatermHide! fzf").await?;
         Ok(())
     }
 
+    async fn open_oil(&self, dir: impl AsRef<str>) -> Result<()> {
+        self.hide_floaterm().await?;
+        // 空白などを含むパスでも開けるように fnameescape する
+        let dir = dir.as_ref().replace('\'', "''");
+        self.command(&format!("execute 'Oil --float '.fnameescape('{dir}')"))
+            .await?;
+        Ok(())
+    }
+
     async fn open(&self, target: OpenTarget, opts: OpenOpts) -> Result<()> {
         let line_opt = match opts.line {
             Some(line) => format!("+{line}"),
             None => "".to_string(),
         };
-        match target {
+        // 開いた直後のウィンドウで実行する
+        let col_cmd = opts.col.map(|col| format!("call cursor(line('.'), {col})"));
+        if let Some(winid) = opts.window {
+            let open_cmd = match target {
+                OpenTarget::File(file) => {
+                    let file = std::fs::canonicalize(file)?;
+                    let file = file.to_string_lossy();
+                    format!("execute 'edit {line_opt} '.fnameescape('{file}')")
+                }
+                OpenTarget::Buffer(bufnr) => format!("buffer {line_opt} {bufnr}"),
+            };
+            let cmds = std::iter::once(open_cmd)
+                .chain(col_cmd)
+                .map(rmpv::Value::from)
+                .collect::<Vec<_>>();
+            self.eval_lua_with_args(
+                r#"
+                    local winid, cmds = ...
+                    vim.api.nvim_win_call(winid, function()
+                      for _, cmd in ipairs(cmds) do
+                        vim.cmd(cmd)
+                      end
+                    end)
+                "#,
+                vec![(winid as u64).into(), cmds.into()],
+            )
+            .await?;
+            return Ok(());
+        }
+        if opts.tabedit {
+            open_in_tab(self, target, &opts).await?;
+            return self.move_to_last_tab().await;
+        }
+        self.stop_insert().await?;
+        self.hide_floaterm().await?;
+        let cmd = match target {
             OpenTarget::File(file) => {
                 let file = std::fs::canonicalize(file)?;
                 let file = file.to_string_lossy();
-                if opts.tabedit {
-                    let cmd = format!("execute 'tabedit {line_opt} '.fnameescape('{file}')",);
-                    self.command(&cmd).await?;
-                    self.move_to_last_tab().await?;
-                    Ok(())
-                } else {
-                    self.stop_insert().await?;
-                    self.hide_floaterm().await?;
-                    let cmd = format!("execute 'edit {line_opt} '.fnameescape('{file}')");
-                    self.command(&cmd).await?;
-                    Ok(())
-                }
-            }
-            OpenTarget::Buffer(bufnr) => {
-                let cmd = format!("buffer {line_opt} {bufnr}");
-                if opts.tabedit {
-                    self.command("tabnew").await?;
-                    self.command(&cmd).await?;
-                    self.move_to_last_tab().await?;
-                    Ok(())
-                } else {
-                    self.stop_insert().await?;
-                    self.hide_floaterm().await?;
-                    self.command(&cmd).await?;
-                    Ok(())
-                }
+                format!("execute 'edit {line_opt} '.fnameescape('{file}')")
             }
+            OpenTarget::Buffer(bufnr) => format!("buffer {line_opt} {bufnr}"),
+        };
+        self.command(&cmd).await?;
+        if let Some(col_cmd) = &col_cmd {
+            self.command(col_cmd).await?;
         }
+        Ok(())
+    }
+
+    async fn open_tabs(&self, targets: Vec<(OpenTarget, OpenOpts)>) -> Result<()> {
+        if targets.is_empty() {
+            return Ok(());
+        }
+        for (target, opts) in targets {
+            open_in_tab(self, target, &opts).await?;
+        }
+        self.move_to_last_tab().await
+    }
+
+    async fn set_arglist(&self, files: &[String]) -> Result<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+        // vim の文字列リテラル中では ' を '' と書き、空白や % などは fnameescape に任せる
+        let args = files
+            .iter()
+            .map(|file| format!("fnameescape('{}')", file.replace('\'', "''")))
+            .collect::<Vec<_>>()
+            .join(".' '.");
+        self.stop_insert().await?;
+        self.hide_floaterm().await?;
+        self.command(&format!("execute 'args '.{args}")).await?;
+        Ok(())
     }
 
     async fn notify_info(&self, msg: impl AsRef<str>) -> Result<()> {
@@ -321,17 +418,137 @@ impl NeovimExt for nvim_rs::Neovim<TokioCompat<WriteHalf<Connection>>> {
         Ok(v)
     }
 
+    async fn eval_lua_retry(&self, expr: impl AsRef<str>) -> Result<rmpv::Value> {
+        let args: Vec<rmpv::Value> = vec![];
+        call_with_retry(self, "nvim_exec_lua", call_args![expr.as_ref(), args]).await
+    }
+
+    async fn eval_retry(&self, expr: impl AsRef<str>) -> Result<rmpv::Value> {
+        call_with_retry(self, "nvim_eval", call_args![expr.as_ref()]).await
+    }
+
     async fn get_buf_name(&self, bufnr: usize) -> Result<String> {
         let x = self
             .eval_lua(&format!("return vim.api.nvim_buf_get_name({bufnr})"))
             .await?;
         Ok(from_value(x)?)
     }
+
+    async fn origin_window(&self) -> Result<usize> {
+        let winid = self.eval("win_getid(g:fzfw_last_win)").await?;
+        match winid.as_u64() {
+            Some(winid) if winid > 0 => Ok(winid as usize),
+            _ => Err(anyhow!("origin window not found")),
+        }
+    }
+
+    async fn cword(&self) -> Result<String> {
+        let winid = self.origin_window().await?;
+        let word = self
+            .eval_lua_with_args(
+                r#"
+                local winid = ...
+                return vim.api.nvim_win_call(winid, function()
+                  return vim.fn.expand('<cword>')
+                end)
+                "#,
+                vec![(winid as u64).into()],
+            )
+            .await?;
+        match word.as_str() {
+            Some(word) => Ok(word.to_string()),
+            None => Err(anyhow!("<cword> is not string")),
+        }
+    }
+}
+
+// 新しいタブで開くだけで fzf のタブには戻らない
+async fn open_in_tab(nvim: &Neovim, target: OpenTarget, opts: &OpenOpts) -> Result<()> {
+    let line_opt = match opts.line {
+        Some(line) => format!("+{line}"),
+        None => "".to_string(),
+    };
+    match target {
+        OpenTarget::File(file) => {
+            let file = std::fs::canonicalize(file)?;
+            let file = file.to_string_lossy();
+            nvim.command(&format!(
+                "execute 'tabedit {line_opt} '.fnameescape('{file}')"
+            ))
+            .await?;
+        }
+        OpenTarget::Buffer(bufnr) => {
+            nvim.command("tabnew").await?;
+            nvim.command(&format!("buffer {line_opt} {bufnr}")).await?;
+        }
+    }
+    if let Some(col) = opts.col {
+        nvim.command(&format!("call cursor(line('.'), {col})"))
+            .await?;
+    }
+    Ok(())
+}
+
+// リトライまでの待ち時間。これを使い切ったら諦める
+const RETRY_DELAYS_MS: [u64; 2] = [100, 300];
+
+// neovim がその時だけ受け付けられない場合のエラー (textlock 中、fast event 中など)。
+// Lua のエラーなどは何度やっても同じなのでリトライしない
+const TRANSIENT_NVIM_ERRORS: &[&str] = &["E523:", "E565:", "E5560:"];
+
+// neovim から返ってくるエラー値は [type, message]
+fn is_transient(err: &rmpv::Value) -> bool {
+    let message = match err {
+        rmpv::Value::Array(a) => a.get(1).and_then(|m| m.as_str()),
+        v => v.as_str(),
+    };
+    let message = message.unwrap_or_default();
+    TRANSIENT_NVIM_ERRORS
+        .iter()
+        .any(|code| message.contains(code))
+}
+
+async fn call_with_retry(
+    nvim: &Neovim,
+    method: &str,
+    args: Vec<rmpv::Value>,
+) -> Result<rmpv::Value> {
+    retry(method, || nvim.call(method, args.clone())).await
+}
+
+async fn retry<F, Fut>(method: &str, mut call: F) -> Result<rmpv::Value>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Result<rmpv::Value, rmpv::Value>, Box<CallError>>>,
+{
+    let mut delays = RETRY_DELAYS_MS.iter();
+    loop {
+        match call().await {
+            Ok(Ok(v)) => return Ok(v),
+            Ok(Err(e)) if is_transient(&e) => match delays.next() {
+                Some(&ms) => {
+                    warn!("nvim: neovim is busy, retrying"; "method" => method, "error" => e.to_string());
+                    tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+                }
+                None => return Err(anyhow!(e.to_string())),
+            },
+            Ok(Err(e)) => return Err(anyhow!(e.to_string())),
+            // 送受信やデコードの失敗は接続が壊れているので、待っても直らない
+            Err(e) => return Err(e.into()),
+        }
+    }
 }
 
+#[derive(Default)]
 pub struct OpenOpts {
     pub line: Option<usize>,
+    /// 1-based byte column. Applied after the file is opened
+    pub col: Option<usize>,
     pub tabedit: bool,
+    /// Window id (`win_getid()`) to open the target in. The floaterm is kept open and
+    /// the focus is not moved. `tabedit` is ignored when this is set.
+    /// `None` opens in the current window after hiding the floaterm.
+    pub window: Option<usize>,
 }
 
 pub enum OpenTarget {
@@ -352,3 +569,122 @@ impl From<usize> for OpenTarget {
 }
 
 const FZFW_AUTOCMD_GROUP: &str = "fzfw";
+
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    /// A neovim for tests that answers every request with nil.
+    pub struct FakeNvim {
+        pub nvim: Neovim,
+        /// `(method, args)` of every request received so far
+        pub requests: Arc<Mutex<Vec<(String, rmpv::Value)>>>,
+    }
+
+    pub async fn fake_nvim() -> FakeNvim {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nvim.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            let (mut tx, _) = listener.accept().unwrap();
+            let mut rx = tx.try_clone().unwrap();
+            // request は [0, msgid, method, args]、response は [1, msgid, error, result]
+            while let Ok(rmpv::Value::Array(msg)) = rmpv::decode::read_value(&mut rx) {
+                if let [kind, msgid, method, args] = &msg[..] {
+                    if kind.as_u64() != Some(0) {
+                        continue;
+                    }
+                    let method = method.as_str().unwrap_or_default().to_string();
+                    received.lock().unwrap().push((method, args.clone()));
+                    let resp = rmpv::Value::Array(vec![
+                        1.into(),
+                        msgid.clone(),
+                        rmpv::Value::Nil,
+                        rmpv::Value::Nil,
+                    ]);
+                    if rmpv::encode::write_value(&mut tx, &resp).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        let (nvim, _io_handler) = nvim_tokio::new_path(&path, NeovimHandler {}).await.unwrap();
+        FakeNvim { nvim, requests }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nvim_error(message: &str) -> rmpv::Value {
+        rmpv::Value::Array(vec![0.into(), message.into()])
+    }
+
+    #[tokio::test]
+    async fn retry_busy_error_once() {
+        let mut calls = 0;
+        let r = retry("nvim_eval", || {
+            calls += 1;
+            let first = calls == 1;
+            async move {
+                if first {
+                    Ok(Err(nvim_error("Vim:E523: Not allowed here")))
+                } else {
+                    Ok(Ok(rmpv::Value::from(42)))
+                }
+            }
+        })
+        .await;
+        assert_eq!(r.unwrap(), rmpv::Value::from(42));
+        assert_eq!(calls, 2);
+    }
+
+    #[tokio::test]
+    async fn lua_error_is_not_retried() {
+        let mut calls = 0;
+        let r = retry("nvim_exec_lua", || {
+            calls += 1;
+            async { Ok(Err(nvim_error("attempt to index a nil value"))) }
+        })
+        .await;
+        assert!(r.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    fn commands(requests: &[(String, rmpv::Value)]) -> Vec<String> {
+        requests
+            .iter()
+            .filter(|(method, _)| method == "nvim_command")
+            .filter_map(|(_, args)| Some(args.as_array()?.first()?.as_str()?.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn open_moves_cursor_to_column() {
+        let fake = testing::fake_nvim().await;
+        let opts = OpenOpts {
+            line: Some(10),
+            col: Some(5),
+            ..Default::default()
+        };
+        fake.nvim.open(OpenTarget::Buffer(3), opts).await.unwrap();
+        let opts = OpenOpts {
+            line: Some(20),
+            col: Some(7),
+            tabedit: true,
+            ..Default::default()
+        };
+        fake.nvim.open(OpenTarget::Buffer(4), opts).await.unwrap();
+
+        let commands = commands(&fake.requests.lock().unwrap());
+        let position = |cmd: &str| commands.iter().position(|c| c == cmd).unwrap();
+        // 開いた後にカーソルを動かす
+        assert!(position("buffer +10 3") < position("call cursor(line('.'), 5)"));
+        assert!(position("buffer +20 4") < position("call cursor(line('.'), 7)"));
+    }
+}
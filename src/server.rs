@@ -1,11 +1,15 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use futures::stream::AbortHandle;
 use futures::stream::Abortable;
 use futures::stream::Aborted;
 use futures::StreamExt as _;
 use futures::TryStreamExt as _;
+use once_cell::sync::Lazy;
 
 // Serde
 use serde_json::json;
@@ -22,6 +26,7 @@ use tokio::sync::Mutex;
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
+use tokio::time::sleep_until;
 
 use crate::logger::Serde;
 use crate::method;
@@ -33,7 +38,14 @@ use crate::method::PreviewResp;
 use crate::mode;
 use crate::mode::Mode;
 use crate::nvim::NeovimExt;
+use crate::state::LastSession;
+use crate::state::Payloads;
 use crate::state::State;
+use crate::timing;
+use crate::utils::action_log;
+use crate::utils::ansi;
+use crate::utils::command;
+use crate::utils::dirs;
 use crate::utils::fzf;
 use crate::Config;
 
@@ -43,33 +55,56 @@ pub async fn server(config: Config, state: State, listener: UnixListener) -> Res
         myself: config.myself.clone(),
         socket: config.socket.clone(),
         log_file: config.log_file.clone(),
-        initial_query: "".to_string(),
+        initial_query: config.initial_query.clone(),
+        generation: 0,
     });
     let callbacks = mode.callbacks();
 
     let config = Arc::new(config);
 
+    let fzf = match spawn_fzf(fzf_config) {
+        Ok(fzf) => fzf,
+        Err(e) => {
+            error!("server: spawn fzf error"; "error" => &e);
+            let _ = config.nvim.notify_error(&e).await;
+            return Err(e);
+        }
+    };
+
     let server_state = ServerState {
-        fzf: Arc::new(RwLock::new(
-            fzf::new(fzf_config)
-                .stdout(std::process::Stdio::piped())
-                .spawn()
-                .expect("Failed to spawn fzf"),
-        )),
+        fzf: Arc::new(RwLock::new(fzf)),
         mode: Arc::new(RwLock::new(mode)),
+        payloads: state.payloads(),
         state: Arc::new(RwLock::new(state)),
         callbacks: Arc::new(RwLock::new(callbacks)),
+        generation: Generation::default(),
     };
-    let current_load_task = Arc::new(Mutex::new(None));
+    serve_requests(config, server_state, listener, idle_timeout()).await
+}
 
+// fzf が終了するまで (idle_timeout が設定されていれば、暇な間に fzf の死が見つかるまで) 待ち受ける
+async fn serve_requests(
+    config: Arc<Config>,
+    server_state: ServerState,
+    listener: UnixListener,
+    idle_timeout: Option<Duration>,
+) -> Result<(), String> {
+    let running_tasks = RunningTasks::default();
+    let mut last_request = tokio::time::Instant::now();
+
+    // 1つのサーバーが持つ fzf は常に1つだけで、mode と callbacks もそれに紐付いている。
+    // クライアント (fzf から呼ばれる fzfw load/preview/execute など) は複数接続しうるが、
+    // 全て同じ fzf の操作として扱われる。別の picker を同時に開きたい場合は
+    // 別のソケットを持つサーバーを新たに起動する (default_bindings の alt-t)。
     loop {
         tokio::select! {
             s = listener.accept() => {
+                last_request = tokio::time::Instant::now();
                 if let Ok((unix_stream, _addr)) = s {
                     handle_one_client(
                         config.clone(),
                         server_state.clone(),
-                        current_load_task.clone(),
+                        running_tasks.clone(),
                         unix_stream,
                     )
                     .await?;
@@ -85,12 +120,35 @@ pub async fn server(config: Config, state: State, listener: UnixListener) -> Res
                     break; // fzf が死んだのでサーバーも終了
                 }
             }
+            _ = async {
+                match idle_timeout {
+                    Some(timeout) => sleep_until(last_request + timeout).await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                // 上の try_wait で拾えなかった場合の保険
+                let alive = matches!(server_state.fzf.write().await.try_wait(), Ok(None));
+                if !alive {
+                    info!("server: idle timeout and fzf is not alive, exiting");
+                    break;
+                }
+                last_request = tokio::time::Instant::now();
+            }
         }
     }
 
     Ok(())
 }
 
+/// `FZFW_IDLE_TIMEOUT` (seconds). Disabled by default.
+fn idle_timeout() -> Option<Duration> {
+    std::env::var("FZFW_IDLE_TIMEOUT")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs)
+}
+
 // field order is the lock order
 #[derive(Clone)]
 struct ServerState {
@@ -98,14 +156,66 @@ struct ServerState {
     mode: Arc<RwLock<Mode>>,
     state: Arc<RwLock<State>>,
     callbacks: Arc<RwLock<mode::CallbackMap>>,
+    // 以下は他の lock を持ったまま触れるので順序は関係ない
+    generation: Generation,
+    /// Same as the payloads in `state`, for previews during a load.
+    payloads: Payloads,
 }
 
-type LoadTask = Arc<Mutex<Option<(JoinHandle<Result<(), Aborted>>, AbortHandle)>>>;
+/// Bumped on every change-mode. Each fzf passes the value it was spawned with to its
+/// load requests, so a load sent by an already-killed fzf can be told apart even when
+/// the new mode has a callback of the same name.
+#[derive(Clone, Default)]
+struct Generation(Arc<AtomicU64>);
+
+impl Generation {
+    fn current(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn next(&self) -> u64 {
+        self.current() + 1
+    }
+
+    fn set(&self, generation: u64) {
+        self.0.store(generation, Ordering::SeqCst);
+    }
+}
+
+type Task = Arc<Mutex<Option<(JoinHandle<Result<(), Aborted>>, AbortHandle)>>>;
+
+// load と execute は別タスクで走らせ、後続のリクエストから中断できるようにしておく
+#[derive(Clone, Default)]
+struct RunningTasks {
+    load: Task,
+    execute: Task,
+}
+
+/// Aborts the running task (if any) and waits until it has actually stopped.
+/// An aborted task only notices the abort at its next poll, so without waiting it may
+/// still hold the `mode`/`state` locks (or write `last_load_resp`) after we move on.
+async fn abort_task(task: &Task) {
+    let task = task.lock().await.take();
+    if let Some((handle, abort_handle)) = task {
+        abort_handle.abort();
+        if let Err(e) = handle.await {
+            error!("server: task join error"; "error" => e.to_string());
+        }
+    }
+}
+
+fn spawn_abortable(
+    future: impl std::future::Future<Output = ()> + Send + 'static,
+) -> (JoinHandle<Result<(), Aborted>>, AbortHandle) {
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    let handle = tokio::spawn(Abortable::new(future, abort_registration));
+    (handle, abort_handle)
+}
 
 async fn handle_one_client(
     config: Arc<Config>,
     server_state: ServerState,
-    current_load_task: LoadTask,
+    running_tasks: RunningTasks,
     unix_stream: UnixStream,
 ) -> Result<(), String> {
     let (rx, tx) = tokio::io::split(unix_stream);
@@ -120,15 +230,9 @@ async fn handle_one_client(
         );
         match req {
             Some(method::Request::Load { params, method: _ }) => {
-                if let Some((_, abort_handle)) = current_load_task.lock().await.take() {
-                    abort_handle.abort();
-                }
-                let (abort_handle, abort_registration) = AbortHandle::new_pair();
-                let handle = tokio::spawn(Abortable::new(
-                    handle_load_request(config, server_state, params, tx),
-                    abort_registration,
-                ));
-                *(current_load_task.lock().await) = Some((handle, abort_handle));
+                abort_task(&running_tasks.load).await;
+                let task = spawn_abortable(handle_load_request(config, server_state, params, tx));
+                *(running_tasks.load.lock().await) = Some(task);
             }
 
             Some(method::Request::Preview {
@@ -140,26 +244,25 @@ async fn handle_one_client(
             }
 
             Some(method::Request::Execute { params, method: _ }) => {
-                if let Some((_, abort_handle)) = current_load_task.lock().await.take() {
-                    abort_handle.abort();
-                }
-                handle_execute_request(config, server_state, params, tx).await;
+                abort_task(&running_tasks.load).await;
+                // 長い execute (lazygit など) の最中でも change-mode などを受け付けられるようにする。
+                // fzf は execute の完了を待つので、execute が並行して来ることはない
+                let task =
+                    spawn_abortable(handle_execute_request(config, server_state, params, tx));
+                *(running_tasks.execute.lock().await) = Some(task);
             }
 
             Some(method::Request::GetLastLoad {
                 params: (),
                 method: _,
             }) => {
-                if let Some((_, abort_handle)) = current_load_task.lock().await.take() {
-                    abort_handle.abort();
-                }
+                abort_task(&running_tasks.load).await;
                 handle_get_last_load_request(server_state, tx).await;
             }
 
             Some(method::Request::ChangeMode { params, method: _ }) => {
-                if let Some((_, abort_handle)) = current_load_task.lock().await.take() {
-                    abort_handle.abort();
-                }
+                abort_task(&running_tasks.load).await;
+                abort_task(&running_tasks.execute).await;
                 handle_change_mode_request(config, server_state, params, tx).await;
             }
 
@@ -167,6 +270,26 @@ async fn handle_one_client(
                 handle_change_directory_request(config, params, tx).await;
             }
 
+            Some(method::Request::ResetMode {
+                params: (),
+                method: _,
+            }) => {
+                abort_task(&running_tasks.load).await;
+                abort_task(&running_tasks.execute).await;
+                handle_reset_mode_request(config, server_state, tx).await;
+            }
+
+            Some(method::Request::GetPrompt {
+                params: (),
+                method: _,
+            }) => {
+                handle_get_prompt_request(server_state, tx).await;
+            }
+
+            Some(method::Request::Transform { params, method: _ }) => {
+                handle_transform_request(config, server_state, params, tx).await;
+            }
+
             _ => {
                 let mut tx = tx.lock().await;
                 (*tx)
@@ -192,12 +315,15 @@ async fn handle_load_request(
         registered_name,
         query,
         item,
+        index,
+        generation: requested_generation,
     } = params;
 
     let ServerState {
         mode,
         state,
         callbacks,
+        generation,
         ..
     } = server_state;
 
@@ -205,18 +331,30 @@ async fn handle_load_request(
     let mut state = state.write().await;
     let callbacks = callbacks.read().await;
 
-    let callback = &callbacks
-        .load
-        .get(&registered_name)
-        .unwrap_or_else(|| {
-            error!("server: execute error";
-                "error" => "unknown callback",
-                "registered_name" => registered_name
-            );
-            panic!("unknown callback");
-        })
-        .callback;
+    // change-mode の直前に古い fzf が送った load の結果で last_load_resp などを上書きしないよう、
+    // state に触る前に捨てる。mode を読んでいる間は change-mode が generation を進められない
+    if requested_generation != generation.current() {
+        info!("server: ignore load from old fzf";
+            "mode" => mode.name(),
+            "generation" => requested_generation,
+        );
+        return;
+    }
+    let Some(callback) = callbacks.load.get(&registered_name) else {
+        info!("server: ignore stale load";
+            "mode" => mode.name(),
+            "registered_name" => registered_name
+        );
+        return;
+    };
+    let callback = &callback.callback;
+
+    state.index = index.0;
+    state.clear_payloads();
+    save_last_session(&mut state, mode.name(), &query);
 
+    // stream を最後まで流し終えるまでを計測する
+    let start = Instant::now();
     state.last_load_resp = {
         let stream = callback(
             mode.mode_def.as_ref(),
@@ -227,16 +365,58 @@ async fn handle_load_request(
         );
         send_load_stream(stream, tx).await
     };
+    let items = state.last_load_resp.as_ref().map(|resp| resp.items.len());
+    timing::record("load", mode.name(), start.elapsed(), items);
+}
+
+// fd や rg の出力が大きすぎると fzf とソケットが詰まるので打ち切る
+static MAX_ITEMS: Lazy<usize> = Lazy::new(|| {
+    std::env::var("FZFW_MAX_ITEMS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(100000)
+});
+
+/// Truncates `stream` once the total number of items (across chunks) exceeds `max_items`.
+/// The rest of the stream is dropped (aborting the load) and a notice is appended.
+fn cap_items<'a>(
+    mut stream: mode::LoadStream<'a>,
+    max_items: usize,
+) -> impl futures::Stream<Item = LoadResp> + 'a {
+    async_stream::stream! {
+        let mut count = 0;
+        while let Some(resp) = stream.next().await {
+            let mut resp = resp.unwrap_or_else(LoadResp::error);
+            if count + resp.items.len() > max_items {
+                resp.items.truncate(max_items - count);
+                resp.items.push(format!(
+                    "{}(truncated: more than {max_items} items)",
+                    method::EMPTY_ITEM_MARKER
+                ));
+                resp.is_last = true;
+                info!("server: load truncated"; "max_items" => max_items);
+                yield resp;
+                break;
+            }
+            count += resp.items.len();
+            yield resp;
+        }
+    }
 }
 
 async fn send_load_stream(
     stream: mode::LoadStream<'_>,
     tx: Arc<Mutex<WriteHalf<UnixStream>>>,
 ) -> Option<LoadResp> {
-    let r = stream
-        .map(|resp| resp.unwrap_or_else(LoadResp::error))
+    let r = cap_items(stream, *MAX_ITEMS)
         .map(Ok::<_, anyhow::Error>) // try_foldを使うために持ち上げる
         .try_fold((None, vec![]), |(mut header, mut items), resp| async {
+            // 色付けは各モードに任せ、ここでまとめて落とす。last_load_resp も色無しになる
+            let resp = if ansi::no_color() {
+                resp.strip_ansi()
+            } else {
+                resp
+            };
             let mut tx = tx.lock().await;
             match send_response(method::Load, &mut *tx, &resp).await {
                 Ok(()) => {
@@ -274,28 +454,62 @@ async fn handle_preview_request(
     tx: Arc<Mutex<WriteHalf<UnixStream>>>,
 ) {
     let ServerState {
-        mode, callbacks, ..
+        mode,
+        callbacks,
+        payloads,
+        ..
     } = server_state;
 
     let mode = mode.read().await;
+    // load は stream を流し終えるまで state を持ち続けるので、state ではなく payloads から読む。
+    // 読み込み済みの chunk の item の payload は load 中でも取れる
+    let payload = payloads.get(&params.item);
     let callbacks = callbacks.read().await;
 
+    let name = params.callback.as_deref().unwrap_or("default");
     let callback = &callbacks
         .preview
-        .get("default")
+        .get(name)
+        .or_else(|| {
+            // reset-mode などで消えた場合はデフォルトに戻す
+            error!("server: preview error"; "error" => "unknown callback", "callback" => name);
+            callbacks.preview.get("default")
+        })
         .unwrap_or_else(|| {
             panic!("unknown callback");
         })
         .callback;
 
-    let resp = callback(
-        mode.mode_def.as_ref(),
-        &config,
-        &preview_window,
-        params.item,
-    )
-    .await
-    .unwrap_or_else(PreviewResp::error);
+    let start = Instant::now();
+    let override_cmd = (name == "default")
+        .then(|| preview_cmd_override(mode.name()))
+        .flatten();
+    let resp = if method::is_empty_item(&params.item) {
+        PreviewResp {
+            message: String::new(),
+        }
+    } else if let Some(cmd) = override_cmd {
+        run_preview_cmd(&cmd, &params.item, &preview_window)
+            .await
+            .unwrap_or_else(PreviewResp::error)
+    } else {
+        callback(
+            mode.mode_def.as_ref(),
+            &config,
+            &preview_window,
+            params.item,
+            params.index.0,
+            payload,
+        )
+        .await
+        .unwrap_or_else(PreviewResp::error)
+    };
+    timing::record("preview", mode.name(), start.elapsed(), None);
+    let resp = if ansi::no_color() {
+        resp.strip_ansi()
+    } else {
+        resp
+    };
 
     let mut tx = tx.lock().await;
     match send_response(method::Preview, &mut *tx, &resp).await {
@@ -304,6 +518,57 @@ async fn handle_preview_request(
     }
 }
 
+/// `FZFW_PREVIEW_CMD_<MODE>` replaces the default preview of the mode with an external command.
+/// `<MODE>` is the mode name uppercased, with every non-alphanumeric character replaced by `_`
+/// and trailing `_` removed (`git-log` => `GIT_LOG`, `git-log(all)` => `GIT_LOG_ALL`).
+///
+/// The command is run by `sh -c` with the item (as fzf's `{}`) in `$1`,
+/// and `FZF_PREVIEW_LINES`/`FZF_PREVIEW_COLUMNS` in the environment. Its stdout is the preview.
+/// e.g. `FZFW_PREVIEW_CMD_FD='head -n "$FZF_PREVIEW_LINES" "$1"'`
+fn preview_cmd_override(mode_name: &str) -> Option<String> {
+    std::env::var(preview_cmd_env_name(mode_name))
+        .ok()
+        .filter(|cmd| !cmd.trim().is_empty())
+}
+
+fn preview_cmd_env_name(mode_name: &str) -> String {
+    let name = mode_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("FZFW_PREVIEW_CMD_{}", name.trim_end_matches('_'))
+}
+
+async fn run_preview_cmd(
+    cmd: &str,
+    item: &str,
+    win: &fzf::PreviewWindow,
+) -> anyhow::Result<PreviewResp> {
+    let output = command::output_with_timeout(
+        "preview",
+        tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .arg("sh") // $0
+            .arg(item)
+            .env("FZF_PREVIEW_LINES", win.lines.to_string())
+            .env("FZF_PREVIEW_COLUMNS", win.columns.to_string()),
+        command::preview_timeout(),
+    )
+    .await?;
+    let mut message = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        message.push_str(&String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(PreviewResp { message })
+}
+
 // ------------------------------------------------------------------------------
 // Execute
 
@@ -317,6 +582,8 @@ async fn handle_execute_request(
         registered_name,
         query,
         item,
+        items,
+        index,
     } = params;
 
     let ServerState {
@@ -330,22 +597,80 @@ async fn handle_execute_request(
     let mut state = state.write().await;
     let callbacks = callbacks.read().await;
 
-    let callback = &callbacks
-        .execute
-        .get(&registered_name)
-        .unwrap_or_else(|| {
-            error!("server: execute error";
-                "error" => "unknown callback",
-                "registered_name" => registered_name
-            );
-            panic!("unknown callback");
-        })
-        .callback;
+    let mode::ExecuteCallback {
+        callback,
+        refresh_after,
+        without_item,
+    } = &callbacks.execute.get(&registered_name).unwrap_or_else(|| {
+        error!("server: execute error";
+            "error" => "unknown callback",
+            "registered_name" => &registered_name
+        );
+        panic!("unknown callback");
+    });
 
-    match callback(mode.mode_def.as_ref(), &config, &mut state, query, item).await {
-        Ok(_) => {}
+    // 空結果の印しか無いときは item を見る操作を動かさない。mode 切り替えや toggle はそのまま動く
+    if only_empty_item(&item, &items) && !without_item {
+        info!("server: skip execute on empty result"; "registered_name" => &registered_name);
+        let mut tx = tx.lock().await;
+        if let Err(e) = send_response(method::Execute, &mut *tx, &()).await {
+            error!("server: execute error"; "error" => e);
+        }
+        return;
+    }
+    let item = if method::is_empty_item(&item) {
+        String::new()
+    } else {
+        item
+    };
+    let items = items
+        .into_iter()
+        .filter(|i| !method::is_empty_item(i))
+        .collect::<Vec<_>>();
+    save_last_session(&mut state, mode.name(), &query);
+    state.index = index.0;
+    state.selected_items = if items.is_empty() {
+        vec![item.clone()]
+    } else {
+        items
+    };
+
+    // actions mode 自身の操作 (記録の閲覧・再実行) は記録しない。再実行は actions mode が記録する
+    let record = (mode.name() != mode::actions::Actions::NAME).then(|| action_log::Record {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        mode: mode.name().to_string(),
+        action: callbacks
+            .key_of(&registered_name)
+            .unwrap_or(&registered_name)
+            .to_string(),
+        callback: registered_name.clone(),
+        cwd: std::env::current_dir()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        query: query.clone(),
+        item: item.clone(),
+        items: state.selected_items.clone(),
+        success: true,
+        error: None,
+    });
+
+    let start = Instant::now();
+    state.refresh_requested = false;
+    let result = callback(mode.mode_def.as_ref(), &config, &mut state, query, item).await;
+    match &result {
+        Ok(_) => state.refresh_requested = *refresh_after,
         Err(e) => error!("server: execute error"; "error" => e.to_string()),
     }
+    timing::record("execute", mode.name(), start.elapsed(), None);
+    if let Some(mut record) = record {
+        if let Err(e) = &result {
+            record.success = false;
+            record.error = Some(e.to_string());
+        }
+        if let Err(e) = action_log::append(&record) {
+            warn!("server: failed to log action"; "error" => e.to_string());
+        }
+    }
 
     let mut tx = tx.lock().await;
     match send_response(method::Execute, &mut *tx, &()).await {
@@ -354,6 +679,11 @@ async fn handle_execute_request(
     }
 }
 
+// {+} は fzf が何も選んでいなければ {} と同じになる
+fn only_empty_item(item: &str, items: &[String]) -> bool {
+    method::is_empty_item(item) && items.iter().all(|i| method::is_empty_item(i))
+}
+
 // ------------------------------------------------------------------------------
 // GetLastLoad
 
@@ -391,44 +721,106 @@ async fn handle_change_mode_request(
     let method::ChangeModeParam {
         mode: new_mode,
         query,
+        arg,
     } = params;
 
     let ServerState {
         mode,
+        state,
         callbacks,
         fzf,
+        generation,
         ..
     } = server_state;
 
+    // fzf を殺す前に確かめる。存在しない mode なら今の mode のまま続ける
+    let Some(mut new_mode) = config.try_get_mode(&new_mode) else {
+        let e = format!("unknown mode: {new_mode}");
+        error!("server: change-mode error"; "error" => &e);
+        let _ = config.nvim.notify_error(&e).await;
+        let mut tx = tx.lock().await;
+        if let Err(e) = send_response(method::ChangeMode, &mut *tx, &Err(e)).await {
+            error!("server: change-mode error"; "error" => e);
+        }
+        return;
+    };
+
     let mut fzf = fzf.write().await;
     let mut mode = mode.write().await;
+    let mut state = state.write().await;
     let mut callbacks = callbacks.write().await;
 
     unsafe { libc::kill(fzf.id().unwrap() as i32, libc::SIGTERM) };
 
-    let new_mode = config.get_mode(new_mode);
+    if let Some(arg) = arg {
+        new_mode.mode_def.set_arg(arg);
+    }
+
     let new_callback_map = new_mode.callbacks();
+    let initial_query = query.unwrap_or_default();
     let new_fzf_config = new_mode.fzf_config(mode::FzfArgs {
         myself: config.myself.clone(),
         socket: config.socket.clone(),
         log_file: config.log_file.clone(),
-        initial_query: query.unwrap_or_default(),
+        initial_query: initial_query.clone(),
+        generation: generation.next(),
     });
 
-    *fzf = fzf::new(new_fzf_config)
-        .stdout(std::process::Stdio::piped())
-        .spawn()
-        .expect("Failed to spawn fzf");
-    *mode = new_mode;
-    *callbacks = new_callback_map;
+    match spawn_fzf(new_fzf_config) {
+        Ok(new_fzf) => {
+            save_last_session(&mut state, new_mode.name(), &initial_query);
+            generation.set(generation.next());
+            *fzf = new_fzf;
+            *mode = new_mode;
+            *callbacks = new_callback_map;
+            // 新しい mode の load が入れ直す
+            state.clear_payloads();
+        }
+        Err(e) => {
+            // 古い fzf は既に終了しているので、サーバーのループもそのまま終了する
+            error!("server: spawn fzf error"; "error" => &e);
+            let _ = config.nvim.notify_error(&e).await;
+        }
+    }
 
     let mut tx = tx.lock().await;
-    match send_response(method::ChangeMode, &mut *tx, &()).await {
+    match send_response(method::ChangeMode, &mut *tx, &Ok(())).await {
         Ok(()) => trace!("server: change-mode done"),
         Err(e) => error!("server: change-mode error"; "error" => e),
     }
 }
 
+// ------------------------------------------------------------------------------
+// ResetMode
+
+// fzf はそのままに、現在のモードを MkMode から作り直す (キャッシュなどを捨てる)。
+// callback の名前は決定的に生成されるので、既存の fzf の binding はそのまま使える。
+async fn handle_reset_mode_request(
+    config: Arc<Config>,
+    server_state: ServerState,
+    tx: Arc<Mutex<WriteHalf<UnixStream>>>,
+) {
+    let ServerState {
+        mode, callbacks, ..
+    } = server_state;
+
+    let mut mode = mode.write().await;
+    let mut callbacks = callbacks.write().await;
+
+    let mut new_mode = config.get_mode(mode.name());
+    if let Some(arg) = mode.mode_def.arg() {
+        new_mode.mode_def.set_arg(arg);
+    }
+    *callbacks = new_mode.callbacks();
+    *mode = new_mode;
+
+    let mut tx = tx.lock().await;
+    match send_response(method::ResetMode, &mut *tx, &()).await {
+        Ok(()) => trace!("server: reset-mode done"),
+        Err(e) => error!("server: reset-mode error"; "error" => e),
+    }
+}
+
 // ------------------------------------------------------------------------------
 // ChangeDirectory
 
@@ -437,6 +829,8 @@ async fn handle_change_directory_request(
     params: method::ChangeDirectoryParam,
     tx: Arc<Mutex<WriteHalf<UnixStream>>>,
 ) {
+    // 明示的にディレクトリが指定された場合は neovim の cwd も追従させる
+    let sync_nvim = matches!(params, method::ChangeDirectoryParam::To(_));
     let dir = match params {
         method::ChangeDirectoryParam::ToParent => {
             let mut dir = std::env::current_dir().unwrap();
@@ -468,7 +862,14 @@ async fn handle_change_directory_request(
 
     match dir {
         Ok(dir) => {
-            std::env::set_current_dir(dir).ok();
+            if let Err(e) = dirs::change_to(&dir) {
+                error!("server: change-directory error"; "error" => e.to_string());
+            }
+            if sync_nvim {
+                if let Err(e) = config.nvim.set_cwd(dir.to_string_lossy()).await {
+                    error!("server: change-directory error"; "error" => e.to_string());
+                }
+            }
         }
         Err(e) => error!("server: change-directory error"; "error" => e),
     }
@@ -482,9 +883,144 @@ async fn handle_change_directory_request(
     }
 }
 
+// ------------------------------------------------------------------------------
+// GetPrompt
+
+async fn handle_get_prompt_request(
+    server_state: ServerState,
+    tx: Arc<Mutex<WriteHalf<UnixStream>>>,
+) {
+    let ServerState { mode, state, .. } = server_state;
+    let mode = mode.read().await;
+    let prompt = mode.mode_def.fzf_prompt();
+    // load 完了時 (fzf の load イベント) にも呼ばれるので、件数を添える。
+    // load 中は state がロックされている。待つと fzf が固まるので件数は省く (完了時にまた呼ばれる)
+    let state = state.try_read();
+    let prompt = match state.as_ref().ok().and_then(|s| s.last_load_resp.as_ref()) {
+        Some(resp) => {
+            let count = resp
+                .items
+                .iter()
+                .filter(|i| !method::is_empty_item(i))
+                .count();
+            prompt_with_count(prompt, count)
+        }
+        None => prompt,
+    };
+
+    let mut tx = tx.lock().await;
+    match send_response(method::GetPrompt, &mut *tx, &prompt).await {
+        Ok(()) => trace!("server: get-prompt done"),
+        Err(e) => error!("server: get-prompt error"; "error" => e),
+    }
+}
+
+// ------------------------------------------------------------------------------
+// Transform
+
+async fn handle_transform_request(
+    config: Arc<Config>,
+    server_state: ServerState,
+    params: method::TransformParam,
+    tx: Arc<Mutex<WriteHalf<UnixStream>>>,
+) {
+    let method::TransformParam {
+        registered_name,
+        query,
+        item,
+        index,
+    } = params;
+
+    let ServerState {
+        mode,
+        state,
+        callbacks,
+        ..
+    } = server_state;
+
+    let mode = mode.read().await;
+    // load は stream を流し終えるまで state を持ち続ける。fzf は transform の応答を待って固まるので、
+    // load 中は待たずに何もしない (どのみち last_load_resp は読み込み途中の古いもの)
+    let Ok(mut state) = state.try_write() else {
+        info!("server: skip transform during load"; "registered_name" => &registered_name);
+        let mut tx = tx.lock().await;
+        if let Err(e) = send_response(method::Transform, &mut *tx, &String::new()).await {
+            error!("server: transform error"; "error" => e);
+        }
+        return;
+    };
+    let callbacks = callbacks.read().await;
+
+    // 古い mode の callback なら何もしない
+    let actions = match callbacks.transform.get(&registered_name) {
+        Some(cb) => (cb.callback)(
+            mode.mode_def.as_ref(),
+            &config,
+            &mut state,
+            query,
+            item,
+            index.0,
+        )
+        .await
+        .unwrap_or_else(|e| {
+            error!("server: transform error"; "error" => e.to_string());
+            String::new()
+        }),
+        None => {
+            error!("server: transform error";
+                "error" => "unknown callback",
+                "registered_name" => registered_name
+            );
+            String::new()
+        }
+    };
+
+    let mut tx = tx.lock().await;
+    match send_response(method::Transform, &mut *tx, &actions).await {
+        Ok(()) => trace!("server: transform done"),
+        Err(e) => error!("server: transform error"; "error" => e),
+    }
+}
+
+// `fd>` => `fd (1234)>`
+fn prompt_with_count(prompt: String, count: usize) -> String {
+    match prompt.strip_suffix('>') {
+        Some(p) => format!("{p} ({count})>"),
+        None => format!("{prompt} ({count})"),
+    }
+}
+
 // ------------------------------------------------------------------------------
 // Util
 
+// --fzfw-resume 用。前回書いたものと同じなら書かない。失敗しても本来の処理は続ける
+fn save_last_session(state: &mut State, mode: &str, query: &str) {
+    let session = LastSession {
+        mode: mode.to_string(),
+        query: query.to_string(),
+    };
+    if state.saved_session.as_ref() == Some(&session) {
+        return;
+    }
+    match session.save() {
+        Ok(()) => state.saved_session = Some(session),
+        Err(e) => warn!("server: failed to save last session"; "error" => e.to_string()),
+    }
+}
+
+fn spawn_fzf(fzf_config: fzf::Config) -> Result<Child, String> {
+    spawn_checked(fzf::new(fzf_config))
+}
+
+fn spawn_checked(mut fzf: tokio::process::Command) -> Result<Child, String> {
+    fzf.stdout(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => "fzfw: fzf not found on PATH".to_string(),
+            _ => format!("fzfw: failed to spawn fzf: {e}"),
+        })
+}
+
 async fn send_response<M: method::Method, TX: AsyncWriteExt + Unpin>(
     _method: M, // 型合わせ用
     tx: &mut TX,
@@ -494,3 +1030,596 @@ async fn send_response<M: method::Method, TX: AsyncWriteExt + Unpin>(
     tx.write_all(resp.as_bytes()).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::ModeDef;
+    use crate::nvim::testing::fake_nvim;
+    use crate::utils::fzf::PreviewWindow;
+    use futures::future::BoxFuture;
+    use futures::FutureExt as _;
+
+    /// Loads `{name}:{query}` after `delay`, with `query` as its payload.
+    /// The preview shows the payload.
+    struct TestMode {
+        name: &'static str,
+        delay: Duration,
+    }
+
+    impl ModeDef for TestMode {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn load<'a>(
+            &'a self,
+            _config: &'a Config,
+            state: &'a mut State,
+            query: String,
+            _item: String,
+        ) -> mode::LoadStream<'a> {
+            Box::pin(async_stream::stream! {
+                sleep(self.delay).await;
+                let item = format!("{}:{query}", self.name);
+                state.set_payload(&item, &query);
+                yield Ok(LoadResp::new_with_default_header(vec![item]))
+            })
+        }
+        fn preview<'a>(
+            &'a self,
+            _config: &'a Config,
+            _win: &'a PreviewWindow,
+            _item: String,
+        ) -> BoxFuture<'a, anyhow::Result<PreviewResp>> {
+            async { Ok(PreviewResp::error("no payload")) }.boxed()
+        }
+        fn preview_with_payload<'a>(
+            &'a self,
+            _config: &'a Config,
+            _win: &'a PreviewWindow,
+            item: String,
+            _index: Option<usize>,
+            payload: Option<serde_json::Value>,
+        ) -> BoxFuture<'a, anyhow::Result<PreviewResp>> {
+            async move {
+                let message: String = mode::decode_payload(payload, &item)?;
+                Ok(PreviewResp { message })
+            }
+            .boxed()
+        }
+    }
+
+    fn test_mode(name: &'static str, delay: Duration) -> (String, mode::MkMode) {
+        let mk_mode: mode::MkMode = Box::pin(move || Mode {
+            mode_def: Box::new(TestMode { name, delay }),
+        });
+        (name.to_string(), mk_mode)
+    }
+
+    // 状態ファイルと action log は tempdir に書かせ、fzf の代わりにすぐ終わるコマンドを起動させる
+    fn init_test_env() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            let dir = tempfile::tempdir().unwrap().into_path();
+            std::env::set_var("XDG_STATE_HOME", dir);
+            std::env::set_var("FZFW_FZF_BIN", "true");
+        });
+    }
+
+    async fn test_config(modes: Vec<(String, mode::MkMode)>) -> Config {
+        init_test_env();
+        Config {
+            myself: "fzfw".to_string(),
+            socket: String::new(),
+            log_file: String::new(),
+            initial_mode: modes[0].0.clone(),
+            initial_query: String::new(),
+            nvim: fake_nvim().await.nvim,
+            nvim_address: String::new(),
+            modes,
+        }
+    }
+
+    fn test_server_state(config: &Config) -> ServerState {
+        let fzf = tokio::process::Command::new("sleep")
+            .arg("60")
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let mode = config.get_initial_mode();
+        let state = State::new();
+        ServerState {
+            fzf: Arc::new(RwLock::new(fzf)),
+            callbacks: Arc::new(RwLock::new(mode.callbacks())),
+            mode: Arc::new(RwLock::new(mode)),
+            payloads: state.payloads(),
+            state: Arc::new(RwLock::new(state)),
+            generation: Generation::default(),
+        }
+    }
+
+    // 受け取る側を閉じると送信が失敗して load の結果が捨てられるので、呼び出し側で持っておく
+    fn connection() -> (Arc<Mutex<WriteHalf<UnixStream>>>, UnixStream) {
+        let (server, client) = UnixStream::pair().unwrap();
+        let (_rx, tx) = tokio::io::split(server);
+        (Arc::new(Mutex::new(tx)), client)
+    }
+
+    fn load_param(query: &str, generation: u64) -> LoadParam {
+        LoadParam {
+            registered_name: "default".to_string(),
+            query: query.to_string(),
+            item: None,
+            index: method::ItemIndex(None),
+            generation,
+        }
+    }
+
+    fn preview_param(item: &str) -> method::PreviewParam {
+        method::PreviewParam {
+            item: item.to_string(),
+            callback: None,
+            index: method::ItemIndex(None),
+        }
+    }
+
+    fn preview_window() -> fzf::PreviewWindow {
+        fzf::PreviewWindow {
+            lines: 10,
+            columns: 80,
+        }
+    }
+
+    // 最初の chunk (`test:{query}`) を送った後、終わらずに state を持ち続ける load を始める
+    async fn start_endless_load(
+        config: &Arc<Config>,
+        server_state: &ServerState,
+        query: &str,
+    ) -> (JoinHandle<()>, UnixStream) {
+        server_state.callbacks.write().await.load.insert(
+            "endless".to_string(),
+            mode::LoadCallback {
+                callback: Box::new(|_mode, _config, state, query, _item| {
+                    Box::pin(async_stream::stream! {
+                        let item = format!("test:{query}");
+                        state.set_payload(&item, &query);
+                        yield Ok(LoadResp::wip_with_default_header(vec![item]));
+                        std::future::pending::<()>().await;
+                    })
+                }),
+            },
+        );
+        let mut params = load_param(query, 0);
+        params.registered_name = "endless".to_string();
+        let (tx, mut client) = connection();
+        let load = tokio::spawn(handle_load_request(
+            config.clone(),
+            server_state.clone(),
+            params,
+            tx,
+        ));
+        BufReader::new(&mut client)
+            .lines()
+            .next_line()
+            .await
+            .unwrap();
+        assert!(server_state.state.try_read().is_err());
+        (load, client)
+    }
+
+    async fn loaded_items(server_state: &ServerState) -> Option<Vec<String>> {
+        let state = server_state.state.read().await;
+        state.last_load_resp.as_ref().map(|resp| resp.items.clone())
+    }
+
+    #[tokio::test]
+    async fn payload_survives_load_to_execute() {
+        let config = Arc::new(test_config(vec![test_mode("test", Duration::ZERO)]).await);
+        let server_state = test_server_state(&config);
+        let executed = Arc::new(std::sync::Mutex::new(vec![]));
+        {
+            let executed = executed.clone();
+            server_state.callbacks.write().await.execute.insert(
+                "test-execute".to_string(),
+                mode::ExecuteCallback {
+                    callback: Box::new(move |mode, _config, state, _query, item| {
+                        let payload = mode.payload::<String>(state, &item);
+                        executed
+                            .lock()
+                            .unwrap()
+                            .push(payload.map_err(|e| e.to_string()));
+                        async { Ok(()) }.boxed()
+                    }),
+                    refresh_after: false,
+                    without_item: false,
+                },
+            );
+        }
+
+        let (tx, _client) = connection();
+        handle_load_request(config.clone(), server_state.clone(), load_param("q", 0), tx).await;
+        assert_eq!(
+            loaded_items(&server_state).await,
+            Some(vec!["test:q".to_string()])
+        );
+
+        let (tx, _client) = connection();
+        let params = ExecuteParam {
+            registered_name: "test-execute".to_string(),
+            query: "q".to_string(),
+            item: "test:q".to_string(),
+            items: vec![],
+            index: method::ItemIndex(Some(0)),
+        };
+        handle_execute_request(config, server_state, params, tx).await;
+        assert_eq!(*executed.lock().unwrap(), vec![Ok("q".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn preview_reads_payload_while_load_is_running() {
+        let config = Arc::new(test_config(vec![test_mode("test", Duration::ZERO)]).await);
+        let server_state = test_server_state(&config);
+
+        let (load, _load_client) = start_endless_load(&config, &server_state, "q").await;
+
+        let (tx, client) = connection();
+        let preview = handle_preview_request(
+            config,
+            server_state,
+            preview_param("test:q"),
+            preview_window(),
+            tx,
+        );
+        tokio::time::timeout(Duration::from_secs(5), preview)
+            .await
+            .expect("preview must not wait for the load");
+        load.abort();
+
+        let mut lines = BufReader::new(client).lines();
+        let resp: PreviewResp =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(resp.message, "q");
+    }
+
+    #[tokio::test]
+    async fn transform_does_not_wait_for_load() {
+        let config = Arc::new(test_config(vec![test_mode("test", Duration::ZERO)]).await);
+        let server_state = test_server_state(&config);
+        server_state.callbacks.write().await.transform.insert(
+            "next".to_string(),
+            mode::TransformCallback {
+                callback: Box::new(|_mode, _config, _state, _query, _item, _index| {
+                    async { Ok("pos(2)".to_string()) }.boxed()
+                }),
+            },
+        );
+        let transform = |server_state: ServerState| async {
+            let params = method::TransformParam {
+                registered_name: "next".to_string(),
+                query: String::new(),
+                item: "test:q".to_string(),
+                index: method::ItemIndex(Some(0)),
+            };
+            let (tx, client) = connection();
+            let request = handle_transform_request(config.clone(), server_state, params, tx);
+            tokio::time::timeout(Duration::from_secs(5), request)
+                .await
+                .expect("transform must not wait for the load");
+            let line = BufReader::new(client).lines().next_line().await.unwrap();
+            serde_json::from_str::<String>(&line.unwrap()).unwrap()
+        };
+
+        assert_eq!(transform(server_state.clone()).await, "pos(2)");
+        let (load, _load_client) = start_endless_load(&config, &server_state, "q").await;
+        assert_eq!(transform(server_state.clone()).await, "");
+        load.abort();
+    }
+
+    #[test]
+    fn preview_cmd_env_name_follows_mode_name() {
+        assert_eq!(preview_cmd_env_name("fd"), "FZFW_PREVIEW_CMD_FD");
+        assert_eq!(preview_cmd_env_name("git-log"), "FZFW_PREVIEW_CMD_GIT_LOG");
+        assert_eq!(
+            preview_cmd_env_name("git-log(all)"),
+            "FZFW_PREVIEW_CMD_GIT_LOG_ALL"
+        );
+        assert_eq!(
+            preview_cmd_env_name("Nvim.Buffer"),
+            "FZFW_PREVIEW_CMD_NVIM_BUFFER"
+        );
+    }
+
+    #[tokio::test]
+    async fn preview_cmd_override_runs_only_when_set() {
+        // 他のテストと被らない mode 名を使う
+        let mode_name = "preview-cmd-test";
+        assert_eq!(preview_cmd_override(mode_name), None);
+        std::env::set_var(preview_cmd_env_name(mode_name), "  ");
+        assert_eq!(preview_cmd_override(mode_name), None);
+
+        std::env::set_var(
+            preview_cmd_env_name(mode_name),
+            r#"echo "$1 $FZF_PREVIEW_LINES $FZF_PREVIEW_COLUMNS""#,
+        );
+        let cmd = preview_cmd_override(mode_name).unwrap();
+        let resp = run_preview_cmd(&cmd, "src/main.rs", &preview_window())
+            .await
+            .unwrap();
+        assert_eq!(resp.message, "src/main.rs 10 80\n");
+        std::env::remove_var(preview_cmd_env_name(mode_name));
+    }
+
+    fn change_mode_param(mode: &str) -> method::ChangeModeParam {
+        method::ChangeModeParam {
+            mode: mode.to_string(),
+            query: None,
+            arg: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_mode_keeps_current_mode() {
+        let modes = vec![
+            test_mode("test", Duration::ZERO),
+            test_mode("other", Duration::ZERO),
+        ];
+        let config = Arc::new(test_config(modes).await);
+        let server_state = test_server_state(&config);
+        let fzf_pid = server_state.fzf.read().await.id();
+        let generation = server_state.generation.current();
+
+        let (tx, client) = connection();
+        let params = change_mode_param("bogus");
+        handle_change_mode_request(config, server_state.clone(), params, tx).await;
+
+        let line = BufReader::new(client).lines().next_line().await.unwrap();
+        assert!(line.unwrap().contains("unknown mode: bogus"));
+        assert_eq!(server_state.mode.read().await.name(), "test");
+        assert_eq!(server_state.generation.current(), generation);
+        // fzf も殺されていない
+        let mut fzf = server_state.fzf.write().await;
+        assert_eq!(fzf.id(), fzf_pid);
+        assert!(fzf.try_wait().unwrap().is_none());
+    }
+
+    // 1 リクエスト分の接続を張って handle_one_client に渡す
+    async fn request(
+        config: &Arc<Config>,
+        server_state: &ServerState,
+        running_tasks: &RunningTasks,
+        req: method::Request,
+    ) -> (JoinHandle<Result<(), String>>, UnixStream) {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let line = format!("{}\n", serde_json::to_string(&req).unwrap());
+        client.write_all(line.as_bytes()).await.unwrap();
+        let handle = tokio::spawn(handle_one_client(
+            config.clone(),
+            server_state.clone(),
+            running_tasks.clone(),
+            server,
+        ));
+        (handle, client)
+    }
+
+    #[tokio::test]
+    async fn change_mode_aborts_running_execute() {
+        let modes = vec![
+            test_mode("test", Duration::ZERO),
+            test_mode("other", Duration::ZERO),
+        ];
+        let config = Arc::new(test_config(modes).await);
+        let server_state = test_server_state(&config);
+        let running_tasks = RunningTasks::default();
+
+        struct SetOnDrop(Arc<std::sync::atomic::AtomicBool>);
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+        let started = Arc::new(tokio::sync::Notify::new());
+        let dropped = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        {
+            let (started, dropped) = (started.clone(), dropped.clone());
+            server_state.callbacks.write().await.execute.insert(
+                "slow".to_string(),
+                mode::ExecuteCallback {
+                    callback: Box::new(move |_mode, _config, _state, _query, _item| {
+                        let (started, dropped) = (started.clone(), dropped.clone());
+                        async move {
+                            let _guard = SetOnDrop(dropped);
+                            started.notify_one();
+                            std::future::pending::<()>().await;
+                            Ok(())
+                        }
+                        .boxed()
+                    }),
+                    refresh_after: false,
+                    without_item: false,
+                },
+            );
+        }
+
+        let execute = method::Request::Execute {
+            method: method::Execute,
+            params: ExecuteParam {
+                registered_name: "slow".to_string(),
+                query: String::new(),
+                item: "test:q".to_string(),
+                items: vec![],
+                index: method::ItemIndex(Some(0)),
+            },
+        };
+        let (handle, _execute_client) =
+            request(&config, &server_state, &running_tasks, execute).await;
+        handle.await.unwrap().unwrap();
+        started.notified().await;
+
+        let change_mode = method::Request::ChangeMode {
+            method: method::ChangeMode,
+            params: change_mode_param("other"),
+        };
+        let (handle, _client) = request(&config, &server_state, &running_tasks, change_mode).await;
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("change-mode must not wait for the execute")
+            .unwrap()
+            .unwrap();
+
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(running_tasks.execute.lock().await.is_none());
+        assert_eq!(server_state.mode.read().await.name(), "other");
+    }
+
+    // 遅い load と change-mode を入り乱れさせた後でも、古い fzf からの load が
+    // last_load_resp を上書きしないことを確かめる
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn stale_load_never_overwrites_after_change_mode() {
+        let modes = vec![
+            test_mode("old", Duration::from_millis(20)),
+            test_mode("new", Duration::from_millis(5)),
+        ];
+        let config = Arc::new(test_config(modes).await);
+        let server_state = test_server_state(&config);
+
+        let mut tasks = vec![];
+        for i in 0..40 {
+            let (config, server_state) = (config.clone(), server_state.clone());
+            if i % 5 == 0 {
+                let mode = if i % 10 == 0 { "new" } else { "old" };
+                tasks.push(tokio::spawn(async move {
+                    let (tx, _client) = connection();
+                    let params = change_mode_param(mode);
+                    handle_change_mode_request(config, server_state, params, tx).await;
+                }));
+            } else {
+                // 送った時点の generation を持って、少し遅れて届く load
+                let params = load_param(&format!("q{i}"), server_state.generation.current());
+                tasks.push(tokio::spawn(async move {
+                    tokio::task::yield_now().await;
+                    let (tx, _client) = connection();
+                    handle_load_request(config, server_state, params, tx).await;
+                }));
+            }
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let current = server_state.mode.read().await.name();
+        let generation = server_state.generation.current();
+        let (tx, _client) = connection();
+        let params = load_param("fresh", generation);
+        handle_load_request(config.clone(), server_state.clone(), params, tx).await;
+        // 切り替え前の fzf が最後に送った load
+        let (tx, _client) = connection();
+        let params = load_param("stale", generation - 1);
+        handle_load_request(config, server_state.clone(), params, tx).await;
+
+        assert_eq!(
+            loaded_items(&server_state).await,
+            Some(vec![format!("{current}:fresh")])
+        );
+    }
+
+    #[tokio::test]
+    async fn load_is_truncated_at_max_items() {
+        let chunk = |n: usize| {
+            let items = (0..3).map(|i| format!("item{}", n * 3 + i)).collect();
+            Ok(LoadResp::wip_with_default_header(items))
+        };
+        let stream: mode::LoadStream = Box::pin(futures::stream::iter((0..4).map(chunk)));
+
+        let resps = cap_items(stream, 5).collect::<Vec<_>>().await;
+        assert_eq!(resps.len(), 2);
+        assert!(!resps[0].is_last);
+        assert!(resps[1].is_last);
+        let items = resps
+            .iter()
+            .flat_map(|r| r.items.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 6);
+        assert_eq!(items[4], "item4");
+        assert!(method::is_empty_item(&items[5]));
+        assert!(items[5].ends_with("(truncated: more than 5 items)"));
+    }
+
+    #[tokio::test]
+    async fn idle_server_exits_when_fzf_is_dead() {
+        let config = Arc::new(test_config(vec![test_mode("test", Duration::ZERO)]).await);
+        let dir = tempfile::tempdir().unwrap();
+        let listen = |name: &str| UnixListener::bind(dir.path().join(name)).unwrap();
+
+        let server_state = test_server_state(&config);
+        server_state.fzf.write().await.kill().await.unwrap();
+        let server = serve_requests(
+            config.clone(),
+            server_state,
+            listen("dead.sock"),
+            Some(Duration::from_millis(50)),
+        );
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server must exit")
+            .unwrap();
+
+        // fzf が生きていれば暇でも終了しない
+        let server_state = test_server_state(&config);
+        let server = serve_requests(
+            config,
+            server_state,
+            listen("alive.sock"),
+            Some(Duration::from_millis(50)),
+        );
+        assert!(tokio::time::timeout(Duration::from_millis(300), server)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_fzf_is_reported() {
+        let fzf = tokio::process::Command::new("/nonexistent/fzf");
+        let e = spawn_checked(fzf).unwrap_err();
+        assert_eq!(e, "fzfw: fzf not found on PATH");
+    }
+
+    #[test]
+    fn empty_result_skips_only_callbacks_using_item() {
+        use crate::mode::config_builder::*;
+        use futures::FutureExt;
+
+        let (_, callbacks) = bindings! {
+            b <= (crate::utils::fzf::Bindings::empty(), mode::CallbackMap::empty()),
+            "enter" => [
+                execute!(b, |_mode,_config,_state,_query,item| {
+                    panic!("must not run on an empty result: {item}")
+                }),
+            ],
+            "alt-y" => [
+                execute_silent_without_item!(b, |_mode,_config,_state,_query,_item| Ok(())),
+            ],
+            "alt-h" => [ b.toggle_option(|| {}) ],
+            // 引数名ではなく明示的に選ぶ
+            "alt-u" => [
+                execute_silent!(b, |_mode,_config,_state,_query,_item| Ok(())),
+            ],
+        };
+        // callback は登録順に callback1, callback2, ... と名付けられる
+        let without_item = |name: &str| callbacks.execute[name].without_item;
+        assert!(!without_item("callback1"));
+        assert!(without_item("callback2"));
+        assert!(without_item("callback3"));
+        assert!(!without_item("callback4"));
+
+        let empty = method::LoadResp::empty_with_message("no files")
+            .items
+            .remove(0);
+        assert!(only_empty_item(&empty, std::slice::from_ref(&empty)));
+        assert!(only_empty_item(&empty, &[]));
+        assert!(!only_empty_item(
+            "src/main.rs",
+            &["src/main.rs".to_string()]
+        ));
+        assert!(!only_empty_item("", &[]));
+    }
+}
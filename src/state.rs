@@ -1,13 +1,157 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
 use crate::method::LoadResp;
 
 pub struct State {
     pub last_load_resp: Option<LoadResp>,
+    /// Items selected in fzf when the current execute callback was invoked.
+    /// Contains only the current item unless the action passes `{+}`.
+    pub selected_items: Vec<String>,
+    /// Index of the current item in the loaded list (`{n}`) when the current
+    /// load/execute callback was invoked.
+    pub index: Option<usize>,
+    /// Set when an execute callback with `refresh_after` succeeded, and taken by
+    /// the transform of `ConfigBuilder::refresh` to decide whether to reload.
+    pub refresh_requested: bool,
+    /// The session the server wrote last, so that the same one is not written again.
+    pub saved_session: Option<LastSession>,
+    /// Machine-readable data attached to the items of the last load.
+    /// Cleared at the start of every load. See `set_payload`.
+    payloads: Payloads,
 }
 
 impl State {
     pub fn new() -> Self {
         State {
             last_load_resp: None,
+            selected_items: vec![],
+            index: None,
+            refresh_requested: false,
+            saved_session: None,
+            payloads: Payloads::default(),
+        }
+    }
+
+    /// `selected_items`, or `[item]` when nothing is selected.
+    pub fn selected_or(&self, item: String) -> Vec<String> {
+        if self.selected_items.is_empty() {
+            if item.is_empty() {
+                vec![]
+            } else {
+                vec![item]
+            }
+        } else {
+            self.selected_items.clone()
+        }
+    }
+
+    /// Attaches `payload` to `item` so that preview and execute callbacks can get it back
+    /// instead of parsing the display string.
+    /// `item` must be exactly the line given to fzf (without ANSI colors, as fzf's `{}`).
+    pub fn set_payload(&mut self, item: impl Into<String>, payload: &impl Serialize) {
+        match serde_json::to_value(payload) {
+            Ok(value) => self.payloads.insert(item.into(), value),
+            Err(e) => error!("state: set_payload failed"; "error" => e.to_string()),
+        }
+    }
+
+    /// The payload attached to `item` by `set_payload` during the last load.
+    /// Decode it with `ModeDef::payload` (execute) or `decode_payload` (preview).
+    pub fn payload_value(&self, item: &str) -> Option<serde_json::Value> {
+        self.payloads.get(item)
+    }
+
+    pub fn clear_payloads(&mut self) {
+        self.payloads.clear();
+    }
+
+    /// A handle to the payloads that can be read without locking `State`.
+    pub fn payloads(&self) -> Payloads {
+        self.payloads.clone()
+    }
+}
+
+/// Payloads keyed by the item line, behind their own lock.
+/// A load holds the `State` lock until its stream ends, so previews read the payloads
+/// through this handle instead. Each `set_payload` is visible as soon as it is made.
+#[derive(Clone, Default)]
+pub struct Payloads(Arc<RwLock<HashMap<String, serde_json::Value>>>);
+
+impl Payloads {
+    pub fn get(&self, item: &str) -> Option<serde_json::Value> {
+        self.0.read().unwrap().get(item).cloned()
+    }
+
+    fn insert(&self, item: String, value: serde_json::Value) {
+        self.0.write().unwrap().insert(item, value);
+    }
+
+    fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Last session
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// The mode and query fzfw showed last, restored by `--fzfw-resume`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LastSession {
+    pub mode: String,
+    pub query: String,
+}
+
+/// `{state_dir}/last_session.json` (see `config::state_dir`)
+pub fn last_session_file() -> String {
+    format!("{}/last_session.json", config::state_dir())
+}
+
+impl LastSession {
+    pub fn load() -> Option<Self> {
+        Self::load_from(Path::new(&last_session_file()))
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        self.save_to(Path::new(&last_session_file()))
+    }
+
+    fn load_from(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_session_is_loaded_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fzfw/last_session.json");
+        assert_eq!(LastSession::load_from(&path), None);
+
+        let session = LastSession {
+            mode: "livegrep".to_string(),
+            query: "foo bar".to_string(),
+        };
+        session.save_to(&path).unwrap();
+        assert_eq!(LastSession::load_from(&path), Some(session));
     }
 }
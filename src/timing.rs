@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+/// How long a request took to be handled.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Timing {
+    /// "load" | "preview" | "execute"
+    pub request: &'static str,
+    pub mode: String,
+    pub ms: u128,
+    /// The number of loaded items (load only)
+    pub items: Option<usize>,
+}
+
+// stats mode などから読めるように直近のものだけ残しておく
+const MAX_RECENT: usize = 256;
+
+static RECENT: Lazy<Mutex<VecDeque<Timing>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+static LOG_TIMING: Lazy<bool> =
+    Lazy::new(|| std::env::var("FZFW_LOG_TIMING").is_ok_and(|v| v == "1"));
+
+/// Logs the timing at info level (warning level if `FZFW_LOG_TIMING=1`, so that it shows up
+/// with `FZFW_LOG_LEVEL=warning`) and keeps it for `recent`.
+pub fn record(
+    request: &'static str,
+    mode: impl Into<String>,
+    elapsed: Duration,
+    items: Option<usize>,
+) {
+    let timing = Timing {
+        request,
+        mode: mode.into(),
+        ms: elapsed.as_millis(),
+        items,
+    };
+    let msg = format!("{request} done");
+    if *LOG_TIMING {
+        warn!("{}", msg; "ms" => timing.ms, "mode" => &timing.mode, "items" => timing.items);
+    } else {
+        info!("{}", msg; "ms" => timing.ms, "mode" => &timing.mode, "items" => timing.items);
+    }
+    let mut recent = RECENT.lock().unwrap();
+    if recent.len() >= MAX_RECENT {
+        recent.pop_front();
+    }
+    recent.push_back(timing);
+}
+
+/// The last (at most 256) recorded timings, oldest first.
+pub fn recent() -> Vec<Timing> {
+    RECENT.lock().unwrap().iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_keeps_only_the_latest() {
+        for i in 0..MAX_RECENT + 10 {
+            record(
+                "preview",
+                "timing-test",
+                Duration::from_millis(i as u64),
+                None,
+            );
+        }
+        // 他のテストも record するので、この mode の分だけ見る
+        let ours = recent()
+            .into_iter()
+            .filter(|t| t.mode == "timing-test")
+            .collect::<Vec<_>>();
+        assert!(ours.len() <= MAX_RECENT);
+        assert_eq!(ours.last().unwrap().ms, (MAX_RECENT + 9) as u128);
+        assert!(recent().len() <= MAX_RECENT);
+    }
+}
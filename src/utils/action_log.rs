@@ -0,0 +1,140 @@
+//! 実行した action (execute callback) の記録
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+
+/// One execute callback run by fzfw.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    /// RFC 3339, local time
+    pub timestamp: String,
+    pub mode: String,
+    /// Key bound to the callback (e.g. `ctrl-x`), or the callback name if not found
+    pub action: String,
+    /// Registered name of the callback (e.g. `callback3`), used to run it again
+    pub callback: String,
+    pub cwd: String,
+    pub query: String,
+    pub item: String,
+    #[serde(default)]
+    pub items: Vec<String>,
+    pub success: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// `{state_dir}/actions.jsonl` (see `config::state_dir`)
+pub fn log_file() -> String {
+    format!("{}/actions.jsonl", config::state_dir())
+}
+
+// これを超えたら古いものから捨てる
+const MAX_RECORDS: usize = 1000;
+
+// 追記の度に全部読むのは重いので、ファイルがこれより大きくなったときだけ切り詰める
+const COMPACT_THRESHOLD_BYTES: u64 = 1024 * 1024;
+
+/// Appends `record` to the log.
+pub fn append(record: &Record) -> Result<()> {
+    append_to(Path::new(&log_file()), record)
+}
+
+/// Logged records, newest first. Broken lines are skipped.
+pub fn list() -> Result<Vec<Record>> {
+    list_from(Path::new(&log_file()))
+}
+
+fn append_to(path: &Path, record: &Record) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    if file.metadata()?.len() > COMPACT_THRESHOLD_BYTES {
+        compact(path, MAX_RECORDS)?;
+    }
+    Ok(())
+}
+
+fn list_from(path: &Path) -> Result<Vec<Record>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+// 新しい方から max 件だけ残す
+fn compact(path: &Path, max: usize) -> Result<()> {
+    let content = std::fs::read_to_string(path)?;
+    let lines = content.lines().collect::<Vec<_>>();
+    if lines.len() <= max {
+        return Ok(());
+    }
+    let mut kept = lines[lines.len() - max..].join("\n");
+    kept.push('\n');
+    std::fs::write(path, kept)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(item: &str) -> Record {
+        Record {
+            timestamp: "2024-01-02T03:04:05+09:00".to_string(),
+            mode: "fd".to_string(),
+            action: "ctrl-x".to_string(),
+            callback: "callback3".to_string(),
+            cwd: "/tmp".to_string(),
+            query: String::new(),
+            item: item.to_string(),
+            items: vec![item.to_string()],
+            success: true,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn append_adds_one_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fzfw/actions.jsonl");
+        append_to(&path, &record("a")).unwrap();
+        assert_eq!(list_from(&path).unwrap().len(), 1);
+        append_to(&path, &record("b")).unwrap();
+        let records = list_from(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].item, "b");
+    }
+
+    #[test]
+    fn compact_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("actions.jsonl");
+        for item in ["a", "b", "c", "d", "e"] {
+            append_to(&path, &record(item)).unwrap();
+        }
+        compact(&path, 3).unwrap();
+        let items = list_from(&path)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.item)
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["e", "d", "c"]);
+    }
+}
@@ -0,0 +1,217 @@
+use once_cell::sync::Lazy;
+use unicode_width::UnicodeWidthChar;
+use unicode_width::UnicodeWidthStr;
+
+/// `FZFW_NO_COLOR=1`, or `NO_COLOR` set to a non-empty value (<https://no-color.org>).
+/// When true, the server strips ANSI escapes from every item and preview it sends.
+pub fn no_color() -> bool {
+    // 毎回環境変数を見るほどのものでもないので起動時に決める
+    static NO_COLOR: Lazy<bool> = Lazy::new(|| {
+        std::env::var("FZFW_NO_COLOR").is_ok_and(|v| v == "1")
+            || std::env::var("NO_COLOR").is_ok_and(|v| !v.is_empty())
+    });
+    *NO_COLOR
+}
+
+/// Removes ANSI escape sequences from `s`.
+///
+/// strip("\x1b[1;31mfoo\x1b[0m bar") => "foo bar"
+pub fn strip(s: &str) -> String {
+    if !s.contains('\x1b') {
+        return s.to_string();
+    }
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            read_escape(c, &mut chars);
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Splits `s` into lines whose display width is at most `columns`.
+/// ANSI escape sequences (`\x1b[...m` etc.) are treated as zero-width and never split.
+/// Active SGR attributes are closed at the end of each line and restored on the next one.
+///
+/// wrap("foobar", 3) => ["foo", "bar"]
+/// wrap("", 3) => [""]
+/// wrap("犬猫", 3) => ["犬", "猫"]
+/// wrap("\x1b[31mfoobar\x1b[0m", 3) => ["\x1b[31mfoo\x1b[0m", "\x1b[31mbar\x1b[0m"]
+pub fn wrap(s: &str, columns: usize) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut chunk = String::new();
+    let mut width = 0;
+    // 現在有効な SGR シーケンス。改行後に付け直す
+    let mut active = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            let seq = read_escape(c, &mut chars);
+            if seq.ends_with('m') && seq.starts_with("\x1b[") {
+                if is_reset(&seq) {
+                    active.clear();
+                } else {
+                    active.push_str(&seq);
+                }
+            }
+            chunk.push_str(&seq);
+            continue;
+        }
+        let c_width = c.width().unwrap_or(0);
+        if width > 0 && width + c_width > columns {
+            if !active.is_empty() {
+                chunk.push_str("\x1b[0m");
+            }
+            result.push(chunk);
+            chunk = active.clone();
+            width = 0;
+        }
+        chunk.push(c);
+        width += c_width;
+    }
+    // 空行も 1 行として残す
+    if !chunk.is_empty() || result.is_empty() {
+        result.push(chunk);
+    }
+    result
+}
+
+/// Applies [`wrap`] to every line of `s` and joins the results with newlines.
+/// Used for previews of plain text so that fzf never needs horizontal scrolling.
+pub fn wrap_lines(s: &str, columns: usize) -> String {
+    s.lines()
+        .flat_map(|line| wrap(line, columns))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Cuts `s` to display width `columns`, ending with `…` if anything was cut.
+/// Escape sequences before the cut are kept, so the visible part keeps its highlight.
+///
+/// truncate("foobar", 4) => "foo…"
+/// truncate("\x1b[31mfoobar\x1b[0m", 4) => "\x1b[31mfoo…\x1b[0m"
+pub fn truncate(s: &str, columns: usize) -> String {
+    // 大半の行は短いので先に判定する
+    if s.len() <= columns || strip(s).width() <= columns {
+        return s.to_string();
+    }
+    let mut result = String::new();
+    let mut width = 0;
+    let mut active = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            let seq = read_escape(c, &mut chars);
+            if seq.ends_with('m') && seq.starts_with("\x1b[") {
+                active = !is_reset(&seq);
+            }
+            result.push_str(&seq);
+            continue;
+        }
+        let c_width = c.width().unwrap_or(0);
+        // '…' の分を残しておく
+        if width + c_width > columns.saturating_sub(1) {
+            break;
+        }
+        result.push(c);
+        width += c_width;
+    }
+    result.push('…');
+    if active {
+        result.push_str("\x1b[0m");
+    }
+    result
+}
+
+/// Applies [`truncate`] to every line of `s`.
+pub fn truncate_lines(s: &str, columns: usize) -> String {
+    s.lines()
+        .map(|line| truncate(line, columns))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// ESC に続く CSI シーケンス (ESC [ ... final byte) を読む。
+// CSI 以外は ESC と次の1文字だけを読む。
+fn read_escape(esc: char, chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut seq = String::from(esc);
+    match chars.next() {
+        Some('[') => {
+            seq.push('[');
+            for c in chars.by_ref() {
+                seq.push(c);
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+        Some(c) => seq.push(c),
+        None => {}
+    }
+    seq
+}
+
+fn is_reset(sgr: &str) -> bool {
+    matches!(sgr, "\x1b[m" | "\x1b[0m")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_lines_keeps_empty_lines() {
+        assert_eq!(wrap_lines("foobar\n\nbaz\n\n", 3), "foo\nbar\n\nbaz\n");
+    }
+
+    #[test]
+    fn wrap_restores_color_on_each_line() {
+        assert_eq!(
+            wrap("\x1b[31mfoobar\x1b[0m", 3),
+            ["\x1b[31mfoo\x1b[0m", "\x1b[31mbar\x1b[0m"]
+        );
+        // 色の切り替えは幅を持たず、途中で分割しない
+        let wrapped = wrap("ab\x1b[1;32mcd\x1b[0mef", 3);
+        assert_eq!(wrapped, ["ab\x1b[1;32mc\x1b[0m", "\x1b[1;32md\x1b[0mef"]);
+        for line in wrapped {
+            assert!(strip(&line).width() <= 3);
+        }
+    }
+
+    #[test]
+    fn wrap_counts_cjk_as_two_columns() {
+        assert_eq!(wrap("犬猫", 3), ["犬", "猫"]);
+        assert_eq!(wrap("a犬b猫", 4), ["a犬b", "猫"]);
+        assert_eq!(
+            wrap("\x1b[33m日本語\x1b[0m", 4),
+            ["\x1b[33m日本\x1b[0m", "\x1b[33m語\x1b[0m"]
+        );
+    }
+
+    #[test]
+    fn strip_removes_sgr_and_other_csi() {
+        assert_eq!(strip("\x1b[1;31mfoo\x1b[0m bar"), "foo bar");
+        // カーソル移動や行消去などの CSI も消える
+        assert_eq!(strip("\x1b[2Kfoo\x1b[3Cbar\x1b[m"), "foobar");
+        assert_eq!(strip("no escape"), "no escape");
+    }
+
+    #[test]
+    fn truncate_cuts_long_lines() {
+        let long = "a".repeat(100_000);
+        let truncated = truncate(&long, 80);
+        assert_eq!(truncated.width(), 80);
+        assert!(truncated.ends_with('…'));
+
+        let highlighted = format!("\x1b[31m{long}\x1b[0m\nshort");
+        let lines = truncate_lines(&highlighted, 80);
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("\x1b[31m"));
+        assert!(lines[0].ends_with("…\x1b[0m"));
+        assert_eq!(strip(lines[0]).width(), 80);
+        assert_eq!(lines[1], "short");
+    }
+}
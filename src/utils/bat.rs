@@ -1,25 +1,363 @@
 use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
 use tokio::process::Command;
+use unicode_width::UnicodeWidthStr;
+
+use crate::utils::command;
+use crate::utils::glow;
+
+/// Like `render_file`, but renders Markdown files with glow (see `glow::renders_file`).
+pub async fn render_file_or_markdown(file: impl AsRef<str>, columns: usize) -> Result<String> {
+    if glow::renders_file(file.as_ref()) {
+        return glow::render_markdown_file(file.as_ref(), columns).await;
+    }
+    render_file(file).await
+}
 
 pub async fn render_file(file: impl AsRef<str>) -> Result<String> {
-    let output = Command::new("bat")
-        .args(vec!["--color", "always"])
-        .arg(file.as_ref())
-        .output()
-        .await?
-        .stdout;
+    if !*BAT_AVAILABLE {
+        return render_plain(file.as_ref(), 1, None).await;
+    }
+    let output = command::output_with_timeout(
+        "preview",
+        Command::new("bat")
+            .args(vec!["--color", "always"])
+            .arg(file.as_ref()),
+        command::preview_timeout(),
+    )
+    .await?
+    .stdout;
     Ok(String::from_utf8_lossy(output.as_slice()).into_owned())
 }
 
+/// Renders `text` (not a file) highlighted as `language` (e.g. `rust`).
+pub async fn render_text(text: impl AsRef<str>, language: Option<&str>) -> Result<String> {
+    if !*BAT_AVAILABLE {
+        return Ok(text.as_ref().to_string());
+    }
+    let mut bat = Command::new("bat");
+    bat.args(vec!["--color", "always"])
+        .args(vec!["--style", "plain"]);
+    if let Some(language) = language {
+        bat.args(vec!["--language", language]);
+    }
+    let output = command::output_with_stdin_and_timeout(
+        "preview",
+        &mut bat,
+        text.as_ref(),
+        command::preview_timeout(),
+    )
+    .await?;
+    if !output.status.success() {
+        // 知らない language などで失敗したらそのまま出す
+        return Ok(text.as_ref().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
 pub async fn render_file_with_highlight(file: impl AsRef<str>, line: isize) -> Result<String> {
     let start_line = std::cmp::max(0, line - 15);
-    let output = Command::new("bat")
-        .args(vec!["--color", "always"])
-        .args(vec!["--line-range", &format!("{start_line}:")])
-        .args(vec!["--highlight-line", &line.to_string()])
-        .arg(file.as_ref())
-        .output()
+    if !*BAT_AVAILABLE {
+        return render_plain(file.as_ref(), start_line as usize, Some(line as usize)).await;
+    }
+    let output = command::output_with_timeout(
+        "preview",
+        Command::new("bat")
+            .args(vec!["--color", "always"])
+            .args(vec!["--line-range", &format!("{start_line}:")])
+            .args(vec!["--highlight-line", &line.to_string()])
+            .arg(file.as_ref()),
+        command::preview_timeout(),
+    )
+    .await?
+    .stdout;
+    Ok(String::from_utf8_lossy(output.as_slice()).into_owned())
+}
+
+/// Like `render_file_with_highlight`, but shows line numbers and puts a caret
+/// (`^`) under column `col` (1-based, in bytes) of `line`.
+pub async fn render_file_with_column_indicator(
+    file: impl AsRef<str>,
+    line: isize,
+    col: usize,
+) -> Result<String> {
+    let start_line = std::cmp::max(0, line - 15);
+    let rendered = if *BAT_AVAILABLE {
+        let output = command::output_with_timeout(
+            "preview",
+            Command::new("bat")
+                .args(vec!["--color", "always"])
+                .args(vec!["--style", "numbers"])
+                .args(vec!["--decorations", "always"])
+                .args(vec!["--line-range", &format!("{start_line}:")])
+                .args(vec!["--highlight-line", &line.to_string()])
+                .arg(file.as_ref()),
+            command::preview_timeout(),
+        )
         .await?
         .stdout;
-    Ok(String::from_utf8_lossy(output.as_slice()).into_owned())
+        String::from_utf8_lossy(output.as_slice()).into_owned()
+    } else {
+        // 行番号の形式は GUTTER に合わせてあるので caret もそのまま出せる
+        render_plain(file.as_ref(), start_line as usize, Some(line as usize)).await?
+    };
+    // rg も nvim も BOM を除いた桁数で数えるので、ここでも除いておく
+    let source_line = tokio::fs::read(file.as_ref())
+        .await
+        .ok()
+        .and_then(|bytes| {
+            decode_text(&bytes)
+                .lines()
+                .nth((line as usize).saturating_sub(1))
+                .map(|s| s.to_string())
+        })
+        .unwrap_or_default();
+    Ok(insert_column_indicator(
+        &rendered,
+        line as usize,
+        col,
+        &source_line,
+    ))
+}
+
+// これより大きいファイルは bat に渡さない。FZFW_PREVIEW_MAX_BYTES で変更可
+static PREVIEW_MAX_BYTES: Lazy<u64> = Lazy::new(|| {
+    std::env::var("FZFW_PREVIEW_MAX_BYTES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10 * 1024 * 1024)
+});
+
+/// Returns a one-line summary (`file <size>, type <mime>`) instead of the content
+/// if `file` is too large or binary to be rendered. `None` means it can be rendered.
+pub async fn summary_if_unpreviewable(file: impl AsRef<str>) -> Result<Option<String>> {
+    summary_if_larger_or_binary(file.as_ref(), *PREVIEW_MAX_BYTES).await
+}
+
+async fn summary_if_larger_or_binary(file: &str, max_bytes: u64) -> Result<Option<String>> {
+    let meta = tokio::fs::metadata(file).await?;
+    if !meta.is_file() {
+        return Ok(None);
+    }
+    if meta.len() <= max_bytes && !is_binary(file).await? {
+        return Ok(None);
+    }
+    let mime = command::output_with_timeout(
+        "preview",
+        Command::new("file")
+            .arg("--brief")
+            .arg("--mime-type")
+            .arg(file),
+        command::preview_timeout(),
+    )
+    .await
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    .unwrap_or_else(|| "unknown".to_string());
+    Ok(Some(format!(
+        "file {}, type {mime}",
+        human_size(meta.len())
+    )))
+}
+
+// git と同じく先頭 8000 バイトに NUL があればバイナリとみなす
+async fn is_binary(file: &str) -> Result<bool> {
+    use tokio::io::AsyncReadExt;
+    let mut buf = vec![0; 8000];
+    let mut f = tokio::fs::File::open(file).await?;
+    let n = f.read(&mut buf).await?;
+    Ok(buf[..n].contains(&0))
+}
+
+fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}{}", units[0])
+    } else {
+        format!("{size:.1}{}", units[unit])
+    }
+}
+
+static BAT_AVAILABLE: Lazy<bool> = Lazy::new(|| {
+    let available = command::on_path("bat");
+    if !available {
+        info!("bat: not found on PATH, falling back to plain preview");
+    }
+    available
+});
+
+// fzf のプレビューに収まれば十分なので適当に打ち切る
+const PLAIN_MAX_LINES: usize = 1000;
+
+/// Renders `file` without bat: plain text with line numbers, starting at `start_line`.
+/// The highlighted line is shown in reverse video.
+async fn render_plain(file: &str, start_line: usize, highlight: Option<usize>) -> Result<String> {
+    let bytes = tokio::fs::read(file).await?;
+    let content = decode_text(&bytes);
+    let start_line = std::cmp::max(1, start_line);
+    let lines = content
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .skip(start_line - 1)
+        .take(PLAIN_MAX_LINES)
+        .map(|(n, l)| {
+            if Some(n) == highlight {
+                format!("{n:>4} \x1b[7m{l}\x1b[0m")
+            } else {
+                format!("{n:>4} {l}")
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(lines.join("\n"))
+}
+
+/// Decodes file content for display: strips a leading UTF-8 BOM and turns CRLF into LF.
+/// The file itself is left untouched.
+pub fn decode_text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let text = String::from_utf8_lossy(bytes);
+    // 単独の \r は改行にしない。rg は \n でしか行を数えないので行番号がずれる
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text.into_owned()
+    }
+}
+
+static ANSI_ESCAPE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+// --style=numbers のときの行番号部分。例: "  12 "
+static GUTTER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*(?P<num>\d+) ").unwrap());
+
+fn insert_column_indicator(rendered: &str, line: usize, col: usize, source_line: &str) -> String {
+    let mut result = vec![];
+    for l in rendered.lines() {
+        result.push(l.to_string());
+        let plain = ANSI_ESCAPE.replace_all(l, "");
+        let Some(c) = GUTTER.captures(&plain) else {
+            continue;
+        };
+        if c["num"].parse::<usize>().ok() != Some(line) {
+            continue;
+        }
+        let gutter_width = UnicodeWidthStr::width(&c[0]);
+        let prefix = source_line
+            .get(..col.saturating_sub(1))
+            .unwrap_or(source_line)
+            .replace('\t', "    "); // bat のデフォルトの --tabs=4
+        let offset = gutter_width + UnicodeWidthStr::width(prefix.as_str());
+        result.push(format!(
+            "{}{}",
+            " ".repeat(offset),
+            ansi_term::Colour::Red.bold().paint("^")
+        ));
+    }
+    result.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_text_keeps_lone_cr() {
+        assert_eq!(decode_text(b"\xef\xbb\xbfa\r\nb\rc\n"), "a\nb\rc\n");
+        assert_eq!(decode_text(b"a\nb"), "a\nb");
+    }
+
+    fn caret_column(indicator: &str) -> usize {
+        ANSI_ESCAPE.replace_all(indicator, "").find('^').unwrap()
+    }
+
+    #[test]
+    fn column_indicator_follows_gutter_width() {
+        // bat --style=numbers の行番号は色付きで、幅は行数の桁で変わる
+        let rendered = "\x1b[38;5;238m  11\x1b[0m \x1b[37mfoo\x1b[0m\n\x1b[38;5;238m  12\x1b[0m \x1b[37mlet x = 1;\x1b[0m";
+        let result = insert_column_indicator(rendered, 12, 5, "let x = 1;");
+        let lines = result.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(caret_column(lines[2]), "  12 ".len() + "let ".len());
+
+        let rendered = "   7 abc\n1000 abc";
+        let result = insert_column_indicator(rendered, 1000, 2, "abc");
+        assert_eq!(caret_column(result.lines().last().unwrap()), 5 + 1);
+    }
+
+    #[test]
+    fn column_indicator_counts_tabs_and_wide_chars() {
+        let result = insert_column_indicator("  3 \t日本x", 3, "\t日本".len() + 1, "\t日本x");
+        // タブは 4 桁、全角は 2 桁
+        assert_eq!(caret_column(result.lines().last().unwrap()), 4 + 4 + 4);
+    }
+
+    #[tokio::test]
+    async fn render_plain_numbers_lines_like_rg() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("crlf.txt");
+        std::fs::write(&file, b"\xef\xbb\xbffirst\r\nsec\rond\r\nthird\r\n").unwrap();
+        let file = file.to_str().unwrap();
+
+        let rendered = render_plain(file, 1, Some(3)).await.unwrap();
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "   1 first");
+        assert_eq!(lines[1], "   2 sec\rond");
+        assert_eq!(lines[2], "   3 \x1b[7mthird\x1b[0m");
+    }
+
+    #[tokio::test]
+    async fn plain_fallback_is_not_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        std::fs::write(&file, "fn main() {\n    println!(\"hi\");\n}\n").unwrap();
+        let file = file.to_str().unwrap();
+
+        let rendered = render_plain(file, 1, None).await.unwrap();
+        assert_eq!(rendered.lines().count(), 3);
+        assert!(rendered.starts_with("   1 fn main() {"));
+        // start_line より前は出さない
+        let rendered = render_plain(file, 3, Some(3)).await.unwrap();
+        assert_eq!(rendered, "   3 \x1b[7m}\x1b[0m");
+    }
+
+    #[tokio::test]
+    async fn unpreviewable_file_gets_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        std::fs::write(path("text"), "hello\n").unwrap();
+        std::fs::write(path("binary"), b"\x7fELF\0\0\0").unwrap();
+        std::fs::write(path("large"), "a".repeat(2048)).unwrap();
+
+        assert_eq!(
+            summary_if_larger_or_binary(&path("text"), 1024)
+                .await
+                .unwrap(),
+            None
+        );
+        let summary = summary_if_larger_or_binary(&path("binary"), 1024)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(summary.starts_with("file 7B, type "), "{summary}");
+        let summary = summary_if_larger_or_binary(&path("large"), 1024)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(summary.starts_with("file 2.0KiB, type "), "{summary}");
+        // ディレクトリは対象外
+        let dir_path = dir.path().to_string_lossy().into_owned();
+        assert_eq!(
+            summary_if_larger_or_binary(&dir_path, 0).await.unwrap(),
+            None
+        );
+    }
 }
@@ -1,6 +1,8 @@
 use std::process::Output;
 use std::process::Stdio;
+use std::time::Duration;
 
+use anyhow::anyhow;
 use anyhow::Result;
 use encoding_rs::Encoding;
 use encoding_rs::EUC_JP;
@@ -9,6 +11,8 @@ use encoding_rs::UTF_8;
 use futures::Stream;
 use futures::StreamExt;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
 use tokio::io::BufReader;
 use tokio::process::Command;
 use tokio::signal;
@@ -32,21 +36,114 @@ pub async fn edit_and_run(
     let output = Command::new("sh").arg("-c").arg(&cmd).output().await?;
     Ok((cmd, output))
 }
+/// Whether `program` is found in `PATH`.
+pub fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| on_path_in(program, &paths))
+}
+
+pub(crate) fn on_path_in(program: &str, paths: &std::ffi::OsStr) -> bool {
+    std::env::split_paths(paths).any(|dir| dir.join(program).is_file())
+}
+
+/// Timeout for external commands run by previews.
+/// `FZFW_PREVIEW_TIMEOUT_MS` (default: 3000)
+pub fn preview_timeout() -> Duration {
+    let ms = std::env::var("FZFW_PREVIEW_TIMEOUT_MS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(3000);
+    Duration::from_millis(ms)
+}
+
+/// Like `Command::output`, but kills the child and returns an error
+/// ("`label` timed out: ...") if it doesn't finish within `timeout`.
+/// `label` names what the command is for (e.g. `preview`, `clipboard`).
+pub async fn output_with_timeout(
+    label: &str,
+    command: &mut Command,
+    timeout: Duration,
+) -> Result<Output> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    // timeout で future が drop されたときに子プロセスも kill される
+    command.kill_on_drop(true);
+    match tokio::time::timeout(timeout, command.output()).await {
+        Ok(output) => Ok(output?),
+        Err(_) => Err(anyhow!(
+            "{label} timed out: {program} did not finish in {}ms",
+            timeout.as_millis()
+        )),
+    }
+}
+
+/// Like `output_with_timeout`, but feeds `input` to the command's stdin.
+pub async fn output_with_stdin_and_timeout(
+    label: &str,
+    command: &mut Command,
+    input: impl Into<Vec<u8>>,
+    timeout: Duration,
+) -> Result<Output> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture stdin"))?;
+    let input = input.into();
+    // 大きい入力で詰まらないように書き込みは別タスクで
+    tokio::spawn(async move {
+        let _ = stdin.write_all(&input).await;
+    });
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => Ok(output?),
+        Err(_) => Err(anyhow!(
+            "{label} timed out: {program} did not finish in {}ms",
+            timeout.as_millis()
+        )),
+    }
+}
+
 pub fn command_output_stream(command: Command) -> impl Stream<Item = Result<String>> {
     command_output_stream_with_encodings(command, vec![UTF_8, EUC_JP, SHIFT_JIS])
 }
 
+/// Streams stdout of `command` line by line.
+/// If the command exits with non-zero status and writes something to stderr,
+/// the stderr is yielded as an error at the end of the stream.
+/// (Non-zero exit without stderr is not an error: e.g. rg exits with 1 when nothing matched)
 pub fn command_output_stream_with_encodings(
     mut command: Command,
     encodings: Vec<&'static Encoding>,
 ) -> impl Stream<Item = Result<String>> {
     async_stream::stream! {
+        let program = command.as_std().get_program().to_string_lossy().into_owned();
         let mut child = command
             .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .spawn()?;
 
         let stdout = child.stdout.take()
             .ok_or_else(|| anyhow::anyhow!("Failed to capture stdout"))?;
+        let mut stderr = child.stderr.take()
+            .ok_or_else(|| anyhow::anyhow!("Failed to capture stderr"))?;
+
+        // stderr はパイプが詰まらないように別タスクで読んでおく
+        let stderr_task = tokio::spawn(async move {
+            let mut bytes = Vec::new();
+            let _ = stderr.read_to_end(&mut bytes).await;
+            bytes
+        });
 
         let read_stream = async_stream::stream! {
             let mut reader = BufReader::new(stdout);
@@ -69,34 +166,49 @@ pub fn command_output_stream_with_encodings(
         };
         tokio::pin!(read_stream);
 
+        // stdout を最後まで読んでから終了ステータスを見る
         loop {
             tokio::select! {
-                Some(line) = read_stream.next() => {
-                    yield line;
-                }
-                result = child.wait() => {
-                    match result {
-                        Ok(status) if status.success() => {
-                            // nop
-                        }
-                        _ => {
-                            info!("Child process exited with status: {:?}", result);
-                        }
+                line = read_stream.next() => {
+                    match line {
+                        Some(line) => yield line,
+                        None => break,
                     }
-                    break;
                 }
                 _ = signal::ctrl_c() => {
                     info!("Received SIGINT, terminating child process...");
                     if let Err(e) = child.kill().await {
                         eprintln!("Failed to kill child process: {}", e);
                     }
-                    break;
+                    return;
                 }
             }
         }
+
+        let status = child.wait().await?;
+        let stderr = stderr_task.await.unwrap_or_default();
+        if !status.success() {
+            let stderr = UTF_8.decode(&stderr).0.trim_end().to_string();
+            info!("Child process exited with status: {:?}", status; "stderr" => &stderr);
+            if !stderr.is_empty() {
+                yield Err(anyhow::anyhow!("{program}: {stderr}"));
+            }
+        }
     }
 }
 
+/// Splits a chunk of `command_output_stream` into the lines before the first error and the error.
+pub fn split_chunk(chunk: Vec<Result<String>>) -> (Vec<String>, Option<anyhow::Error>) {
+    let mut lines = vec![];
+    for r in chunk {
+        match r {
+            Ok(line) => lines.push(line),
+            Err(e) => return (lines, Some(e)),
+        }
+    }
+    (lines, None)
+}
+
 fn decode(bytes: &[u8], encodings: Vec<&'static Encoding>) -> Option<String> {
     for &encoding in &encodings {
         let (cow, _, had_errors) = encoding.decode(bytes);
@@ -106,3 +218,74 @@ fn decode(bytes: &[u8], encodings: Vec<&'static Encoding>) -> Option<String> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_path_looks_only_in_given_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("bat"), "").unwrap();
+        assert!(on_path_in("bat", dir.path().as_os_str()));
+        // PATH から外すと見つからない
+        assert!(!on_path_in("bat", std::ffi::OsStr::new("")));
+        assert!(!on_path_in("bat", std::ffi::OsStr::new("/nonexistent")));
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    #[tokio::test]
+    async fn stderr_of_failed_command_ends_the_stream() {
+        let stream = command_output_stream(sh("echo out; echo err >&2; exit 2"));
+        let lines = stream.collect::<Vec<_>>().await;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].as_ref().unwrap(), "out");
+        assert_eq!(lines[1].as_ref().unwrap_err().to_string(), "sh: err");
+
+        // 成功したなら stderr は無視する
+        let stream = command_output_stream(sh("echo out; echo err >&2"));
+        let lines = stream.collect::<Vec<_>>().await;
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].as_ref().unwrap(), "out");
+    }
+
+    #[tokio::test]
+    async fn timed_out_command_is_killed() {
+        let started = std::time::Instant::now();
+        let err = output_with_timeout("preview", &mut sh("sleep 10"), Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("preview timed out"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn stdin_is_fed_and_timeout_applies() {
+        let output = output_with_stdin_and_timeout(
+            "preview",
+            &mut sh("tr a-z A-Z"),
+            "hello",
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.stdout, b"HELLO");
+
+        let started = std::time::Instant::now();
+        let err = output_with_stdin_and_timeout(
+            "preview",
+            &mut sh("sleep 10"),
+            "hello",
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("preview timed out"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
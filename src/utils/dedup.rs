@@ -0,0 +1,93 @@
+//! mru / visits / browser-history などで同じものが複数回出てくるのをまとめる。
+//! `FZFW_DEDUP=1` のときだけ有効で、まとめた数を `foo (x3)` のように末尾に付ける。
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// 重複をまとめるかどうか (`FZFW_DEDUP=1`)
+pub fn enabled() -> bool {
+    std::env::var("FZFW_DEDUP").is_ok_and(|v| v == "1")
+}
+
+/// Removes items with the same `key`, keeping the first occurrence (= the most recent one
+/// if `items` is sorted from new to old), and returns each kept item with its number of occurrences.
+pub fn dedup_by_key<T, K: Eq + Hash>(items: Vec<T>, key: impl Fn(&T) -> K) -> Vec<(T, usize)> {
+    let mut positions: HashMap<K, usize> = HashMap::new();
+    let mut deduped: Vec<(T, usize)> = vec![];
+    for item in items {
+        let k = key(&item);
+        match positions.get(&k) {
+            Some(&i) => deduped[i].1 += 1,
+            None => {
+                positions.insert(k, deduped.len());
+                deduped.push((item, 1));
+            }
+        }
+    }
+    deduped
+}
+
+/// Appends ` (xN)` to `display` if `count` > 1. Reverted by `strip`.
+pub fn annotate(display: impl Into<String>, count: usize) -> String {
+    let display = display.into();
+    if count > 1 {
+        format!("{display} (x{count})")
+    } else {
+        display
+    }
+}
+
+/// Removes the ` (xN)` suffix added by `annotate`, if any.
+/// Does nothing unless `enabled()`, so that a file really named `foo (x2)` survives.
+pub fn strip(item: &str) -> &str {
+    if enabled() {
+        strip_count(item)
+    } else {
+        item
+    }
+}
+
+fn strip_count(item: &str) -> &str {
+    let Some((rest, count)) = item.rsplit_once(" (x") else {
+        return item;
+    };
+    match count.strip_suffix(')') {
+        Some(n) if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => item,
+    }
+}
+
+/// `dedup_by_key` + `annotate` for items which are used as is (paths etc).
+/// Does nothing unless `enabled()`.
+pub fn dedup_items(items: Vec<String>) -> Vec<String> {
+    if !enabled() {
+        return items;
+    }
+    dedup_by_key(items, |item| item.clone())
+        .into_iter()
+        .map(|(item, count)| annotate(item, count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedup_keeps_first_occurrence_and_counts() {
+        let items = vec!["b", "a", "b", "c", "b", "a"];
+        assert_eq!(
+            dedup_by_key(items, |x| *x),
+            vec![("b", 3), ("a", 2), ("c", 1)]
+        );
+    }
+
+    #[test]
+    fn annotate_and_strip_round_trip() {
+        assert_eq!(annotate("foo", 1), "foo");
+        assert_eq!(annotate("foo", 3), "foo (x3)");
+        assert_eq!(strip_count(&annotate("foo", 3)), "foo");
+        assert_eq!(strip_count("foo (xyz)"), "foo (xyz)");
+        assert_eq!(strip_count("foo (x)"), "foo (x)");
+    }
+}
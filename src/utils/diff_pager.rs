@@ -0,0 +1,69 @@
+use once_cell::sync::Lazy;
+use tokio::process::Command;
+
+use crate::utils::command;
+use crate::utils::fzf::PreviewWindow;
+
+#[derive(Clone, Copy)]
+enum Pager {
+    Delta,
+    DiffSoFancy,
+}
+
+impl Pager {
+    fn program(&self) -> &'static str {
+        match self {
+            Pager::Delta => "delta",
+            Pager::DiffSoFancy => "diff-so-fancy",
+        }
+    }
+}
+
+// FZFW_DIFF_PAGER=delta|diff-so-fancy (default: none)
+static PAGER: Lazy<Option<Pager>> = Lazy::new(|| {
+    let pager = match std::env::var("FZFW_DIFF_PAGER").as_deref() {
+        Ok("delta") => Pager::Delta,
+        Ok("diff-so-fancy") => Pager::DiffSoFancy,
+        Ok("") | Ok("none") | Err(_) => return None,
+        Ok(other) => {
+            error!("diff_pager: unknown FZFW_DIFF_PAGER"; "value" => other);
+            return None;
+        }
+    };
+    if !command::on_path(pager.program()) {
+        info!("diff_pager: not found on PATH, falling back to builtin"; "pager" => pager.program());
+        return None;
+    }
+    Some(pager)
+});
+
+/// Renders `diff` (unified diff, with or without colors) with `FZFW_DIFF_PAGER`.
+/// Returns `None` if no pager is configured or it failed, so the caller can fall back.
+pub async fn render(diff: &str, win: &PreviewWindow) -> Option<String> {
+    let pager = (*PAGER)?;
+    let mut cmd = Command::new(pager.program());
+    if let Pager::Delta = pager {
+        cmd.arg("--paging=never")
+            .arg(format!("--width={}", win.columns));
+    }
+    cmd.stderr(std::process::Stdio::null());
+    let output = match command::output_with_stdin_and_timeout(
+        "preview",
+        &mut cmd,
+        diff,
+        command::preview_timeout(),
+    )
+    .await
+    {
+        Ok(output) => output,
+        Err(e) => {
+            error!("diff_pager: failed"; "pager" => pager.program(), "error" => e.to_string());
+            return None;
+        }
+    };
+    if !output.status.success() {
+        error!("diff_pager: failed"; "pager" => pager.program());
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+
+// 1週間で重みが半分になる
+const HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+// これを超えたら古いものから捨てる
+const MAX_VISITS: usize = 10000;
+
+#[derive(Serialize, Deserialize)]
+struct Visit {
+    path: String,
+    time: i64, // unix time (secs)
+}
+
+/// `{state_dir}/dirs.jsonl` (see `config::state_dir`)
+pub fn history_file() -> String {
+    format!("{}/dirs.jsonl", config::state_dir())
+}
+
+/// Moves the cwd of fzfw to `dir` and records the visit.
+pub fn change_to(dir: &Path) -> Result<()> {
+    std::env::set_current_dir(dir)?;
+    record(dir)
+}
+
+/// Appends `dir` to the history.
+pub fn record(dir: &Path) -> Result<()> {
+    let path = history_file();
+    if let Some(parent) = Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let visit = Visit {
+        path: dir.to_string_lossy().into_owned(),
+        time: chrono::Local::now().timestamp(),
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&visit)?)?;
+    Ok(())
+}
+
+/// Existing directories in the history, sorted by frecency (descending).
+pub fn ranked() -> Result<Vec<String>> {
+    let path = history_file();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut visits = content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Visit>(line).ok())
+        .collect::<Vec<_>>();
+    if visits.len() > MAX_VISITS {
+        visits.drain(..visits.len() - MAX_VISITS);
+        compact(&path, &visits)?;
+    }
+
+    let now = chrono::Local::now().timestamp();
+    let mut scores: HashMap<&str, f64> = HashMap::new();
+    for visit in &visits {
+        *scores.entry(visit.path.as_str()).or_default() += weight(now - visit.time);
+    }
+    let mut dirs = scores
+        .into_iter()
+        .filter(|(dir, _)| Path::new(dir).is_dir())
+        .collect::<Vec<_>>();
+    dirs.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    Ok(dirs.into_iter().map(|(dir, _)| dir.to_string()).collect())
+}
+
+/// Weight of a visit `age_secs` ago: 1.0 now, halved every `HALF_LIFE_SECS`.
+fn weight(age_secs: i64) -> f64 {
+    let age = age_secs.max(0) as f64;
+    0.5_f64.powf(age / HALF_LIFE_SECS)
+}
+
+fn compact(path: &str, visits: &[Visit]) -> Result<()> {
+    let mut content = String::new();
+    for visit in visits {
+        content.push_str(&serde_json::to_string(visit)?);
+        content.push('\n');
+    }
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weight_halves_every_half_life() {
+        let half_life = HALF_LIFE_SECS as i64;
+        assert_eq!(weight(0), 1.0);
+        assert!((weight(half_life) - 0.5).abs() < 1e-9);
+        assert!((weight(2 * half_life) - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn weight_of_future_visit_is_one() {
+        // 時計が戻った場合でも 1 を超えない
+        assert_eq!(weight(-100), 1.0);
+    }
+}
@@ -1,11 +1,49 @@
 use tokio::process::Command;
 
-pub fn new() -> Command {
+#[derive(Clone, Copy, Debug)]
+pub struct FdOpts {
+    pub hidden: bool,
+    pub no_ignore: bool,
+    /// `--follow`. fd detects symlink loops by itself (reports them as errors
+    /// on stderr and skips them), so following never hangs the stream.
+    pub follow: bool,
+    /// List directories too (`--type d`), not only files.
+    pub directories: bool,
+}
+
+impl Default for FdOpts {
+    fn default() -> Self {
+        Self {
+            hidden: true,
+            no_ignore: true,
+            follow: true,
+            directories: false,
+        }
+    }
+}
+
+impl FdOpts {
+    pub fn args(&self) -> Vec<&'static str> {
+        let mut args = vec!["--type", "f"];
+        if self.directories {
+            args.extend(["--type", "d"]);
+        }
+        if self.hidden {
+            args.push("--hidden");
+        }
+        if self.no_ignore {
+            args.push("--no-ignore");
+        }
+        if self.follow {
+            args.push("--follow");
+        }
+        args
+    }
+}
+
+pub fn new(opts: FdOpts) -> Command {
     let mut fd = Command::new("fd");
-    fd.args(vec!["--hidden"]);
-    fd.args(vec!["--follow"]);
-    fd.args(vec!["--no-ignore"]);
-    fd.args(vec!["--type", "f"]);
+    fd.args(opts.args());
     fd.args(vec!["--exclude", ".git"]);
 
     let exclude_paths = std::env::var("FZFW_FD_EXCLUDE_PATHS");
@@ -25,3 +63,36 @@ pub fn new() -> Command {
     fd.kill_on_drop(true);
     fd
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args_lists_directories_only_when_enabled() {
+        let opts = FdOpts::default();
+        assert!(!opts.args().contains(&"d"));
+        let opts = FdOpts {
+            directories: true,
+            ..opts
+        };
+        assert_eq!(opts.args()[..4], ["--type", "f", "--type", "d"]);
+    }
+
+    #[test]
+    fn args_follow_hidden_and_no_ignore() {
+        for hidden in [false, true] {
+            for no_ignore in [false, true] {
+                let opts = FdOpts {
+                    hidden,
+                    no_ignore,
+                    ..FdOpts::default()
+                };
+                let args = opts.args();
+                assert_eq!(args.contains(&"--hidden"), hidden);
+                assert_eq!(args.contains(&"--no-ignore"), no_ignore);
+                assert_eq!(args[..2], ["--type", "f"]);
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use tokio::process::Command;
+
+/// Opens the directory containing `file` in `$FZFW_FILE_MANAGER` (default: xdg-open).
+/// The file manager is spawned detached so that it doesn't block the picker.
+pub fn reveal(file: impl AsRef<Path>) -> Result<()> {
+    let file = std::fs::canonicalize(file.as_ref())?;
+    let dir = if file.is_dir() {
+        file.as_path()
+    } else {
+        file.parent().ok_or(anyhow!("no parent dir"))?
+    };
+    let file_manager =
+        std::env::var("FZFW_FILE_MANAGER").unwrap_or_else(|_| "xdg-open".to_string());
+    Command::new(file_manager)
+        .arg(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! fd / mru の並び順 (alt-m で切り替える)
+
+use std::cmp::Reverse;
+use std::time::SystemTime;
+
+use crate::utils::icons;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum FileSort {
+    /// モードのデフォルト (fd の出力順、mru の新しい順)
+    #[default]
+    Default,
+    Path,
+    /// 更新日時の新しい順
+    Mtime,
+}
+
+impl FileSort {
+    /// Default -> Path -> Mtime -> Default
+    pub fn next(self) -> Self {
+        match self {
+            FileSort::Default => FileSort::Path,
+            FileSort::Path => FileSort::Mtime,
+            FileSort::Mtime => FileSort::Default,
+        }
+    }
+
+    /// For the prompt. `None` for the default order.
+    pub fn label(self) -> Option<&'static str> {
+        match self {
+            FileSort::Default => None,
+            FileSort::Path => Some("sort:path"),
+            FileSort::Mtime => Some("sort:mtime"),
+        }
+    }
+
+    /// Sorts decorated items (icons, pin marks, ...) by the path they point to.
+    pub fn sort(self, items: &mut [String]) {
+        match self {
+            FileSort::Default => {}
+            FileSort::Path => items.sort_by(|a, b| icons::strip(a).cmp(icons::strip(b))),
+            FileSort::Mtime => {
+                // 取れなければ一番古い扱い
+                items.sort_by_cached_key(|item| {
+                    let mtime = std::fs::metadata(icons::strip(item))
+                        .and_then(|m| m.modified())
+                        .unwrap_or(SystemTime::UNIX_EPOCH);
+                    Reverse(mtime)
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn path_sort_ignores_icons() {
+        let mut items = vec![
+            "\u{e7a8} b.rs".to_string(),
+            "a.rs".to_string(),
+            "\u{e7a8} a.rs".to_string(),
+        ];
+        FileSort::Path.sort(&mut items);
+        // 同じ path なら元の順のまま
+        assert_eq!(items, ["a.rs", "\u{e7a8} a.rs", "\u{e7a8} b.rs"]);
+
+        let mut items = vec!["b".to_string(), "a".to_string()];
+        FileSort::Default.sort(&mut items);
+        assert_eq!(items, ["b", "a"]);
+    }
+
+    #[test]
+    fn mtime_sort_puts_newest_first_and_missing_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let touch = |name: &str, secs: u64| {
+            let path = dir.path().join(name);
+            let file = std::fs::File::create(&path).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let old = touch("old", 1_000);
+        let new = touch("new", 2_000);
+        let tie = touch("tie", 2_000);
+        let missing = dir.path().join("missing").to_string_lossy().into_owned();
+
+        let mut items = vec![
+            missing.clone(),
+            old.clone(),
+            format!("\u{e7a8} {new}"),
+            tie.clone(),
+        ];
+        FileSort::Mtime.sort(&mut items);
+        assert_eq!(items, [format!("\u{e7a8} {new}"), tie, old, missing]);
+    }
+}
@@ -13,6 +13,8 @@ pub struct Config {
     pub myself: String,
     pub socket: String,
     pub log_file: String,
+    /// Passed to the load requests of this fzf as `FZFW_GENERATION`
+    pub generation: u64,
     pub load: Vec<String>,
     pub initial_prompt: String,
     pub initial_query: String,
@@ -39,6 +41,9 @@ pub enum Action {
     Execute(String),
     ExecuteSilent(String),
     ChangePrompt(String),
+    TransformPrompt(String),
+    Transform(String),
+    ChangePreview(String),
     ToggleSort,
     ClearQuery,
     ClearScreen,
@@ -48,12 +53,15 @@ pub enum Action {
 }
 
 impl Action {
-    fn render(&self, myself: &str) -> String {
+    pub fn render(&self, myself: &str) -> String {
         match self {
             Action::Reload(cmd) => format!("reload[{myself} {cmd}]"),
             Action::Execute(cmd) => format!("execute[{myself} {cmd}]"),
             Action::ExecuteSilent(cmd) => format!("execute-silent[{myself} {cmd}]"),
             Action::ChangePrompt(prompt) => format!("change-prompt[{prompt}]"),
+            Action::TransformPrompt(cmd) => format!("transform-prompt[{myself} {cmd}]"),
+            Action::Transform(cmd) => format!("transform[{myself} {cmd}]"),
+            Action::ChangePreview(cmd) => format!("change-preview[{myself} {cmd}]"),
             Action::ToggleSort => "toggle-sort".to_string(),
             Action::ClearQuery => "clear-query".to_string(),
             Action::ClearScreen => "clear-screen".to_string(),
@@ -71,11 +79,80 @@ pub struct PreviewWindow {
 }
 
 impl PreviewWindow {
-    pub fn from_env() -> Option<Self> {
-        let lines = std::env::var("FZF_PREVIEW_LINES").ok()?.parse().ok()?;
-        let columns = std::env::var("FZF_PREVIEW_COLUMNS").ok()?.parse().ok()?;
-        Some(Self { lines, columns })
+    /// The size fzf tells via `FZF_PREVIEW_LINES`/`FZF_PREVIEW_COLUMNS`.
+    /// Outside fzf (e.g. `fzfw preview` run by hand) falls back to the terminal size, then 80x24.
+    pub fn from_env() -> Self {
+        let env = |name: &str| std::env::var(name).ok().and_then(|s| s.parse().ok());
+        Self::from_sizes(
+            env("FZF_PREVIEW_LINES"),
+            env("FZF_PREVIEW_COLUMNS"),
+            terminal_size(),
+        )
     }
+
+    fn from_sizes(
+        lines: Option<usize>,
+        columns: Option<usize>,
+        terminal: Option<(usize, usize)>,
+    ) -> Self {
+        let lines = lines.or(terminal.map(|(lines, _)| lines)).unwrap_or(24);
+        let columns = columns
+            .or(terminal.map(|(_, columns)| columns))
+            .unwrap_or(80);
+        Self { lines, columns }
+    }
+
+    /// Lines in previews longer than this are cut off (see `ansi::truncate`).
+    /// `FZFW_PREVIEW_MAX_LINE_WIDTH=N` sets it to `columns * N`; unset or 0 means no limit.
+    pub fn max_line_width(&self) -> Option<usize> {
+        let n = std::env::var("FZFW_PREVIEW_MAX_LINE_WIDTH")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .filter(|n| *n > 0)?;
+        Some(self.columns.max(1) * n)
+    }
+}
+
+// (lines, columns)。stdout が端末でなければ stderr で試す
+fn terminal_size() -> Option<(usize, usize)> {
+    [libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        .find_map(|fd| {
+            let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+            let ok = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0;
+            (ok && size.ws_row > 0 && size.ws_col > 0)
+                .then_some((size.ws_row as usize, size.ws_col as usize))
+        })
+}
+
+// FZFW_FZF_COLORS (--color の値) と FZFW_FZF_OPTS (そのまま渡すオプション)
+fn env_opts(colors: Option<String>, opts: Option<String>) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(colors) = colors {
+        args.push("--color".to_string());
+        args.push(colors);
+    }
+    if let Some(opts) = opts {
+        match shellwords::split(&opts) {
+            Ok(opts) => args.extend(opts),
+            Err(e) => error!("fzf: invalid FZFW_FZF_OPTS"; "error" => e.to_string()),
+        }
+    }
+    args
+}
+
+/// The fzf binary to run. `FZFW_FZF_BIN` (default: `fzf`)
+//
+// skim (`sk`) も指定できるが、transform-prompt などの action や --delimiter/--with-nth の
+// 挙動が fzf と違うのでメインの画面はまともに動かない。select/input 程度なら動く。
+pub fn fzf_bin() -> String {
+    fzf_bin_from(std::env::var("FZFW_FZF_BIN").ok())
+}
+
+fn fzf_bin_from(fzf_bin: Option<String>) -> String {
+    fzf_bin
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "fzf".to_string())
 }
 
 pub fn new(config: Config) -> Command {
@@ -83,13 +160,14 @@ pub fn new(config: Config) -> Command {
         myself,
         socket,
         log_file,
+        generation,
         load,
         initial_prompt,
         initial_query,
         bindings,
         extra_opts,
     } = config;
-    let mut fzf = Command::new("fzf");
+    let mut fzf = Command::new(fzf_bin());
     fzf.kill_on_drop(true);
 
     // Envirionment variables
@@ -105,6 +183,7 @@ pub fn new(config: Config) -> Command {
     );
     fzf.env("FZFW_LOG_FILE", log_file);
     fzf.env("FZFW_SOCKET", socket);
+    fzf.env("FZFW_GENERATION", generation.to_string());
 
     let c = |s: &str| s.to_string();
 
@@ -114,9 +193,9 @@ pub fn new(config: Config) -> Command {
         c("--header-lines"), c("1"),
         c("--layout"), c("reverse"),
         c("--query"), initial_query,
-        c("--preview"), format!("{myself} preview {{}}"),
+        c("--preview"), format!("{myself} preview --index {{n}} {{}}"),
         c("--preview-window"), c("right:50%:noborder"),
-        c("--prompt"), initial_prompt
+        c("--prompt"), initial_prompt,
     ];
 
     bindings.0.iter().for_each(|(key, actions)| {
@@ -132,13 +211,20 @@ pub fn new(config: Config) -> Command {
         args.push(opt.to_string());
     });
 
+    // ユーザー指定のオプションが勝つように最後に置く
+    args.extend(crate::config::file().fzf_opts.iter().cloned());
+    args.extend(env_opts(
+        std::env::var("FZFW_FZF_COLORS").ok(),
+        std::env::var("FZFW_FZF_OPTS").ok(),
+    ));
+
     fzf.args(args);
 
     fzf
 }
 
 pub async fn select(items: Vec<&str>) -> Result<String> {
-    let mut fzf = Command::new("fzf")
+    let mut fzf = Command::new(fzf_bin())
         .arg("--ansi")
         .arg("--no-sort")
         .args(vec!["--layout", "reverse"])
@@ -158,7 +244,7 @@ pub async fn select(items: Vec<&str>) -> Result<String> {
 }
 
 pub async fn select_with_header(header: impl AsRef<str>, items: Vec<&str>) -> Result<String> {
-    let mut fzf = Command::new("fzf")
+    let mut fzf = Command::new(fzf_bin())
         .arg("--ansi")
         .arg("--no-sort")
         .args(vec!["--header-lines", "1"])
@@ -180,6 +266,39 @@ pub async fn select_with_header(header: impl AsRef<str>, items: Vec<&str>) -> Re
     )
 }
 
+/// Asks yes/no with `header`. "no" is the default (first) choice.
+pub async fn confirm(header: impl AsRef<str>) -> Result<bool> {
+    Ok(select_with_header(header, vec!["no", "yes"]).await? == "yes")
+}
+
+/// Like `select_with_header`, but allows selecting multiple items with tab.
+/// Returns the selected items (empty if cancelled).
+pub async fn select_multi_with_header(
+    header: impl AsRef<str>,
+    items: Vec<String>,
+) -> Result<Vec<String>> {
+    let mut fzf = Command::new(fzf_bin())
+        .arg("--ansi")
+        .arg("--no-sort")
+        .arg("--multi")
+        .args(vec!["--header", header.as_ref()])
+        .args(vec!["--layout", "reverse"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = fzf.stdin.take().unwrap();
+    stdin.write_all(items.join("\n").as_bytes()).await.unwrap();
+    drop(stdin);
+
+    Ok(
+        String::from_utf8_lossy(&fzf.wait_with_output().await?.stdout)
+            .lines()
+            .map(|s| s.to_string())
+            .collect(),
+    )
+}
+
 pub async fn input(header: impl AsRef<str>) -> Result<String> {
     input_with_placeholder(header, "").await
 }
@@ -188,7 +307,7 @@ pub async fn input_with_placeholder(
     header: impl AsRef<str>,
     placeholder: impl AsRef<str>,
 ) -> Result<String> {
-    let fzf = Command::new("fzf")
+    let fzf = Command::new(fzf_bin())
         .arg("--ansi")
         .args(vec!["--header", header.as_ref()])
         .args(vec!["--layout", "reverse"])
@@ -204,3 +323,95 @@ pub async fn input_with_placeholder(
             .to_string(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            myself: "fzfw".to_string(),
+            socket: "/tmp/fzfw.sock".to_string(),
+            log_file: "/tmp/fzfw.log".to_string(),
+            generation: 0,
+            load: vec!["load".to_string(), "default".to_string()],
+            initial_prompt: "fd>".to_string(),
+            initial_query: String::new(),
+            bindings: Bindings::empty(),
+            extra_opts: vec!["--no-sort".to_string()],
+        }
+    }
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn env_opts_come_after_builtin_args() {
+        let colors = "bg+:#262626,hl:#5f87af".to_string();
+        let opts = "--border 'rounded' --no-sort".to_string();
+        let env_opts = env_opts(Some(colors.clone()), Some(opts));
+        assert_eq!(
+            env_opts,
+            [
+                "--color",
+                colors.as_str(),
+                "--border",
+                "rounded",
+                "--no-sort"
+            ]
+        );
+    }
+
+    #[test]
+    fn command_ends_with_env_opts() {
+        // fzf では後ろのオプションが勝つ
+        let expected = env_opts(
+            std::env::var("FZFW_FZF_COLORS").ok(),
+            std::env::var("FZFW_FZF_OPTS").ok(),
+        );
+        let args = args(&new(config()));
+        assert!(args.ends_with(&expected));
+        assert!(args.contains(&"--no-sort".to_string()));
+    }
+
+    #[test]
+    fn no_env_opts_when_unset() {
+        assert!(env_opts(None, None).is_empty());
+        assert!(env_opts(None, Some("'unterminated".to_string())).is_empty());
+    }
+
+    #[test]
+    fn fzf_bin_can_be_overridden() {
+        assert_eq!(fzf_bin_from(None), "fzf");
+        assert_eq!(fzf_bin_from(Some(String::new())), "fzf");
+        assert_eq!(fzf_bin_from(Some("sk".to_string())), "sk");
+        assert_eq!(
+            fzf_bin_from(Some("/opt/fzf/bin/fzf".to_string())),
+            "/opt/fzf/bin/fzf"
+        );
+        // server のテストは FZFW_FZF_BIN を書き換えるので、値ではなく fzf_bin() と比べる
+        let program = new(config()).as_std().get_program().to_owned();
+        assert_eq!(program, std::ffi::OsString::from(fzf_bin()));
+    }
+
+    #[test]
+    fn preview_window_falls_back_to_terminal_size() {
+        let win = PreviewWindow::from_sizes(None, None, Some((50, 200)));
+        assert_eq!((win.lines, win.columns), (50, 200));
+        let win = PreviewWindow::from_sizes(Some(10), None, Some((50, 200)));
+        assert_eq!((win.lines, win.columns), (10, 200));
+        // 端末でもなければ 80x24
+        let win = PreviewWindow::from_sizes(None, None, None);
+        assert_eq!((win.lines, win.columns), (24, 80));
+
+        // fzf の外から呼ばれても panic しない
+        std::env::remove_var("FZF_PREVIEW_LINES");
+        std::env::remove_var("FZF_PREVIEW_COLUMNS");
+        let win = PreviewWindow::from_env();
+        assert!(win.lines > 0 && win.columns > 0);
+    }
+}
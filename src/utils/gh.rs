@@ -1,8 +1,12 @@
 use std::process::ExitStatus;
 
+use anyhow::anyhow;
 use anyhow::Result;
 use tokio::process::Command;
 
+use crate::utils::browser;
+use crate::utils::git;
+
 pub async fn browse_github(file: impl AsRef<str>) -> Result<()> {
     let _: ExitStatus = Command::new("gh")
         .arg("browse")
@@ -20,10 +24,144 @@ pub async fn browse_github_line(
 ) -> Result<()> {
     let _: ExitStatus = Command::new("gh")
         .arg("browse")
-        .arg(&format!("{}:{}", file.as_ref(), line))
-        .arg(&format!("--commit={}", revision.as_ref()))
+        .arg(format!("{}:{}", file.as_ref(), line))
+        .arg(format!("--commit={}", revision.as_ref()))
         .spawn()?
         .wait()
         .await?;
     Ok(())
 }
+
+/// The permalink of `file` (relative to the current directory, or absolute) at `revision`
+/// on the web UI of the `origin` remote, optionally pointing at the line range `lines` (1-based, inclusive).
+pub fn permalink(
+    file: impl AsRef<std::path::Path>,
+    revision: impl AsRef<str>,
+    lines: Option<(usize, usize)>,
+) -> Result<String> {
+    let remote = git::remote_url("origin")?;
+    let workdir = std::fs::canonicalize(git::workdir()?)?;
+    let file = std::fs::canonicalize(file.as_ref())?;
+    let file = file
+        .strip_prefix(&workdir)
+        .map_err(|_| anyhow!("{} is not in the repository", file.display()))?;
+    let file = file.to_str().ok_or(anyhow!("invalid utf-8 path"))?;
+    blob_url(&remote, revision.as_ref(), file, lines).ok_or(anyhow!("unsupported remote: {remote}"))
+}
+
+/// Opens `commit` on the web UI of the `origin` remote (github.com or gitlab.com).
+pub async fn browse_commit(commit: impl AsRef<str>) -> Result<()> {
+    let remote = git::remote_url("origin")?;
+    let url =
+        commit_url(&remote, commit.as_ref()).ok_or(anyhow!("unsupported remote: {remote}"))?;
+    let _: ExitStatus = Command::new(browser::get_browser().as_ref())
+        .arg(url)
+        .spawn()?
+        .wait()
+        .await?;
+    Ok(())
+}
+
+fn commit_url(remote: &str, commit: &str) -> Option<String> {
+    match web_repo(remote)? {
+        (Host::GitHub, base) => Some(format!("{base}/commit/{commit}")),
+        (Host::GitLab, base) => Some(format!("{base}/-/commit/{commit}")),
+    }
+}
+
+// `file` はリポジトリのルートからの相対パス
+fn blob_url(
+    remote: &str,
+    revision: &str,
+    file: &str,
+    lines: Option<(usize, usize)>,
+) -> Option<String> {
+    let (host, base) = web_repo(remote)?;
+    let anchor = match (host, lines) {
+        (_, None) => "".to_string(),
+        (_, Some((start, end))) if start == end => format!("#L{start}"),
+        (Host::GitHub, Some((start, end))) => format!("#L{start}-L{end}"),
+        (Host::GitLab, Some((start, end))) => format!("#L{start}-{end}"),
+    };
+    match host {
+        Host::GitHub => Some(format!("{base}/blob/{revision}/{file}{anchor}")),
+        Host::GitLab => Some(format!("{base}/-/blob/{revision}/{file}{anchor}")),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Host {
+    GitHub,
+    GitLab,
+}
+
+// remote の URL から (ホスト, https://host/owner/repo) を得る。
+// 対応している形式:
+//   git@github.com:owner/repo.git
+//   ssh://git@github.com[:port]/owner/repo.git
+//   https://[user@]gitlab.com/group/subgroup/repo.git
+fn web_repo(remote: &str) -> Option<(Host, String)> {
+    let (host, path) = match remote.split_once("://") {
+        Some((_scheme, rest)) => {
+            let (authority, path) = rest.split_once('/')?;
+            let host = authority.rsplit('@').next()?;
+            let host = host.split(':').next()?;
+            (host, path)
+        }
+        None => {
+            let (authority, path) = remote.split_once(':')?;
+            let host = authority.rsplit('@').next()?;
+            (host, path)
+        }
+    };
+    let path = path.trim_end_matches('/').trim_end_matches(".git");
+    match host {
+        "github.com" => Some((Host::GitHub, format!("https://github.com/{path}"))),
+        "gitlab.com" => Some((Host::GitLab, format!("https://gitlab.com/{path}"))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn web_repo_accepts_ssh_and_https_remotes() {
+        for remote in [
+            "git@github.com:owner/repo.git",
+            "git@github.com:owner/repo",
+            "ssh://git@github.com:22/owner/repo.git",
+            "https://github.com/owner/repo.git",
+            "https://user@github.com/owner/repo/",
+        ] {
+            let (host, base) = web_repo(remote).unwrap();
+            assert!(matches!(host, Host::GitHub), "{remote}");
+            assert_eq!(base, "https://github.com/owner/repo", "{remote}");
+        }
+        let (host, base) = web_repo("git@gitlab.com:group/sub/repo.git").unwrap();
+        assert!(matches!(host, Host::GitLab));
+        assert_eq!(base, "https://gitlab.com/group/sub/repo");
+        assert!(web_repo("git@example.com:owner/repo.git").is_none());
+    }
+
+    #[test]
+    fn blob_url_points_at_lines() {
+        assert_eq!(
+            blob_url("git@github.com:o/r.git", "abc", "src/main.rs", Some((3, 3))).as_deref(),
+            Some("https://github.com/o/r/blob/abc/src/main.rs#L3")
+        );
+        assert_eq!(
+            blob_url("https://github.com/o/r", "abc", "a.rs", Some((3, 5))).as_deref(),
+            Some("https://github.com/o/r/blob/abc/a.rs#L3-L5")
+        );
+        assert_eq!(
+            blob_url("ssh://git@gitlab.com/g/r.git", "abc", "a.rs", Some((3, 5))).as_deref(),
+            Some("https://gitlab.com/g/r/-/blob/abc/a.rs#L3-5")
+        );
+        assert_eq!(
+            blob_url("https://gitlab.com/g/r.git", "abc", "a.rs", None).as_deref(),
+            Some("https://gitlab.com/g/r/-/blob/abc/a.rs")
+        );
+    }
+}
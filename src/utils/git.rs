@@ -1,4 +1,6 @@
+use std::io::Write;
 use std::process::Output;
+use std::sync::Mutex;
 
 use anyhow::anyhow;
 use anyhow::Result;
@@ -8,9 +10,12 @@ use git2::Repository;
 use git2::Status;
 use git2::StatusEntry;
 use git2::StatusOptions;
+use once_cell::sync::Lazy;
 use regex::Regex;
+use tempfile::NamedTempFile;
 use tokio::process::Command;
 
+use crate::utils::command;
 use crate::utils::fzf;
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -18,6 +23,18 @@ use crate::utils::fzf;
 ////////////////////////////////////////////////////////////////////////////////
 
 pub async fn log_graph(commit: impl AsRef<str>) -> Result<Vec<String>> {
+    log_graph_with_paths(commit, &[]).await
+}
+
+/// Like `log_graph`, but only commits touching `path`.
+pub async fn log_graph_for_path(
+    commit: impl AsRef<str>,
+    path: impl AsRef<str>,
+) -> Result<Vec<String>> {
+    log_graph_with_paths(commit, &[path.as_ref()]).await
+}
+
+async fn log_graph_with_paths(commit: impl AsRef<str>, paths: &[&str]) -> Result<Vec<String>> {
     let commits = Command::new("git")
         .arg("log")
         .arg(
@@ -27,6 +44,8 @@ pub async fn log_graph(commit: impl AsRef<str>) -> Result<Vec<String>> {
         .arg("--graph")
         .arg("--color=always")
         .arg(commit.as_ref())
+        .arg("--")
+        .args(paths)
         .output()
         .await?
         .stdout;
@@ -73,28 +92,64 @@ pub fn parse_branches_of_log(line: impl AsRef<str>) -> Vec<String> {
 // Commit
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Format of `show_commit`. `FZFW_GIT_SHOW_FORMAT` (a `git show --format` string) overrides it.
+fn show_format() -> &'static str {
+    static FORMAT: Lazy<String> = Lazy::new(|| match std::env::var("FZFW_GIT_SHOW_FORMAT") {
+        Ok(format) if !format.is_empty() => format,
+        _ => [
+            "%C(yellow)commit %H%Creset",
+            "Author:       %aN <%aE>",
+            "AuthorDate:   %ai",
+            "Commiter:     %cN <%cE>",
+            "CommiterDate: %ci",
+            "Co-Author:    %(trailers:key=Co-authored-by)",
+            "Refname:      %D",
+            "",
+            "%w(0,2,2)%B",
+        ]
+        .join("%n"),
+    });
+    &FORMAT
+}
+
 pub async fn show_commit(commit: impl AsRef<str>) -> Result<String> {
-    let format = [
-        "%C(yellow)commit %H%Creset",
-        "Author:       %aN <%aE>",
-        "AuthorDate:   %ai",
-        "Commiter:     %cN <%cE>",
-        "CommiterDate: %ci",
-        "Co-Author:    %(trailers:key=Co-authored-by)",
-        "Refname:      %D",
-        "",
-        "%w(0,2,2)%B",
-    ]
-    .join("%n");
-    let commit = Command::new("git")
-        .arg("show")
+    show_commit_with_format(commit, show_format()).await
+}
+
+/// `git show` with a one-line header, for reading the patch rather than the message
+pub async fn show_patch(commit: impl AsRef<str>) -> Result<String> {
+    show_commit_with_format(commit, "%C(yellow)%h%Creset %s").await
+}
+
+async fn show_commit_with_format(commit: impl AsRef<str>, format: &str) -> Result<String> {
+    let mut cmd = show_command(commit.as_ref(), format);
+    let commit = command::output_with_timeout("preview", &mut cmd, command::preview_timeout())
+        .await?
+        .stdout;
+    Ok(String::from_utf8_lossy(commit.as_slice()).into_owned())
+}
+
+fn show_command(commit: &str, format: &str) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("show")
         .arg("--color=always")
         .arg(format!("--format={format}"))
+        .arg(commit);
+    cmd
+}
+
+pub async fn commit_message(commit: impl AsRef<str>) -> Result<String> {
+    let message = Command::new("git")
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%B")
         .arg(commit.as_ref())
         .output()
         .await?
         .stdout;
-    Ok(String::from_utf8_lossy(commit.as_slice()).into_owned())
+    Ok(String::from_utf8_lossy(message.as_slice())
+        .trim_end()
+        .to_string())
 }
 
 pub fn parse_short_commit(commit: impl AsRef<str>) -> Result<String> {
@@ -139,6 +194,116 @@ pub async fn diff_cached() -> Result<String> {
     Ok(String::from_utf8_lossy(diff.as_slice()).into_owned())
 }
 
+/// Summary of `git diff --shortstat`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShortStat {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl ShortStat {
+    // " 3 files changed, 10 insertions(+), 2 deletions(-)"
+    // 変更が無ければ空行。insertions/deletions は 0 なら省略される
+    pub fn parse(line: &str) -> Self {
+        let mut stat = ShortStat::default();
+        for part in line.split(',') {
+            let mut words = part.split_whitespace();
+            let (Some(n), Some(kind)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let Ok(n) = n.parse() else {
+                continue;
+            };
+            if kind.starts_with("file") {
+                stat.files = n;
+            } else if kind.starts_with("insertion") {
+                stat.insertions = n;
+            } else if kind.starts_with("deletion") {
+                stat.deletions = n;
+            }
+        }
+        stat
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files == 0
+    }
+
+    /// e.g. `3 files +10 -2`
+    pub fn render(&self) -> String {
+        let files = if self.files == 1 { "file" } else { "files" };
+        format!(
+            "{} {files} {} {}",
+            self.files,
+            ansi_term::Colour::Green.paint(format!("+{}", self.insertions)),
+            ansi_term::Colour::Red.paint(format!("-{}", self.deletions)),
+        )
+    }
+}
+
+/// `git diff [--cached] --shortstat`
+pub async fn shortstat(cached: bool) -> Result<ShortStat> {
+    let mut git = Command::new("git");
+    git.arg("diff").arg("--no-ext").arg("--shortstat");
+    if cached {
+        git.arg("--cached");
+    }
+    let output = git.output().await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git diff --shortstat failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(ShortStat::parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// `git merge-base --is-ancestor a b`
+pub async fn is_ancestor(a: impl AsRef<str>, b: impl AsRef<str>) -> Result<bool> {
+    let status = Command::new("git")
+        .arg("merge-base")
+        .arg("--is-ancestor")
+        .arg(a.as_ref())
+        .arg(b.as_ref())
+        .status()
+        .await?;
+    match status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(anyhow!("git merge-base --is-ancestor failed: {status}")),
+    }
+}
+
+/// `git diff --cached --quiet` exits with 1 if there are staged changes
+pub async fn has_staged_changes() -> Result<bool> {
+    let status = Command::new("git")
+        .arg("diff")
+        .arg("--cached")
+        .arg("--quiet")
+        .status()
+        .await?;
+    match status.code() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        _ => Err(anyhow!("git diff --cached --quiet failed: {status}")),
+    }
+}
+
+/// `git diff a...b --stat`: changes on `b` since it diverged from `a`
+pub async fn diff_stat_range(a: impl AsRef<str>, b: impl AsRef<str>) -> Result<String> {
+    let diff = Command::new("git")
+        .arg("diff")
+        .arg("--no-ext")
+        .arg(format!("{}...{}", a.as_ref(), b.as_ref()))
+        .arg("--stat")
+        .arg("--color=always")
+        .output()
+        .await?
+        .stdout;
+    Ok(String::from_utf8_lossy(diff.as_slice()).into_owned())
+}
+
 pub async fn apply(patch_file: String, args: Vec<&str>) -> Result<Output> {
     let r = Command::new("git")
         .current_dir(workdir()?)
@@ -221,6 +386,48 @@ pub async fn stage_file(file: impl AsRef<str>) -> Result<Output> {
     Ok(output)
 }
 
+pub async fn stage_files(files: &[String]) -> Result<Output> {
+    let output = Command::new("git")
+        .current_dir(workdir()?)
+        .arg("add")
+        .arg("--")
+        .args(files)
+        .output()
+        .await?;
+    Ok(output)
+}
+
+pub async fn stage_all() -> Result<Output> {
+    let output = Command::new("git")
+        .current_dir(workdir()?)
+        .arg("add")
+        .arg("-A")
+        .output()
+        .await?;
+    Ok(output)
+}
+
+pub async fn unstage_all() -> Result<Output> {
+    let output = Command::new("git")
+        .current_dir(workdir()?)
+        .arg("reset")
+        .arg("--quiet")
+        .output()
+        .await?;
+    Ok(output)
+}
+
+pub async fn unstage_files(files: &[String]) -> Result<Output> {
+    let output = Command::new("git")
+        .current_dir(workdir()?)
+        .arg("reset")
+        .arg("--")
+        .args(files)
+        .output()
+        .await?;
+    Ok(output)
+}
+
 pub async fn unstage_file(file: impl AsRef<str>) -> Result<Output> {
     let output = Command::new("git")
         .current_dir(workdir()?)
@@ -246,6 +453,51 @@ pub async fn restore_file(
     Ok(output)
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// Stash
+////////////////////////////////////////////////////////////////////////////////
+
+/// `git stash list`: `stash@{n}  <relative date>  <subject>`
+pub async fn stash_list() -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .arg("stash")
+        .arg("list")
+        .arg("--format=%gd  %cr  %gs")
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git stash list: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|s| s.to_string())
+        .collect())
+}
+
+/// Files touched by `stash` (`git stash show --name-only`)
+pub async fn stash_files(stash: impl AsRef<str>) -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .arg("stash")
+        .arg("show")
+        .arg("--name-only")
+        .arg(stash.as_ref())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git stash show: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|s| s.to_string())
+        .collect())
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Remote
 ////////////////////////////////////////////////////////////////////////////////
@@ -259,6 +511,15 @@ pub fn remotes() -> Result<Vec<String>> {
     Ok(remotes)
 }
 
+pub fn remote_url(remote: impl AsRef<str>) -> Result<String> {
+    let repo = get_repo()?;
+    let remote = repo.find_remote(remote.as_ref())?;
+    let url = remote
+        .url()
+        .ok_or(anyhow!("remote url is not valid utf-8"))?;
+    Ok(url.to_string())
+}
+
 pub async fn push(
     remote: impl AsRef<str>,
     from: impl AsRef<str>,
@@ -297,6 +558,36 @@ pub fn upstream_of(branch: impl AsRef<str>) -> Result<String> {
     Ok(upstream.name()?.ok_or(anyhow!("no upstream"))?.to_string())
 }
 
+/// HEAD が upstream に既に含まれているか (amend すると履歴の書き換えになるか)。
+/// upstream が無い場合は false
+pub fn is_head_pushed() -> Result<bool> {
+    let repo = get_repo()?;
+    let Ok(upstream) = upstream_of(head()?) else {
+        return Ok(false);
+    };
+    let head = repo.head()?.peel_to_commit()?.id();
+    let upstream = repo.revparse_single(&upstream)?.peel_to_commit()?.id();
+    let (ahead, _behind) = repo.graph_ahead_behind(head, upstream)?;
+    Ok(ahead == 0)
+}
+
+pub async fn amend(no_edit: bool) -> Result<Output> {
+    let mut command = Command::new("git");
+    command.current_dir(workdir()?).arg("commit").arg("--amend");
+    if no_edit {
+        command.arg("--no-edit");
+        Ok(command.output().await?)
+    } else {
+        // エディタを開くので stdio はそのまま引き継ぐ
+        let status = command.spawn()?.wait().await?;
+        Ok(Output {
+            status,
+            stdout: vec![],
+            stderr: vec![],
+        })
+    }
+}
+
 pub fn local_branches() -> Result<Vec<String>> {
     list_branches(Some(BranchType::Local))
 }
@@ -330,12 +621,167 @@ pub fn rev_parse(commitish: impl AsRef<str>) -> Result<String> {
         .to_string())
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// Rebase
+////////////////////////////////////////////////////////////////////////////////
+
+/// Whether a rebase is stopped in the middle (`.git/rebase-merge` or `.git/rebase-apply` exists)
+pub fn rebase_in_progress() -> bool {
+    match get_repo() {
+        Ok(repo) => {
+            repo.path().join("rebase-merge").exists() || repo.path().join("rebase-apply").exists()
+        }
+        Err(_) => false,
+    }
+}
+
+/// `git rebase --continue` without opening an editor for the commit message
+pub async fn rebase_continue() -> Result<Output> {
+    let output = Command::new("git")
+        .current_dir(workdir()?)
+        .env("GIT_EDITOR", ":")
+        .arg("rebase")
+        .arg("--continue")
+        .output()
+        .await?;
+    Ok(output)
+}
+
+pub async fn rebase_abort() -> Result<Output> {
+    let output = Command::new("git")
+        .current_dir(workdir()?)
+        .arg("rebase")
+        .arg("--abort")
+        .output()
+        .await?;
+    Ok(output)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Undo
+////////////////////////////////////////////////////////////////////////////////
+
+/// An action that reverts the last mutating git action.
+/// 取り消せない操作 (reset --hard や restore など) は積まないこと
+#[derive(Clone, Debug)]
+pub enum UndoAction {
+    /// Restore the index to a tree saved by `snapshot_index`
+    RestoreIndex { description: String, tree: String },
+    /// Re-apply a patch which was reverse-applied to the working tree (and the index)
+    ReapplyPatch {
+        description: String,
+        patch: Vec<u8>,
+        index: bool,
+    },
+    /// Delete a branch which was created
+    DeleteBranch { branch: String },
+    /// Point a branch (back) at a commit, re-creating it if deleted
+    ResetBranch { branch: String, commit: String },
+}
+
+impl UndoAction {
+    pub fn description(&self) -> String {
+        match self {
+            UndoAction::RestoreIndex { description, .. } => description.clone(),
+            UndoAction::ReapplyPatch { description, .. } => description.clone(),
+            UndoAction::DeleteBranch { branch } => format!("create branch {branch}"),
+            UndoAction::ResetBranch { branch, .. } => format!("change branch {branch}"),
+        }
+    }
+}
+
+const MAX_UNDO: usize = 32;
+
+static UNDO_STACK: Lazy<Mutex<Vec<UndoAction>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+pub fn push_undo(action: UndoAction) {
+    let mut stack = UNDO_STACK.lock().unwrap();
+    if stack.len() >= MAX_UNDO {
+        stack.remove(0);
+    }
+    stack.push(action);
+}
+
+pub fn last_undo() -> Option<UndoAction> {
+    UNDO_STACK.lock().unwrap().last().cloned()
+}
+
+pub fn pop_undo() -> Option<UndoAction> {
+    UNDO_STACK.lock().unwrap().pop()
+}
+
+/// Writes the current index as a tree object and returns its id.
+/// Fails while there are conflicts.
+pub fn snapshot_index() -> Result<String> {
+    Ok(get_repo()?.index()?.write_tree()?.to_string())
+}
+
+pub async fn undo(action: &UndoAction) -> Result<Output> {
+    let mut command = Command::new("git");
+    command.current_dir(workdir()?);
+    // patch は一時ファイル経由で渡すので command が終わるまで生かしておく
+    let mut _temp = None;
+    match action {
+        UndoAction::RestoreIndex { tree, .. } => {
+            command.arg("read-tree").arg(tree);
+        }
+        UndoAction::ReapplyPatch { patch, index, .. } => {
+            let mut temp = NamedTempFile::new()?;
+            temp.write_all(patch)?;
+            command.arg("apply");
+            if *index {
+                command.arg("--index");
+            }
+            command.arg(temp.path());
+            _temp = Some(temp);
+        }
+        UndoAction::DeleteBranch { branch } => {
+            command.arg("branch").arg("-D").arg(branch);
+        }
+        UndoAction::ResetBranch { branch, commit } => {
+            command.arg("branch").arg("-f").arg(branch).arg(commit);
+        }
+    }
+    Ok(command.output().await?)
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Repository
 ////////////////////////////////////////////////////////////////////////////////
 
+// dotfiles を `git --git-dir=$HOME/.dotfiles --work-tree=$HOME` で管理している場合用。
+// FZFW_GIT_DIR が指定されていればそちらを優先する
 pub fn get_repo() -> Result<Repository> {
-    Ok(Repository::discover(".")?)
+    open_repo(
+        std::env::var("FZFW_GIT_DIR").ok(),
+        std::env::var("FZFW_GIT_WORK_TREE").ok(),
+    )
+}
+
+fn open_repo(git_dir: Option<String>, work_tree: Option<String>) -> Result<Repository> {
+    match git_dir {
+        Some(git_dir) => {
+            let git_dir = shellexpand::tilde(&git_dir).to_string();
+            let repo = Repository::open(git_dir)?;
+            if let Some(work_tree) = work_tree {
+                let work_tree = shellexpand::tilde(&work_tree).to_string();
+                repo.set_workdir(std::path::Path::new(&work_tree), false)?;
+            }
+            Ok(repo)
+        }
+        None => Ok(Repository::discover(".")?),
+    }
+}
+
+// git コマンドを直接叩いている箇所も同じリポジトリを見るように
+// FZFW_GIT_DIR/FZFW_GIT_WORK_TREE を GIT_DIR/GIT_WORK_TREE として子プロセスに渡す
+pub fn export_git_env() {
+    if let Ok(git_dir) = std::env::var("FZFW_GIT_DIR") {
+        std::env::set_var("GIT_DIR", shellexpand::tilde(&git_dir).to_string());
+        if let Ok(work_tree) = std::env::var("FZFW_GIT_WORK_TREE") {
+            std::env::set_var("GIT_WORK_TREE", shellexpand::tilde(&work_tree).to_string());
+        }
+    }
 }
 
 pub fn workdir() -> Result<String> {
@@ -346,3 +792,81 @@ pub fn workdir() -> Result<String> {
         .unwrap()
         .to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_repo_uses_separate_work_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path().join("dotfiles.git");
+        let home = dir.path().join("home");
+        Repository::init_bare(&git_dir).unwrap();
+        std::fs::create_dir(&home).unwrap();
+        std::fs::write(home.join(".bashrc"), "").unwrap();
+
+        let repo = open_repo(
+            Some(git_dir.to_string_lossy().into_owned()),
+            Some(home.to_string_lossy().into_owned()),
+        )
+        .unwrap();
+        assert!(!repo.is_bare());
+        assert_eq!(
+            repo.workdir().unwrap().canonicalize().unwrap(),
+            home.canonicalize().unwrap()
+        );
+        let statuses = repo.statuses(None).unwrap();
+        let paths = statuses
+            .iter()
+            .filter_map(|s| s.path().map(|p| p.to_string()));
+        assert_eq!(paths.collect::<Vec<_>>(), [".bashrc"]);
+    }
+
+    #[test]
+    fn show_command_passes_format() {
+        let cmd = show_command("abc123", "%H%n%B");
+        let args = cmd.as_std().get_args().collect::<Vec<_>>();
+        assert_eq!(
+            args,
+            ["show", "--color=always", "--format=%H%n%B", "abc123"]
+        );
+    }
+
+    #[test]
+    fn default_show_format_keeps_fields() {
+        if std::env::var("FZFW_GIT_SHOW_FORMAT").is_ok() {
+            return;
+        }
+        let format = show_format();
+        assert!(format.contains("Author:       %aN <%aE>"));
+        assert!(format.ends_with("%w(0,2,2)%B"));
+    }
+
+    #[test]
+    fn shortstat_parses_singular_and_plural() {
+        let stat = |files, insertions, deletions| ShortStat {
+            files,
+            insertions,
+            deletions,
+        };
+        assert_eq!(
+            ShortStat::parse(" 1 file changed, 1 insertion(+), 1 deletion(-)\n"),
+            stat(1, 1, 1)
+        );
+        assert_eq!(
+            ShortStat::parse(" 3 files changed, 10 insertions(+), 2 deletions(-)\n"),
+            stat(3, 10, 2)
+        );
+        // 0 の方は省略される
+        assert_eq!(
+            ShortStat::parse(" 2 files changed, 5 insertions(+)\n"),
+            stat(2, 5, 0)
+        );
+        assert_eq!(
+            ShortStat::parse(" 1 file changed, 4 deletions(-)\n"),
+            stat(1, 0, 4)
+        );
+        assert!(ShortStat::parse("").is_empty());
+    }
+}
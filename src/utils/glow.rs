@@ -1,18 +1,117 @@
-use tokio::io::AsyncWriteExt;
+use once_cell::sync::Lazy;
 use tokio::process::Command;
 
 use anyhow::Result;
 
+use crate::utils::command;
+
 pub async fn render_markdown(md: String) -> Result<String> {
-    let mut glow = Command::new("glow")
-        .args(vec!["-s", "dark"])
-        .args(vec!["-"])
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .spawn()?;
-    let mut stdin = glow.stdin.take().unwrap();
-    stdin.write_all(md.as_bytes()).await.unwrap();
-    drop(stdin);
-    let glow_output = glow.wait_with_output().await?;
+    let glow_output = command::output_with_stdin_and_timeout(
+        "preview",
+        Command::new("glow")
+            .args(vec!["-s", "dark"])
+            .args(vec!["-"]),
+        md,
+        command::preview_timeout(),
+    )
+    .await?;
     Ok(String::from_utf8_lossy(glow_output.stdout.as_slice()).to_string())
 }
+
+/// Renders a Markdown file wrapped to `columns`.
+pub async fn render_markdown_file(file: impl AsRef<str>, columns: usize) -> Result<String> {
+    let output = command::output_with_timeout(
+        "preview",
+        Command::new("glow")
+            .args(vec!["-s", "dark"])
+            .args(vec!["-w".to_string(), columns.to_string()])
+            .arg(file.as_ref()),
+        command::preview_timeout(),
+    )
+    .await?;
+    Ok(String::from_utf8_lossy(output.stdout.as_slice()).into_owned())
+}
+
+/// Whether the preview of `file` should be rendered with glow instead of bat:
+/// `file` is Markdown, `FZFW_MARKDOWN_PREVIEW` is not `bat` (default: `glow`), and glow is installed.
+pub fn renders_file(file: &str) -> bool {
+    renders_file_with(file, markdown_preview(), *GLOW_AVAILABLE)
+}
+
+fn renders_file_with(file: &str, preview: MarkdownPreview, glow_available: bool) -> bool {
+    is_markdown(file) && preview == MarkdownPreview::Glow && glow_available
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum MarkdownPreview {
+    Glow,
+    Bat,
+}
+
+fn markdown_preview() -> MarkdownPreview {
+    parse_markdown_preview(std::env::var("FZFW_MARKDOWN_PREVIEW").ok().as_deref())
+}
+
+fn parse_markdown_preview(value: Option<&str>) -> MarkdownPreview {
+    match value {
+        Some("bat") => MarkdownPreview::Bat,
+        _ => MarkdownPreview::Glow,
+    }
+}
+
+fn is_markdown(file: &str) -> bool {
+    let ext = std::path::Path::new(file)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown")
+}
+
+static GLOW_AVAILABLE: Lazy<bool> = Lazy::new(|| {
+    let available = command::on_path("glow");
+    if !available {
+        info!("glow: not found on PATH, falling back to bat for markdown");
+    }
+    available
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_markdown_checks_extension() {
+        assert!(is_markdown("README.md"));
+        assert!(is_markdown("docs/guide.MARKDOWN"));
+        assert!(!is_markdown("src/main.rs"));
+        assert!(!is_markdown("md"));
+    }
+
+    #[test]
+    fn markdown_preview_defaults_to_glow() {
+        assert_eq!(parse_markdown_preview(None), MarkdownPreview::Glow);
+        assert_eq!(parse_markdown_preview(Some("glow")), MarkdownPreview::Glow);
+        assert_eq!(parse_markdown_preview(Some("bat")), MarkdownPreview::Bat);
+    }
+
+    #[test]
+    fn falls_back_to_bat_without_glow() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("glow"), "").unwrap();
+        let installed = command::on_path_in("glow", dir.path().as_os_str());
+        assert!(renders_file_with(
+            "README.md",
+            MarkdownPreview::Glow,
+            installed
+        ));
+        // PATH に無ければ bat で表示する
+        let installed = command::on_path_in("glow", std::ffi::OsStr::new(""));
+        assert!(!renders_file_with(
+            "README.md",
+            MarkdownPreview::Glow,
+            installed
+        ));
+        assert!(!renders_file_with("README.md", MarkdownPreview::Bat, true));
+        assert!(!renders_file_with("main.rs", MarkdownPreview::Glow, true));
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use crate::utils::pins;
+
+/// Nerd Font のアイコンを付けるかどうか (`FZFW_ICONS=1`)
+pub fn enabled() -> bool {
+    std::env::var("FZFW_ICONS").is_ok_and(|v| v == "1")
+}
+
+const DEFAULT_ICON: &str = "\u{f15b}";
+
+const FILENAME_ICONS: &[(&str, &str)] = &[
+    ("Dockerfile", "\u{f308}"),
+    ("Makefile", "\u{e779}"),
+    ("Cargo.lock", "\u{e7a8}"),
+    ("Cargo.toml", "\u{e7a8}"),
+    ("flake.lock", "\u{f313}"),
+    (".gitignore", "\u{e702}"),
+    (".gitmodules", "\u{e702}"),
+];
+
+const EXTENSION_ICONS: &[(&str, &str)] = &[
+    ("rs", "\u{e7a8}"),
+    ("py", "\u{e606}"),
+    ("js", "\u{e74e}"),
+    ("ts", "\u{e628}"),
+    ("tsx", "\u{e7ba}"),
+    ("jsx", "\u{e7ba}"),
+    ("json", "\u{e60b}"),
+    ("md", "\u{e609}"),
+    ("toml", "\u{e615}"),
+    ("yaml", "\u{e615}"),
+    ("yml", "\u{e615}"),
+    ("lua", "\u{e620}"),
+    ("nix", "\u{f313}"),
+    ("sh", "\u{f489}"),
+    ("bash", "\u{f489}"),
+    ("zsh", "\u{f489}"),
+    ("go", "\u{e626}"),
+    ("hs", "\u{e61f}"),
+    ("html", "\u{e736}"),
+    ("css", "\u{e749}"),
+    ("c", "\u{e61e}"),
+    ("h", "\u{e61e}"),
+    ("cpp", "\u{e61d}"),
+    ("cc", "\u{e61d}"),
+    ("hpp", "\u{e61d}"),
+    ("java", "\u{e738}"),
+    ("rb", "\u{e739}"),
+    ("vim", "\u{e62b}"),
+    ("lock", "\u{f023}"),
+    ("txt", "\u{f0f6}"),
+];
+
+pub fn icon_for(path: impl AsRef<Path>) -> &'static str {
+    let path = path.as_ref();
+    let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    if let Some((_, icon)) = FILENAME_ICONS.iter().find(|(name, _)| *name == filename) {
+        return icon;
+    }
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+    EXTENSION_ICONS
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+        .map_or(DEFAULT_ICON, |(_, icon)| icon)
+}
+
+/// `FZFW_ICONS=1` のときだけアイコンを前置する
+pub fn decorate(path: impl AsRef<str>) -> String {
+    let path = path.as_ref();
+    if enabled() {
+        format!("{} {}", icon_for(path), path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Removes the icon prefix added by `decorate` (and the pin mark before it), if any.
+/// アイコンの有無に関わらず呼んでよい
+pub fn strip(item: &str) -> &str {
+    let item = item.strip_prefix(pins::MARK).unwrap_or(item);
+    let mut chars = item.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), Some(' ')) if is_private_use(c) => chars.as_str(),
+        _ => item,
+    }
+}
+
+fn is_private_use(c: char) -> bool {
+    matches!(c, '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{FFFFD}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icon_for_prefers_filename_over_extension() {
+        assert_eq!(icon_for("src/main.rs"), "\u{e7a8}");
+        assert_eq!(icon_for("README.MD"), "\u{e609}");
+        assert_eq!(icon_for("docker/Dockerfile"), "\u{f308}");
+        assert_eq!(icon_for("flake.lock"), "\u{f313}");
+        assert_eq!(icon_for("yarn.lock"), "\u{f023}");
+        assert_eq!(icon_for("LICENSE"), DEFAULT_ICON);
+    }
+
+    #[test]
+    fn strip_round_trips_decorated_paths() {
+        for path in [
+            "src/main.rs",
+            "a dir/with space.txt",
+            "LICENSE",
+            ".gitignore",
+        ] {
+            let decorated = format!("{} {}", icon_for(path), path);
+            assert_eq!(strip(&decorated), path);
+            assert_eq!(strip(&format!("{}{decorated}", pins::MARK)), path);
+            // アイコンが無くてもそのまま
+            assert_eq!(strip(path), path);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use anyhow::Result;
+use tokio::process::Command;
+
+use crate::utils::command;
+use crate::utils::fzf::PreviewWindow;
+
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "bmp", "webp", "tiff", "tif", "ico", "svg",
+];
+
+pub fn is_image(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+/// Renders an image for the preview window with the backend selected by
+/// `FZFW_IMAGE_PREVIEW` (`chafa` (default) | `kitty` | `wezterm`).
+/// Falls back to a plain message when the backend is not available.
+pub async fn render_image(path: impl AsRef<str>, win: &PreviewWindow) -> Result<String> {
+    let path = path.as_ref();
+    let size = format!("{}x{}", win.columns, win.lines);
+    let backend = std::env::var("FZFW_IMAGE_PREVIEW").unwrap_or_else(|_| "chafa".to_string());
+    let mut command = match backend.as_str() {
+        "kitty" => {
+            let mut command = Command::new("kitty");
+            command
+                .arg("icat")
+                .arg("--clear")
+                .arg("--transfer-mode=memory")
+                .arg("--unicode-placeholder")
+                .arg("--stdin=no")
+                .arg(format!("--place={size}@0x0"))
+                .arg(path);
+            command
+        }
+        "wezterm" => {
+            let mut command = Command::new("wezterm");
+            command
+                .arg("imgcat")
+                .arg("--width")
+                .arg(win.columns.to_string())
+                .arg("--height")
+                .arg(win.lines.to_string())
+                .arg(path);
+            command
+        }
+        _ => {
+            let mut command = Command::new("chafa");
+            command
+                .arg("--size")
+                .arg(size)
+                .arg("--animate=off")
+                .arg(path);
+            command
+        }
+    };
+    match command::output_with_timeout("preview", &mut command, command::preview_timeout()).await {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        Ok(output) => {
+            warn!("image: render failed"; "backend" => &backend, "stderr" => String::from_utf8_lossy(&output.stderr).into_owned());
+            Ok(fallback(path))
+        }
+        Err(e) => {
+            warn!("image: render failed"; "backend" => &backend, "error" => e.to_string());
+            Ok(fallback(path))
+        }
+    }
+}
+
+fn fallback(path: &str) -> String {
+    format!("image file: {path}")
+}
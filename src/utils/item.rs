@@ -0,0 +1,51 @@
+//! fzf の item を「表示部分」と「機械可読な payload」に分けて持つためのヘルパー。
+//! 区切りには title や path に現れない ASCII unit separator (`\x1f`) を使う。
+//! これを使う mode は `FZF_OPTS` を fzf に渡して payload を隠す。
+
+pub const SEPARATOR: char = '\x1f';
+
+/// Hides the payload in fzf. Return these from `ModeDef::fzf_extra_opts` of modes using `render_item`.
+pub const FZF_OPTS: [&str; 4] = ["--delimiter", "\x1f", "--with-nth", "1"];
+
+/// `display` と `payload` を `SEPARATOR` で繋ぐ。
+/// 万が一どちらかに `SEPARATOR` が含まれていたら空白に置き換える。
+pub fn render_item(display: impl AsRef<str>, payload: impl AsRef<str>) -> String {
+    let display = display.as_ref().replace(SEPARATOR, " ");
+    let payload = payload.as_ref().replace(SEPARATOR, " ");
+    format!("{display}{SEPARATOR}{payload}")
+}
+
+/// `render_item` の逆。`SEPARATOR` を含まない行は全体を display とみなす。
+pub fn parse_item(line: &str) -> (&str, &str) {
+    line.split_once(SEPARATOR).unwrap_or((line, ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_with_pipes_and_spaces() {
+        let line = render_item("a | b  c", r#"{"url":"https://x.test/?q=a|b c"}"#);
+        assert_eq!(
+            parse_item(&line),
+            ("a | b  c", r#"{"url":"https://x.test/?q=a|b c"}"#)
+        );
+    }
+
+    #[test]
+    fn separator_in_parts_becomes_space() {
+        let line = render_item("a\x1fb", "c\x1fd");
+        assert_eq!(parse_item(&line), ("a b", "c d"));
+    }
+
+    #[test]
+    fn line_without_separator_is_display() {
+        assert_eq!(parse_item("plain | line"), ("plain | line", ""));
+    }
+
+    #[test]
+    fn fzf_opts_split_on_separator() {
+        assert_eq!(FZF_OPTS[1], SEPARATOR.to_string());
+    }
+}
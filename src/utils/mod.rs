@@ -1,14 +1,27 @@
+pub mod action_log;
+pub mod ansi;
 pub mod bat;
 pub mod browser;
 pub mod command;
+pub mod dedup;
+pub mod diff_pager;
+pub mod dirs;
 pub mod fd;
+pub mod file_manager;
+pub mod file_sort;
 pub mod fzf;
 pub mod gh;
 pub mod git;
 pub mod glow;
+pub mod icons;
+pub mod image;
+pub mod item;
 pub mod path;
+pub mod pins;
+pub mod query_history;
 pub mod rg;
 pub mod sqlite;
+pub mod trash;
 pub mod vscode;
 pub mod xsel;
 pub mod zoxide;
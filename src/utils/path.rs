@@ -11,3 +11,68 @@ pub fn to_relpath(path: impl AsRef<Path>) -> String {
         .expect("Invalid UTF-8 path")
         .to_string()
 }
+
+/// Inverse of `to_relpath`. A relative path is resolved against the current directory
+/// (i.e. the cwd of the server, which `to_relpath` stripped).
+/// Falls back to the joined path if it cannot be canonicalized (e.g. the file was deleted).
+pub fn to_abspath(path: impl AsRef<Path>) -> String {
+    let path = path.as_ref();
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    std::fs::canonicalize(&joined)
+        .unwrap_or(joined)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The absolute path of the directory containing `path`
+pub fn parent_dir(path: impl AsRef<Path>) -> String {
+    let abspath = to_abspath(path);
+    Path::new(&abspath)
+        .parent()
+        .unwrap_or(Path::new("/"))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_path_is_resolved_against_cwd() {
+        let cwd = std::fs::canonicalize(std::env::current_dir().unwrap()).unwrap();
+        let expected = cwd.join("Cargo.toml").to_string_lossy().into_owned();
+        assert_eq!(to_abspath("Cargo.toml"), expected);
+        assert_eq!(to_abspath("./src/../Cargo.toml"), expected);
+    }
+
+    #[test]
+    fn abspath_follows_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real.txt");
+        std::fs::write(&real, "").unwrap();
+        let link = dir.path().join("link.txt");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        assert_eq!(to_abspath(&link), to_abspath(&real));
+        assert!(Path::new(&to_abspath(&link)).is_absolute());
+    }
+
+    #[test]
+    fn missing_file_keeps_joined_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("deleted.txt");
+        assert_eq!(to_abspath(&missing), missing.to_string_lossy());
+    }
+
+    #[test]
+    fn relpath_round_trips_through_abspath() {
+        let cwd = std::env::current_dir().unwrap();
+        let abspath = cwd.join("src/main.rs");
+        assert_eq!(to_relpath(&abspath), "src/main.rs");
+        assert_eq!(to_relpath("/not/under/cwd"), "/not/under/cwd");
+    }
+}
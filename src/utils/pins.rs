@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::utils::path::to_abspath;
+
+/// Prefix of pinned items in fd / mru / visits. `icons::strip` removes it.
+pub const MARK: &str = "★ ";
+
+#[derive(Serialize, Deserialize)]
+struct Pin {
+    path: String, // 絶対パス
+}
+
+/// `{state_dir}/pins.jsonl` (see `config::state_dir`)
+pub fn pins_file() -> String {
+    format!("{}/pins.jsonl", config::state_dir())
+}
+
+/// Pinned paths (absolute), in the order they were pinned.
+pub fn list() -> Result<Vec<String>> {
+    list_from(Path::new(&pins_file()))
+}
+
+/// Pins `path` if it is not pinned, unpins it otherwise. Returns whether it is pinned now.
+pub fn toggle(path: impl AsRef<Path>) -> Result<bool> {
+    toggle_in(Path::new(&pins_file()), path)
+}
+
+fn list_from(file: &Path) -> Result<Vec<String>> {
+    let content = match std::fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Pin>(line).ok())
+        .map(|pin| pin.path)
+        .collect())
+}
+
+// 既に pin 済みなら何もしない
+fn add_to(file: &Path, path: impl AsRef<Path>) -> Result<()> {
+    let path = to_abspath(path);
+    if list_from(file)?.contains(&path) {
+        return Ok(());
+    }
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)?;
+    writeln!(file, "{}", serde_json::to_string(&Pin { path })?)?;
+    Ok(())
+}
+
+// pin されていなければ何もしない
+fn remove_from(file: &Path, path: impl AsRef<Path>) -> Result<()> {
+    let path = to_abspath(path);
+    let pins = list_from(file)?;
+    if !pins.contains(&path) {
+        return Ok(());
+    }
+    let mut content = String::new();
+    for p in pins.into_iter().filter(|p| *p != path) {
+        content.push_str(&serde_json::to_string(&Pin { path: p })?);
+        content.push('\n');
+    }
+    std::fs::write(file, content)?;
+    Ok(())
+}
+
+fn toggle_in(file: &Path, path: impl AsRef<Path>) -> Result<bool> {
+    let abspath = to_abspath(&path);
+    if list_from(file)?.contains(&abspath) {
+        remove_from(file, path)?;
+        Ok(false)
+    } else {
+        add_to(file, path)?;
+        Ok(true)
+    }
+}
+
+/// Snapshot of the pins for marking items in a load.
+/// Both the pins and the items are compared by `to_abspath`, so a path through a symlink
+/// matches the pin of its target.
+#[derive(Default)]
+pub struct Pinned {
+    paths: HashSet<String>,
+}
+
+impl Pinned {
+    pub fn load() -> Self {
+        match list() {
+            Ok(paths) => Self::new(paths),
+            Err(e) => {
+                error!("pins: load failed"; "error" => e.to_string());
+                Self::default()
+            }
+        }
+    }
+
+    fn new(paths: Vec<String>) -> Self {
+        // 保存後に symlink が張り替えられていることもあるので読み込み時にも解決し直す
+        Self {
+            paths: paths.into_iter().map(to_abspath).collect(),
+        }
+    }
+
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        // pin が無ければ fd の大量の item を canonicalize しない
+        !self.paths.is_empty() && self.paths.contains(&to_abspath(path))
+    }
+
+    /// Prefixes `item` with `MARK` if `path` is pinned
+    pub fn decorate(&self, item: String, path: impl AsRef<Path>) -> String {
+        if self.contains(path) {
+            format!("{MARK}{item}")
+        } else {
+            item
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggle_twice_restores_pins() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("fzfw/pins.jsonl");
+        let target = dir.path().join("a.txt");
+        std::fs::write(&target, "").unwrap();
+
+        assert!(toggle_in(&file, &target).unwrap());
+        assert_eq!(list_from(&file).unwrap(), vec![to_abspath(&target)]);
+        // 既に pin 済みなら add しても重複しない
+        add_to(&file, &target).unwrap();
+        assert_eq!(list_from(&file).unwrap().len(), 1);
+
+        assert!(!toggle_in(&file, &target).unwrap());
+        assert!(list_from(&file).unwrap().is_empty());
+        remove_from(&file, &target).unwrap();
+        assert!(list_from(&file).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pinned_matches_path_through_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        std::fs::create_dir(&real).unwrap();
+        std::fs::write(real.join("a.txt"), "").unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        let pinned = Pinned::new(vec![to_abspath(link.join("a.txt"))]);
+        assert!(pinned.contains(real.join("a.txt")));
+        assert!(pinned.contains(link.join("a.txt")));
+        assert!(!pinned.contains(real.join("b.txt")));
+        assert_eq!(
+            pinned.decorate("a.txt".to_string(), link.join("a.txt")),
+            format!("{MARK}a.txt")
+        );
+    }
+}
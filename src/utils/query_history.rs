@@ -0,0 +1,113 @@
+//! livegrep のクエリ履歴
+
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::config;
+
+// これを超えたら古いものから捨てる
+const MAX_QUERIES: usize = 500;
+
+/// `{state_dir}/livegrep_history` (see `config::state_dir`)
+pub fn history_file() -> String {
+    format!("{}/livegrep_history", config::state_dir())
+}
+
+/// Past queries, newest first.
+pub fn list() -> Result<Vec<String>> {
+    let mut queries = read_from(Path::new(&history_file()))?;
+    queries.reverse();
+    Ok(queries)
+}
+
+/// Records `query` as the newest entry. Empty queries are ignored.
+pub fn record(query: &str) -> Result<()> {
+    record_to(Path::new(&history_file()), query)
+}
+
+fn record_to(path: &Path, query: &str) -> Result<()> {
+    let query = query.trim();
+    if query.is_empty() || query.contains('\n') {
+        return Ok(());
+    }
+    let queries = push(read_from(path)?, query);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut content = queries.join("\n");
+    content.push('\n');
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+// 古い順 (1 行 1 クエリ)
+fn read_from(path: &Path) -> Result<Vec<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn push(mut queries: Vec<String>, query: &str) -> Vec<String> {
+    // 直前のクエリを延長しただけなら置き換える。短くした方は別のクエリとして残す
+    if let Some(last) = queries.last() {
+        if query.starts_with(last.as_str()) {
+            queries.pop();
+        }
+    }
+    queries.retain(|q| q != query);
+    queries.push(query.to_string());
+    if queries.len() > MAX_QUERIES {
+        queries.drain(..queries.len() - MAX_QUERIES);
+    }
+    queries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(qs: &[&str]) -> Vec<String> {
+        qs.iter().map(|q| q.to_string()).collect()
+    }
+
+    #[test]
+    fn push_replaces_extended_query() {
+        assert_eq!(
+            push(strings(&["foo", "ba"]), "bar"),
+            strings(&["foo", "bar"])
+        );
+    }
+
+    #[test]
+    fn push_keeps_shortened_query() {
+        assert_eq!(
+            push(strings(&["foo", "bar"]), "ba"),
+            strings(&["foo", "bar", "ba"])
+        );
+    }
+
+    #[test]
+    fn push_moves_duplicate_to_newest() {
+        assert_eq!(
+            push(strings(&["foo", "bar"]), "foo"),
+            strings(&["bar", "foo"])
+        );
+    }
+
+    #[test]
+    fn record_saves_newest_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fzfw/livegrep_history");
+        for query in ["foo", " bar ", "", "foo"] {
+            record_to(&path, query).unwrap();
+        }
+        assert_eq!(read_from(&path).unwrap(), strings(&["bar", "foo"]));
+    }
+}
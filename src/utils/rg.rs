@@ -2,21 +2,47 @@ use tokio::process::Command;
 
 pub fn new() -> Command {
     let mut rg = Command::new("rg");
-    rg.arg("--column");
-    rg.arg("--line-number");
-    rg.arg("--no-heading");
-    rg.arg("--hidden");
-    rg.arg("--color=never");
-    rg.arg("--smart-case");
-    rg.arg("--color=ansi");
-
-    let extra_opts = std::env::var("FZFW_RG_EXTRA_OPTS");
-    if let Ok(extra_opts) = extra_opts {
-        // XXX オプションに,が含まれていると困る。が、多分ないはず
-        for extra_opt in extra_opts.split(',') {
-            rg.args(vec![extra_opt]);
-        }
-    }
+    let extra_opts = std::env::var("FZFW_RG_EXTRA_OPTS").unwrap_or_default();
+    rg.args(args(&extra_opts));
     rg.kill_on_drop(true);
     rg
 }
+
+fn args(extra_opts: &str) -> Vec<&str> {
+    // 既定値。FZFW_RG_EXTRA_OPTS (--no-hidden, --case-sensitive など) で上書きできるように前に置く
+    let mut args = vec!["--hidden", "--smart-case", "--color=ansi"];
+
+    // XXX オプションに,が含まれていると困る。が、多分ないはず
+    args.extend(extra_opts.split(',').filter(|opt| !opt.is_empty()));
+
+    // 出力形式 (file:line:col:text) は parse 側が依存しているので、
+    // extra_opts や rg の設定ファイルで上書きされないように後ろに置く
+    args.extend([
+        "--column",
+        "--line-number",
+        "--no-heading",
+        "--with-filename",
+    ]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extra_opts_override_defaults_but_not_format() {
+        let args = args("--no-hidden,--case-sensitive,--color=never,--no-line-number");
+        let pos = |opt| args.iter().rposition(|a| *a == opt).unwrap();
+        assert!(pos("--no-hidden") > pos("--hidden"));
+        assert!(pos("--case-sensitive") > pos("--smart-case"));
+        assert!(pos("--color=never") > pos("--color=ansi"));
+        assert!(pos("--line-number") > pos("--no-line-number"));
+        assert_eq!(args.iter().filter(|a| a.starts_with("--color")).count(), 2);
+    }
+
+    #[test]
+    fn empty_extra_opts_add_nothing() {
+        assert!(!args("").contains(&""));
+    }
+}
@@ -0,0 +1,22 @@
+use std::path::Path;
+
+use anyhow::Result;
+use tokio::process::Command;
+
+/// Moves `file` to the trash with `trash-put` (trash-cli) when available.
+/// Falls back to removing the file if `trash-put` is not installed.
+pub async fn remove(file: impl AsRef<Path>) -> Result<()> {
+    let file = file.as_ref();
+    match Command::new("trash-put").arg("--").arg(file).output().await {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(anyhow::anyhow!(
+            "trash-put failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tokio::fs::remove_file(file).await?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
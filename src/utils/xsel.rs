@@ -1,7 +1,10 @@
+use anyhow::anyhow;
 use anyhow::Result;
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 
+use crate::utils::command;
+
 pub async fn yank(str: impl AsRef<str>) -> Result<()> {
     let mut glow = Command::new("xsel")
         .args(vec!["-b"])
@@ -12,3 +15,20 @@ pub async fn yank(str: impl AsRef<str>) -> Result<()> {
     drop(stdin);
     Ok(())
 }
+
+/// Contents of the clipboard (`xsel -b -o`)
+pub async fn clipboard() -> Result<Vec<u8>> {
+    let output = command::output_with_timeout(
+        "clipboard",
+        Command::new("xsel").args(vec!["-b", "-o"]),
+        command::preview_timeout(),
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "xsel: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}